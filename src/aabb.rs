@@ -0,0 +1,109 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+// Flat boxes (around axis-aligned triangles and rectangles) would never
+// register a hit in Aabb::hit, so planar shapes pad their boxes by this much.
+const BOX_PADDING: f32 = 0.0001;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Aabb {
+    minimum: Point3,
+    maximum: Point3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Aabb {
+        Aabb { minimum, maximum }
+    }
+
+    pub fn min(&self) -> Point3 {
+        self.minimum
+    }
+
+    pub fn max(&self) -> Point3 {
+        self.maximum
+    }
+
+    /// The box grown by a small margin on every side.
+    pub fn padded(&self) -> Aabb {
+        let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        Aabb::new(self.minimum - padding, self.maximum + padding)
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let extent = self.maximum - self.minimum;
+        2.0 * (extent.x() * extent.y() + extent.y() * extent.z() + extent.z() * extent.x())
+    }
+
+    pub fn hit(&self, ray: Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction().elements[axis];
+            let mut t0 = (self.minimum.elements[axis] - ray.origin().elements[axis]) * inv_d;
+            let mut t1 = (self.maximum.elements[axis] - ray.origin().elements[axis]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub fn surrounding_box(box0: Aabb, box1: Aabb) -> Aabb {
+    let small = Point3::new(
+        box0.min().x().min(box1.min().x()),
+        box0.min().y().min(box1.min().y()),
+        box0.min().z().min(box1.min().z()),
+    );
+    let big = Point3::new(
+        box0.max().x().max(box1.max().x()),
+        box0.max().y().max(box1.max().y()),
+        box0.max().z().max(box1.max().z()),
+    );
+    Aabb::new(small, big)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_box() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        assert!(aabb.hit(ray, 0.001, f32::INFINITY));
+        assert!(!aabb.hit(ray, 0.001, 3.0));
+    }
+
+    #[test]
+    fn ray_misses_box() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        assert!(!aabb.hit(ray, 0.001, f32::INFINITY));
+    }
+
+    #[test]
+    fn box_union_and_area() {
+        let box0 = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let box1 = Aabb::new(Point3::new(-1.0, 0.5, 0.0), Point3::new(0.5, 2.0, 1.0));
+        let both = surrounding_box(box0, box1);
+
+        assert_eq!(both.min(), Point3::new(-1.0, 0.0, 0.0));
+        assert_eq!(both.max(), Point3::new(1.0, 2.0, 1.0));
+        assert_eq!(box0.surface_area(), 6.0);
+    }
+}
@@ -0,0 +1,75 @@
+use std::ops::BitOr;
+
+use crate::vec3::{Color, Vec3};
+
+/// Flags describing the kinds of scattering a BSDF can do, so the integrator
+/// knows which lobes light sampling can reach.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Lobes(u8);
+
+impl Lobes {
+    pub const NONE: Lobes = Lobes(0);
+    pub const REFLECTION: Lobes = Lobes(1);
+    pub const TRANSMISSION: Lobes = Lobes(1 << 1);
+    pub const DIFFUSE: Lobes = Lobes(1 << 2);
+    pub const GLOSSY: Lobes = Lobes(1 << 3);
+    /// A single outgoing direction (or one with no known density), which
+    /// can only be sampled and never evaluated.
+    pub const SPECULAR: Lobes = Lobes(1 << 4);
+
+    /// Whether every flag in `other` is set.
+    pub fn contains(self, other: Lobes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any flag in `other` is set.
+    pub fn intersects(self, other: Lobes) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Whether `eval` and `pdf` are meaningful for some directions, which is
+    /// what light sampling needs.
+    pub fn has_smooth(self) -> bool {
+        self.intersects(Lobes::DIFFUSE | Lobes::GLOSSY)
+    }
+}
+
+impl BitOr for Lobes {
+    type Output = Lobes;
+
+    fn bitor(self, other: Lobes) -> Lobes {
+        Lobes(self.0 | other.0)
+    }
+}
+
+/// A scattered direction drawn from a BSDF.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct BsdfSample {
+    /// Unit direction the light arrives from, pointing away from the surface
+    pub direction: Vec3,
+    /// The BSDF times the cosine over the pdf: what the path's throughput
+    /// gets multiplied by
+    pub weight: Color,
+    /// Solid-angle density of `direction`; zero for specular lobes
+    pub pdf: f32,
+    /// The single lobe the direction was drawn from
+    pub lobe: Lobes,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_combine() {
+        let glass = Lobes::SPECULAR | Lobes::REFLECTION | Lobes::TRANSMISSION;
+
+        assert!(glass.contains(Lobes::SPECULAR | Lobes::REFLECTION));
+        assert!(!glass.contains(Lobes::SPECULAR | Lobes::DIFFUSE));
+        assert!(glass.intersects(Lobes::DIFFUSE | Lobes::TRANSMISSION));
+        assert!(!glass.has_smooth());
+        assert!((Lobes::DIFFUSE | Lobes::REFLECTION).has_smooth());
+        assert!(!Lobes::NONE.intersects(glass));
+    }
+}
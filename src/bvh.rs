@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+
+use crate::aabb::{Aabb, surrounding_box};
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utility::INFINITY;
+
+enum BvhChildren {
+    Leaf(Box<dyn Hittable>),
+    Branch {
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+    /// Objects that can't go in the tree, tested one by one
+    List(HittableList),
+}
+
+pub struct BvhNode {
+    /// `None` for a list holding something without a bounding box, or
+    /// nothing at all
+    bbox: Option<Aabb>,
+    children: BvhChildren,
+}
+
+impl BvhNode {
+    /// Builds a tree over `objects`. Any without a bounding box, such as an
+    /// infinite plane, are kept in a list beside the tree and tested against
+    /// every ray.
+    pub fn new(objects: Vec<Box<dyn Hittable>>, time0: f32, time1: f32) -> BvhNode {
+        let mut entries = vec![];
+        let mut unbounded: Vec<Box<dyn Hittable>> = vec![];
+        for object in objects {
+            match object.bounding_box(time0, time1) {
+                Some(bbox) => entries.push((bbox, object)),
+                None => unbounded.push(object),
+            }
+        }
+
+        if unbounded.is_empty() && !entries.is_empty() {
+            return BvhNode::build(entries);
+        }
+        if !entries.is_empty() {
+            unbounded.push(Box::new(BvhNode::build(entries)));
+        }
+        let list = HittableList::new(unbounded);
+        BvhNode { bbox: list.bounding_box(time0, time1), children: BvhChildren::List(list) }
+    }
+
+    pub fn from_list(list: HittableList, time0: f32, time1: f32) -> BvhNode {
+        BvhNode::new(list.into_objects(), time0, time1)
+    }
+
+    fn build(mut entries: Vec<(Aabb, Box<dyn Hittable>)>) -> BvhNode {
+        if entries.len() == 1 {
+            let (bbox, object) = entries.pop().unwrap();
+            return BvhNode { bbox: Some(bbox), children: BvhChildren::Leaf(object) };
+        }
+
+        let (axis, split) = sah_split(&mut entries);
+        sort_by_axis(&mut entries, axis);
+        let right_entries = entries.split_off(split);
+
+        let left = BvhNode::build(entries);
+        let right = BvhNode::build(right_entries);
+
+        BvhNode {
+            bbox: left.bbox.zip(right.bbox).map(|(left, right)| surrounding_box(left, right)),
+            children: BvhChildren::Branch {
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+        }
+    }
+}
+
+fn sort_by_axis(entries: &mut [(Aabb, Box<dyn Hittable>)], axis: usize) {
+    entries.sort_by(|a, b| {
+        let a_center = a.0.centroid().elements[axis];
+        let b_center = b.0.centroid().elements[axis];
+        a_center.partial_cmp(&b_center).unwrap_or(Ordering::Equal)
+    });
+}
+
+// Surface area heuristic: try every split along every axis (objects sorted by
+// centroid) and keep the one where area * object count summed over both
+// halves is smallest. Returns the axis and the index the right half starts at.
+fn sah_split(entries: &mut [(Aabb, Box<dyn Hittable>)]) -> (usize, usize) {
+    let n = entries.len();
+    let mut best_axis = 0;
+    let mut best_split = n / 2;
+    let mut best_cost = INFINITY;
+
+    for axis in 0..3 {
+        sort_by_axis(entries, axis);
+
+        let mut right_areas = vec![0.0; n];
+        let mut right_box = entries[n - 1].0;
+        for i in (1..n).rev() {
+            right_box = surrounding_box(right_box, entries[i].0);
+            right_areas[i] = right_box.surface_area();
+        }
+
+        let mut left_box = entries[0].0;
+        for (i, right_area) in right_areas.iter().enumerate().skip(1) {
+            let cost = left_box.surface_area() * i as f32 + right_area * (n - i) as f32;
+            if cost < best_cost {
+                best_cost = cost;
+                best_axis = axis;
+                best_split = i;
+            }
+            left_box = surrounding_box(left_box, entries[i].0);
+        }
+    }
+
+    (best_axis, best_split)
+}
+
+impl BvhNode {
+    /// The closest of the hits that `hit` finds on the leaves whose boxes
+    /// the ray passes through.
+    fn closest<'a>(
+        &'a self, ray: Ray, t_min: f32, t_max: f32,
+        hit: &mut dyn FnMut(&'a dyn Hittable, f32) -> Option<HitRecord<'a>>,
+    ) -> Option<HitRecord<'a>> {
+        if self.bbox.is_some_and(|bbox| !bbox.hit(ray, t_min, t_max)) {
+            return None;
+        }
+
+        match &self.children {
+            BvhChildren::Leaf(object) => hit(object.as_ref(), t_max),
+            BvhChildren::Branch { left, right } => {
+                let hit_left = left.closest(ray, t_min, t_max, hit);
+                let closest_so_far = hit_left.as_ref().map_or(t_max, |hit| hit.t);
+                let hit_right = right.closest(ray, t_min, closest_so_far, hit);
+                hit_right.or(hit_left)
+            }
+            BvhChildren::List(list) => list.closest(t_max, hit),
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.closest(ray, t_min, t_max, &mut |object, t_max| object.hit(ray, t_min, t_max))
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.closest(ray, t_min, t_max, &mut |object, t_max| object.sample_hit(ray, t_min, t_max, sampler))
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sphere::{MovingSphere, Sphere};
+    use crate::vec3::{Color, Point3, Vec3};
+
+    fn grid_scene() -> Vec<Box<dyn Hittable>> {
+        let material = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+        let mut objects: Vec<Box<dyn Hittable>> = vec![];
+
+        objects.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, material.clone())));
+
+        for i in -11..11 {
+            for b in -11..11 {
+                let center = Point3::new(i as f32 + 0.3, 0.2, b as f32 + 0.6);
+                if (i + b) % 3 == 0 {
+                    let center2 = center + Vec3::new(0.0, 0.25, 0.0);
+                    objects.push(Box::new(MovingSphere::new(center, center2, 0.0, 1.0, 0.2, material.clone())));
+                } else {
+                    objects.push(Box::new(Sphere::new(center, 0.2, material.clone())));
+                }
+            }
+        }
+        objects.push(Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material.clone())));
+        objects.push(Box::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material.clone())));
+        objects.push(Box::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material.clone())));
+
+        objects
+    }
+
+    // Shades each pixel by its hit normal and distance so that any difference
+    // in the closest hit between the two structures shows up in the image.
+    fn render(world: &dyn Hittable, width: usize, height: usize) -> Vec<Color> {
+        let origin = Point3::new(13.0, 2.0, 3.0);
+        let mut pixels = vec![];
+
+        for j in 0..height {
+            for i in 0..width {
+                let target = Point3::new(
+                    -8.0 + 16.0 * i as f32 / width as f32,
+                    4.0 - 5.0 * j as f32 / height as f32,
+                    -6.0 + 12.0 * ((i + j) % 7) as f32 / 7.0,
+                );
+                let time = ((i * 31 + j * 17) % 10) as f32 / 10.0;
+                let ray = Ray::new(origin, target - origin, time);
+
+                let color = match world.hit(ray, 0.001, INFINITY) {
+                    Some(hit) => 0.5 * (hit.normal + Color::new(1.0, 1.0, 1.0)) / (1.0 + hit.t),
+                    None => Color::new(0.0, 0.0, 0.0),
+                };
+                pixels.push(color);
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn bvh_renders_same_image_as_list() {
+        let list = HittableList::new(grid_scene());
+        let bvh = BvhNode::new(grid_scene(), 0.0, 1.0);
+
+        assert_eq!(render(&list, 160, 90), render(&bvh, 160, 90));
+    }
+
+    #[test]
+    fn bvh_bounds_match_list() {
+        let list = HittableList::new(grid_scene());
+        let bvh = BvhNode::from_list(HittableList::new(grid_scene()), 0.0, 1.0);
+
+        assert_eq!(list.bounding_box(0.0, 1.0), bvh.bounding_box(0.0, 1.0));
+    }
+
+    #[test]
+    fn single_object_bvh() {
+        let material = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+        let objects: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material))
+        ];
+        let bvh = BvhNode::new(objects, 0.0, 1.0);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let hit = bvh.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 0.5);
+    }
+
+    #[test]
+    fn unbounded_objects_stay_beside_the_tree() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let empty = BvhNode::new(vec![], 0.0, 1.0);
+        assert!(empty.hit(ray, 0.001, INFINITY).is_none());
+        assert!(empty.bounding_box(0.0, 1.0).is_none());
+
+        // A list holding an empty list has no bounding box, as an infinite
+        // plane wouldn't
+        let scene = || {
+            let material = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+            let mut objects = grid_scene();
+            objects.push(Box::new(HittableList::new(vec![
+                Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material)),
+                Box::new(HittableList::new(vec![])),
+            ])));
+            objects
+        };
+        let bvh = BvhNode::new(scene(), 0.0, 1.0);
+        assert!(bvh.bounding_box(0.0, 1.0).is_none());
+        assert_eq!(bvh.hit(ray, 0.001, INFINITY).unwrap().t, 0.5);
+        assert_eq!(render(&HittableList::new(scene()), 160, 90), render(&bvh, 160, 90));
+    }
+}
@@ -1,9 +1,34 @@
 use crate::ray::{Ray};
+use crate::sampler::Sampler;
 use crate::utility::{
-    degrees_to_radians, cross, unit_vector, random_double_range,
+    degrees_to_radians, cross, unit_vector,
 };
 use crate::vec3::{Point3, Vec3, random_in_unit_disk};
 
+/// Everything `Camera::new` needs apart from the aspect ratio, which comes
+/// from the render settings.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct CameraSettings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub time0: f32,
+    pub time1: f32,
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: f32) -> Camera {
+        Camera::new(
+            self.look_from, self.look_at, self.vup,
+            self.vfov, aspect_ratio, self.aperture, self.focus_dist,
+            self.time0, self.time1,
+        )
+    }
+}
+
 pub struct Camera {
     origin: Point3,
     horizontal: Vec3,
@@ -17,6 +42,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Point3, look_at: Point3, vup: Vec3,
         vfov: f32, aspect_ratio: f32, aperture: f32,
@@ -38,27 +64,27 @@ impl Camera {
         let lens_radius = aperture / 2.0;
 
         Camera {
-            origin: origin,
-            horizontal: horizontal,
-            vertical: vertical,
-            lower_left_corner: lower_left_corner,
-            u: u,
-            v: v,
-            lens_radius: lens_radius,
+            origin,
+            horizontal,
+            vertical,
+            lower_left_corner,
+            u,
+            v,
+            lens_radius,
             time0: _time0,
             time1: _time1,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd: Vec3 = self.lens_radius * random_in_unit_disk();
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rd: Vec3 = self.lens_radius * random_in_unit_disk(sampler);
         let offset: Vec3 = self.u * rd.x() + self.v * rd.y();
 
         Ray::new(
            self.origin + offset,
            self.lower_left_corner + s * self.horizontal + 
            t * self.vertical - self.origin - offset,
-           random_double_range(self.time0, self.time1),
+           sampler.range(self.time0, self.time1),
         )
     }
 }
@@ -0,0 +1,394 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use river::{ImageFormat, Integrator, RenderSettings, SamplerKind, Vec3};
+
+#[derive(Parser)]
+#[command(name = "river", about = "Toy raytracer via Ray Tracing in One Weekend")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render a single image
+    Render {
+        #[command(flatten)]
+        options: RenderOptions,
+    },
+
+    /// Render a sequence of frames, moving the camera between them
+    Animate {
+        #[command(flatten)]
+        options: RenderOptions,
+
+        /// Number of frames to render
+        #[arg(long, default_value_t = 10)]
+        frames: usize,
+
+        /// Camera movement per frame as x,y,z
+        #[arg(long, value_parser = parse_vec3, default_value = "0.05,0,0.1", allow_hyphen_values = true)]
+        step: Vec3,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// Binary PPM (P6)
+    Ppm,
+    /// Plain-text PPM (P3)
+    PpmAscii,
+    Png,
+    /// Radiance RGBE, unclamped linear radiance
+    Hdr,
+    /// OpenEXR with half-float channels, unclamped linear radiance
+    Exr,
+    /// OpenEXR with 32-bit float channels, unclamped linear radiance
+    ExrFloat,
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> ImageFormat {
+        match format {
+            OutputFormat::Ppm => ImageFormat::Ppm,
+            OutputFormat::PpmAscii => ImageFormat::PpmAscii,
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Hdr => ImageFormat::Hdr,
+            OutputFormat::Exr => ImageFormat::Exr,
+            OutputFormat::ExrFloat => ImageFormat::ExrFloat,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum IntegratorOption {
+    /// Follow scattered rays only
+    Naive,
+    /// Sample lights directly at each diffuse bounce
+    NextEvent,
+}
+
+impl From<IntegratorOption> for Integrator {
+    fn from(integrator: IntegratorOption) -> Integrator {
+        match integrator {
+            IntegratorOption::Naive => Integrator::Naive,
+            IntegratorOption::NextEvent => Integrator::NextEvent,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum SamplerOption {
+    /// Independent random numbers
+    Independent,
+    /// Jittered strata in every dimension
+    Stratified,
+    /// Scrambled Halton sequence
+    Halton,
+    /// Owen-scrambled Sobol sequence
+    Sobol,
+    /// Sobol points dithered with blue noise across pixels
+    BlueNoise,
+}
+
+impl From<SamplerOption> for SamplerKind {
+    fn from(sampler: SamplerOption) -> SamplerKind {
+        match sampler {
+            SamplerOption::Independent => SamplerKind::Independent,
+            SamplerOption::Stratified => SamplerKind::Stratified,
+            SamplerOption::Halton => SamplerKind::Halton,
+            SamplerOption::Sobol => SamplerKind::Sobol,
+            SamplerOption::BlueNoise => SamplerKind::BlueNoise,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct RenderOptions {
+    /// Scene file (.toml) or the name of a built-in scene ("random")
+    pub scene: String,
+
+    /// Image width in pixels
+    #[arg(long)]
+    pub width: Option<usize>,
+
+    /// Image height in pixels; derived from the aspect ratio when omitted
+    #[arg(long)]
+    pub height: Option<usize>,
+
+    /// Samples per pixel
+    #[arg(long, short = 's')]
+    pub samples: Option<usize>,
+
+    /// Maximum number of bounces per path
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// How each pixel's radiance is estimated
+    #[arg(long, value_enum)]
+    pub integrator: Option<IntegratorOption>,
+
+    /// How random numbers are spread over each pixel's samples
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerOption>,
+
+    /// Stop sampling a pixel once the standard error of its mean, relative to
+    /// the mean, falls to this; --samples becomes the most any pixel takes
+    #[arg(long)]
+    pub adaptive_threshold: Option<f32>,
+
+    /// Samples every pixel takes in each pass over the image
+    #[arg(long)]
+    pub min_samples: Option<usize>,
+
+    /// Stop starting new passes after this many seconds
+    #[arg(long, value_parser = parse_seconds)]
+    pub time_limit: Option<Duration>,
+
+    /// Write the image so far to the output file every this many seconds
+    #[arg(long, value_parser = parse_seconds)]
+    pub snapshot_interval: Option<Duration>,
+
+    /// Trace one wavelength per sample instead of RGB, so glass disperses;
+    /// RGB colors are upsampled to smooth spectra (Smits 1999)
+    #[arg(long)]
+    pub spectral: bool,
+
+    /// Output file; animations insert the frame number before the extension
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    /// Also write an image of how many samples each pixel took
+    #[arg(long)]
+    pub heatmap: Option<PathBuf>,
+
+    /// Output image format; guessed from the output extension when omitted
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Number of render threads (defaults to one per core)
+    #[arg(long, short = 'j')]
+    pub threads: Option<usize>,
+
+    /// Seed for the random numbers; a seed always gives the same image
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl RenderOptions {
+    /// Applies the command-line overrides on top of the scene's own settings.
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        if let Some(width) = self.width {
+            settings.image_width = width;
+        }
+        if let Some(height) = self.height {
+            settings.aspect_ratio = settings.image_width as f32 / height as f32;
+        }
+        if let Some(samples) = self.samples {
+            settings.samples_per_pixel = samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        if let Some(integrator) = self.integrator {
+            settings.integrator = integrator.into();
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler.into();
+        }
+        if let Some(threshold) = self.adaptive_threshold {
+            settings.adaptive_threshold = Some(threshold);
+        }
+        if let Some(min_samples) = self.min_samples {
+            settings.min_samples_per_pixel = min_samples;
+        }
+        if let Some(time_limit) = self.time_limit {
+            settings.time_limit = Some(time_limit);
+        }
+        if self.spectral {
+            settings.spectral = true;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+    }
+
+    pub fn output_format(&self) -> ImageFormat {
+        if let Some(format) = self.format {
+            return format.into();
+        }
+        self.output
+            .as_deref()
+            .and_then(ImageFormat::from_path)
+            .unwrap_or(ImageFormat::Ppm)
+    }
+
+    pub fn output_path(&self, frame: Option<usize>) -> PathBuf {
+        let path = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("output.{}", self.output_format().extension())));
+
+        match frame {
+            Some(frame) => frame_path(&path, frame),
+            None => path,
+        }
+    }
+
+    /// Where the sample-count heatmap goes, if one was asked for, and in
+    /// which format; the format follows its extension, then the image's.
+    pub fn heatmap_output(&self, frame: Option<usize>) -> Option<(PathBuf, ImageFormat)> {
+        let path = self.heatmap.as_deref()?;
+        let format = ImageFormat::from_path(path).unwrap_or_else(|| self.output_format());
+        match frame {
+            Some(frame) => Some((frame_path(path, frame), format)),
+            None => Some((path.to_path_buf(), format)),
+        }
+    }
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let components: Vec<f32> = value
+        .split(',')
+        .map(|component| component.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|error| error.to_string())?;
+
+    match components.as_slice() {
+        [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err("expected three comma-separated numbers".to_string()),
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f32 = value.trim().parse().map_err(|error: std::num::ParseFloatError| error.to_string())?;
+    if seconds <= 0.0 {
+        return Err("expected a positive number of seconds".to_string());
+    }
+    Duration::try_from_secs_f32(seconds).map_err(|error| error.to_string())
+}
+
+/// `renders/out.ppm` becomes `renders/out-3.ppm` for frame 3.
+fn frame_path(path: &Path, frame: usize) -> PathBuf {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("output");
+    let file_name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}-{}.{}", stem, frame, extension),
+        None => format!("{}-{}", stem, frame),
+    };
+    path.with_file_name(file_name)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_numbers_go_before_the_extension() {
+        assert_eq!(frame_path(Path::new("renders/out.ppm"), 3), PathBuf::from("renders/out-3.ppm"));
+        assert_eq!(frame_path(Path::new("out"), 12), PathBuf::from("out-12"));
+    }
+
+    #[test]
+    fn parses_animate() {
+        let cli = Cli::try_parse_from([
+            "river", "animate", "random", "--frames", "4", "--width", "200",
+            "--height", "100", "--step", "0.1,-0.2,0", "--seed", "7", "--sampler", "blue-noise",
+        ]).unwrap();
+
+        match cli.command {
+            Command::Animate { options, frames, step } => {
+                assert_eq!(frames, 4);
+                assert_eq!(step, Vec3::new(0.1, -0.2, 0.0));
+                assert_eq!(options.seed, Some(7));
+
+                let mut settings = RenderSettings::default();
+                options.apply(&mut settings);
+                assert_eq!(settings.image_width, 200);
+                assert_eq!(settings.image_height(), 100);
+                assert_eq!(settings.seed, 7);
+                assert_eq!(settings.sampler, SamplerKind::BlueNoise);
+                assert_eq!(options.output_path(Some(2)), PathBuf::from("output-2.ppm"));
+            }
+            _ => panic!("expected the animate subcommand"),
+        }
+    }
+
+    #[test]
+    fn format_follows_flag_then_extension() {
+        let parse = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Command::Render { options } => options,
+            _ => panic!("expected the render subcommand"),
+        };
+
+        let options = parse(&["river", "render", "random", "-o", "out.png"]);
+        assert_eq!(options.output_format(), ImageFormat::Png);
+
+        let options = parse(&["river", "render", "random", "-o", "out.png", "--format", "ppm-ascii"]);
+        assert_eq!(options.output_format(), ImageFormat::PpmAscii);
+
+        let options = parse(&["river", "render", "random", "--format", "png"]);
+        assert_eq!(options.output_path(None), PathBuf::from("output.png"));
+
+        let options = parse(&["river", "render", "random"]);
+        assert_eq!(options.output_format(), ImageFormat::Ppm);
+    }
+
+    #[test]
+    fn parses_adaptive_sampling() {
+        let cli = Cli::try_parse_from([
+            "river", "animate", "random", "--adaptive-threshold", "0.02", "--min-samples", "8",
+            "--heatmap", "samples.png", "-o", "out.exr",
+        ]).unwrap();
+
+        match cli.command {
+            Command::Animate { options, .. } => {
+                let mut settings = RenderSettings::default();
+                options.apply(&mut settings);
+                assert_eq!(settings.adaptive_threshold, Some(0.02));
+                assert_eq!(settings.min_samples_per_pixel, 8);
+                assert_eq!(options.heatmap_output(Some(3)), Some((PathBuf::from("samples-3.png"), ImageFormat::Png)));
+            }
+            _ => panic!("expected the animate subcommand"),
+        }
+
+        let cli = Cli::try_parse_from(["river", "render", "random", "--heatmap", "samples", "-o", "out.exr"]).unwrap();
+        match cli.command {
+            Command::Render { options } => {
+                assert_eq!(options.heatmap_output(None), Some((PathBuf::from("samples"), ImageFormat::Exr)));
+            }
+            _ => panic!("expected the render subcommand"),
+        }
+    }
+
+    #[test]
+    fn parses_time_budgets() {
+        let cli = Cli::try_parse_from([
+            "river", "render", "random", "--time-limit", "90", "--snapshot-interval", "2.5",
+        ]).unwrap();
+
+        match cli.command {
+            Command::Render { options } => {
+                assert_eq!(options.snapshot_interval, Some(Duration::from_millis(2500)));
+                let mut settings = RenderSettings::default();
+                options.apply(&mut settings);
+                assert_eq!(settings.time_limit, Some(Duration::from_secs(90)));
+            }
+            _ => panic!("expected the render subcommand"),
+        }
+
+        assert!(Cli::try_parse_from(["river", "render", "random", "--time-limit", "0"]).is_err());
+        assert!(Cli::try_parse_from(["river", "render", "random", "--snapshot-interval", "soon"]).is_err());
+        // Too long for a Duration, or not a number at all
+        assert!(Cli::try_parse_from(["river", "render", "random", "--time-limit", "1e30"]).is_err());
+        assert!(Cli::try_parse_from(["river", "render", "random", "--time-limit", "NaN"]).is_err());
+    }
+
+    #[test]
+    fn render_needs_a_scene() {
+        assert!(Cli::try_parse_from(["river", "render"]).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::spectrum::xyz_to_srgb;
+use crate::utility::{PI, dot, unit_vector};
+use crate::vec3::{Color, Vec3};
+
+/// Brings sky luminance, in kcd/m², down to the range the rest of the
+/// renderer works in, so a daylight scene needs no special exposure.
+const LUMINANCE_SCALE: f32 = 0.05;
+/// The sun's illuminance above the atmosphere, in klx.
+const SOLAR_ILLUMINANCE: f32 = 128.0;
+
+/// Where the sun is and how hazy the air is; `build` turns it into a
+/// `Daylight` environment.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DaylightSettings {
+    /// Degrees above the horizon, from 0 to 90
+    pub elevation: f32,
+    /// Degrees clockwise seen from above, starting from -z; 90 puts the sun
+    /// toward +x
+    pub azimuth: f32,
+    /// From 2 for a very clear sky to 10 for a hazy one
+    pub turbidity: f32,
+    /// Angular radius of the sun's disk in degrees. Larger suns cast softer
+    /// shadows.
+    pub sun_radius: f32,
+    /// Scales both the sky and the sun
+    pub intensity: f32,
+}
+
+impl Default for DaylightSettings {
+    fn default() -> Self {
+        DaylightSettings {
+            elevation: 45.0,
+            azimuth: 0.0,
+            turbidity: 3.0,
+            sun_radius: 0.265,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl DaylightSettings {
+    pub fn build(&self) -> Daylight {
+        Daylight::new(self)
+    }
+}
+
+/// One channel of the Perez et al. sky luminance distribution.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    /// The distribution relative to the sky's brightness at angle `theta`
+    /// from the zenith and `gamma` from the sun.
+    fn f(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta.max(0.01)).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// The Preetham, Shirley and Smits (1999) analytic daylight sky, with the
+/// sun as a small bright disk in it. The disk can be sampled, so next-event
+/// estimation finds the sun and its shadows come out soft.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Daylight {
+    sun: Vec3,
+    /// Cosine of the sun's angular radius
+    sun_cos_radius: f32,
+    /// Solid angle the sun covers
+    sun_solid_angle: f32,
+    sun_radiance: Color,
+    /// Luminance (Y) and chromaticity (x, y) at the zenith
+    zenith: [f32; 3],
+    perez: [Perez; 3],
+    /// Each channel's distribution at the zenith, which the zenith values
+    /// are relative to
+    zenith_f: [f32; 3],
+    intensity: f32,
+}
+
+impl Daylight {
+    pub fn new(settings: &DaylightSettings) -> Daylight {
+        let elevation = settings.elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = settings.azimuth.to_radians();
+        let t = settings.turbidity;
+        let sun = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        let theta_s = PI / 2.0 - elevation;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let chromaticity = |m: [[f32; 4]; 3]| {
+            let angles = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let row = |r: [f32; 4]| (0..4).map(|i| r[i] * angles[i]).sum::<f32>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let perez = [
+            Perez {
+                a: 0.1787 * t - 1.4630,
+                b: -0.3554 * t + 0.4275,
+                c: -0.0227 * t + 5.3251,
+                d: 0.1206 * t - 2.5771,
+                e: -0.0670 * t + 0.3703,
+            },
+            Perez {
+                a: -0.0193 * t - 0.2592,
+                b: -0.0665 * t + 0.0008,
+                c: -0.0004 * t + 0.2125,
+                d: -0.0641 * t - 0.8989,
+                e: -0.0033 * t + 0.0452,
+            },
+            Perez {
+                a: -0.0167 * t - 0.2608,
+                b: -0.0950 * t + 0.0092,
+                c: -0.0079 * t + 0.2102,
+                d: -0.0441 * t - 1.6537,
+                e: -0.0109 * t + 0.0529,
+            },
+        ];
+        let zenith_f = [
+            perez[0].f(1.0, theta_s),
+            perez[1].f(1.0, theta_s),
+            perez[2].f(1.0, theta_s),
+        ];
+
+        // 1 - cos(r), written so it stays accurate for tiny suns
+        let radius = settings.sun_radius.to_radians();
+        let one_minus_cos = 2.0 * (radius / 2.0).sin().powi(2);
+        let sun_solid_angle = 2.0 * PI * one_minus_cos;
+        let sun_radiance = LUMINANCE_SCALE * SOLAR_ILLUMINANCE / sun_solid_angle
+            * sun_transmittance(theta_s, t);
+
+        Daylight {
+            sun,
+            sun_cos_radius: 1.0 - one_minus_cos,
+            sun_solid_angle,
+            sun_radiance,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            perez,
+            zenith_f,
+            intensity: settings.intensity,
+        }
+    }
+
+    /// Unit direction toward the center of the sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun
+    }
+
+    /// The sky alone, without the sun's disk. Directions below the horizon
+    /// see the sky at the horizon.
+    pub fn sky_radiance(&self, direction: Vec3) -> Color {
+        let direction = if direction.y() < 0.0 && (direction.x() != 0.0 || direction.z() != 0.0) {
+            unit_vector(Vec3::new(direction.x(), 0.0, direction.z()))
+        } else {
+            direction
+        };
+        let cos_theta = direction.y().max(0.0);
+        let gamma = dot(direction, self.sun).clamp(-1.0, 1.0).acos();
+        let channel = |i: usize| self.zenith[i] * self.perez[i].f(cos_theta, gamma) / self.zenith_f[i];
+        let (luminance, x, y) = (channel(0), channel(1), channel(2));
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_srgb(xyz);
+        // Keep the gamut clipping of saturated horizons from going negative
+        let rgb = Color::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0));
+        self.intensity * LUMINANCE_SCALE * rgb
+    }
+
+    /// Radiance arriving from the unit `direction`, sun included.
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let sky = self.sky_radiance(direction);
+        if dot(direction, self.sun) >= self.sun_cos_radius {
+            sky + self.intensity * self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    /// Density of `random` over solid angle: uniform across the sun.
+    pub fn pdf_value(&self, direction: Vec3) -> f32 {
+        if dot(unit_vector(direction), self.sun) >= self.sun_cos_radius {
+            1.0 / self.sun_solid_angle
+        } else {
+            0.0
+        }
+    }
+
+    /// A unit direction toward a uniformly chosen point on the sun's disk.
+    pub fn random(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u1, u2) = sampler.next_2d();
+        let cos_theta = 1.0 - u1 * (1.0 - self.sun_cos_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let direction = Onb::from_w(self.sun).local(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta));
+        unit_vector(direction)
+    }
+}
+
+/// Fraction of sunlight that makes it through the atmosphere at zenith
+/// angle `theta_s`, from Rayleigh scattering and Ångström's aerosol model,
+/// taken at a red, green and blue wavelength.
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Color {
+    // Kasten's relative optical air mass
+    let theta_degrees = theta_s.to_degrees().min(93.0);
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = (0.04608 * turbidity - 0.04586).max(0.0);
+
+    let channel = |micrometres: f32| {
+        let rayleigh = 0.008735 * micrometres.powf(-4.08);
+        let aerosol = beta * micrometres.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+    Color::new(channel(0.65), channel(0.57), channel(0.475))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::{luminance, random_unit_vector};
+
+    fn daylight(elevation: f32, turbidity: f32) -> Daylight {
+        DaylightSettings { elevation, turbidity, ..DaylightSettings::default() }.build()
+    }
+
+    #[test]
+    fn the_sun_is_where_it_was_put() {
+        let sky = DaylightSettings { elevation: 30.0, azimuth: 90.0, ..DaylightSettings::default() }.build();
+        let expected = Vec3::new(30f32.to_radians().cos(), 0.5, 0.0);
+        assert!((sky.sun_direction() - expected).length() < 1e-5);
+
+        // The disk is far brighter than the sky beside it
+        let beside = unit_vector(expected + Vec3::new(0.0, 0.05, 0.0));
+        assert!(luminance(sky.radiance(expected)) > 1000.0 * luminance(sky.radiance(beside)));
+        assert_eq!(sky.radiance(beside), sky.sky_radiance(beside));
+    }
+
+    #[test]
+    fn sky_brightens_toward_the_sun_and_is_blue_overhead() {
+        let sky = daylight(30.0, 3.0);
+        let toward_sun = unit_vector(sky.sun_direction() + Vec3::new(0.0, 0.2, 0.0));
+        let away = unit_vector(Vec3::new(-sky.sun_direction().x(), toward_sun.y(), -sky.sun_direction().z()));
+        assert!(luminance(sky.sky_radiance(toward_sun)) > luminance(sky.sky_radiance(away)));
+
+        let zenith = sky.sky_radiance(Vec3::new(0.0, 1.0, 0.0));
+        assert!(zenith.b() > zenith.r());
+        // The zenith is at the model's own zenith luminance
+        assert!((luminance(zenith) / LUMINANCE_SCALE - sky.zenith[0]).abs() < 0.05 * sky.zenith[0]);
+
+        // Below the horizon the horizon's color carries on
+        let down = sky.sky_radiance(Vec3::new(0.6, -0.8, 0.0));
+        assert_eq!(down, sky.sky_radiance(Vec3::new(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn low_and_hazy_suns_are_redder_and_dimmer() {
+        let noon = daylight(80.0, 3.0).sun_radiance;
+        let evening = daylight(5.0, 3.0).sun_radiance;
+        let hazy = daylight(80.0, 8.0).sun_radiance;
+
+        assert!(evening.r() / evening.b() > noon.r() / noon.b());
+        assert!(luminance(evening) < luminance(noon));
+        assert!(luminance(hazy) < luminance(noon));
+    }
+
+    #[test]
+    fn sun_samples_stay_on_the_disk() {
+        let sky = DaylightSettings { sun_radius: 20.0, ..DaylightSettings::default() }.build();
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..1000 {
+            let direction = sky.random(&mut sampler);
+            assert!((direction.length() - 1.0).abs() < 1e-5);
+            assert!(sky.pdf_value(direction) > 0.0);
+        }
+
+        // The density integrates to one over the sphere
+        let trials = 400_000;
+        let total: f32 = (0..trials).map(|_| sky.pdf_value(random_unit_vector(&mut sampler))).sum();
+        assert!((total * 4.0 * PI / trials as f32 - 1.0).abs() < 0.05);
+
+        // A tiny sun still has a sensible solid angle
+        let real = daylight(45.0, 3.0);
+        assert!((real.sun_solid_angle / 6.72e-5 - 1.0).abs() < 0.01);
+    }
+}
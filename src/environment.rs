@@ -0,0 +1,369 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::daylight::Daylight;
+use crate::image::Image;
+use crate::input;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utility::{PI, unit_vector};
+use crate::vec3::{Color, Vec3, luminance};
+
+/// What a ray sees when it leaves the scene.
+#[derive(Clone)]
+pub enum Environment {
+    Constant(Color),
+    /// Blends from `bottom` straight down to `top` straight up
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    /// Light from every direction, read from an HDR image; see
+    /// `EnvironmentMap`
+    Map(Arc<EnvironmentMap>),
+    /// An analytic daylight sky with a sun; see `Daylight`
+    Daylight(Daylight),
+}
+
+impl Environment {
+    /// The white-to-blue gradient from "Ray Tracing in One Weekend".
+    pub const SKY: Environment = Environment::Gradient {
+        bottom: Color { elements: [1.0, 1.0, 1.0] },
+        top: Color { elements: [0.5, 0.7, 1.0] },
+    };
+
+    pub fn color(&self, ray: Ray) -> Color {
+        let direction = unit_vector(ray.direction());
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.y() + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Environment::Map(map) => map.radiance(direction),
+            Environment::Daylight(daylight) => daylight.radiance(direction),
+        }
+    }
+
+    /// Solid-angle density with which `random` returns `direction`; zero for
+    /// environments that aren't sampled.
+    pub fn pdf_value(&self, direction: Vec3) -> f32 {
+        match self {
+            Environment::Map(map) => map.pdf_value(unit_vector(direction)),
+            Environment::Daylight(daylight) => daylight.pdf_value(direction),
+            _ => 0.0,
+        }
+    }
+
+    /// A unit direction toward the bright parts of the environment, or
+    /// `None` when it isn't worth sampling directly.
+    pub fn random(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        match self {
+            Environment::Map(map) => map.random(sampler),
+            Environment::Daylight(daylight) => Some(daylight.random(sampler)),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Environment {
+    /// Maps only equal themselves.
+    fn eq(&self, other: &Environment) -> bool {
+        match (self, other) {
+            (Environment::Constant(a), Environment::Constant(b)) => a == b,
+            (Environment::Gradient { bottom: a, top: b }, Environment::Gradient { bottom: c, top: d }) => {
+                a == c && b == d
+            }
+            (Environment::Map(a), Environment::Map(b)) => Arc::ptr_eq(a, b),
+            (Environment::Daylight(a), Environment::Daylight(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Environment::Constant(color) => f.debug_tuple("Constant").field(color).finish(),
+            Environment::Gradient { bottom, top } => {
+                f.debug_struct("Gradient").field("bottom", bottom).field("top", top).finish()
+            }
+            Environment::Map(map) => f
+                .debug_struct("Map")
+                .field("width", &map.image.width())
+                .field("height", &map.image.height())
+                .field("rotation", &map.rotation)
+                .field("intensity", &map.intensity)
+                .finish(),
+            Environment::Daylight(daylight) => f.debug_tuple("Daylight").field(daylight).finish(),
+        }
+    }
+}
+
+/// An equirectangular (latitude-longitude) HDR image wrapped around the
+/// scene. The image's center looks down -z and its top row is straight up;
+/// `rotation` turns it about the y axis. Directions are importance sampled
+/// by luminance, so small bright suns are found by light sampling.
+pub struct EnvironmentMap {
+    image: Image,
+    /// Counterclockwise turn about +y, in radians
+    rotation: f32,
+    intensity: f32,
+    distribution: Distribution2d,
+}
+
+impl EnvironmentMap {
+    /// `rotation` is in degrees; `intensity` scales the image's radiance.
+    pub fn new(image: Image, rotation: f32, intensity: f32) -> EnvironmentMap {
+        let (width, height) = (image.width(), image.height());
+        // Rows near the poles cover less of the sphere
+        let weights = (0..height)
+            .flat_map(|y| {
+                let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+                let image = &image;
+                (0..width).map(move |x| luminance(image.pixel(x, y)).max(0.0) * sin_theta)
+            })
+            .collect();
+
+        EnvironmentMap {
+            distribution: Distribution2d::new(weights, width, height),
+            image,
+            rotation: rotation.to_radians(),
+            intensity,
+        }
+    }
+
+    /// Reads an HDR (or any other supported) image file.
+    pub fn load(path: &Path, rotation: f32, intensity: f32) -> io::Result<EnvironmentMap> {
+        Ok(EnvironmentMap::new(input::load(path)?, rotation, intensity))
+    }
+
+    /// Image coordinates in [0, 1)² of a unit direction.
+    fn uv(&self, direction: Vec3) -> (f32, f32) {
+        let phi = direction.x().atan2(-direction.z()) + self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = direction.y().clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn direction_at(&self, u: f32, v: f32) -> Vec3 {
+        let phi = 2.0 * PI * (u - 0.5) - self.rotation;
+        let theta = PI * v;
+        Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    fn texel(&self, u: f32, v: f32) -> (usize, usize) {
+        let (width, height) = (self.image.width(), self.image.height());
+        (
+            ((u * width as f32) as usize).min(width - 1),
+            ((v * height as f32) as usize).min(height - 1),
+        )
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        if self.image.width() == 0 || self.image.height() == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let (u, v) = self.uv(direction);
+        let (x, y) = self.texel(u, v);
+        self.intensity * self.image.pixel(x, y)
+    }
+
+    pub fn pdf_value(&self, direction: Vec3) -> f32 {
+        if self.distribution.is_empty() {
+            return 0.0;
+        }
+        let (u, v) = self.uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.texel(u, v);
+        // From density over the image to density over the sphere
+        self.distribution.pdf(x, y) / (2.0 * PI * PI * sin_theta)
+    }
+
+    pub fn random(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if self.distribution.is_empty() {
+            return None;
+        }
+        let (u1, u2) = sampler.next_2d();
+        let (u, v) = self.distribution.sample(u1, u2);
+        Some(self.direction_at(u, v))
+    }
+}
+
+/// A piecewise-constant density over [0, 1).
+struct Distribution1d {
+    weights: Vec<f32>,
+    /// Running sums of `weights`, normalized to end at one
+    cdf: Vec<f32>,
+    total: f32,
+}
+
+impl Distribution1d {
+    fn new(weights: Vec<f32>) -> Distribution1d {
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        for weight in &weights {
+            cdf.push(cdf[cdf.len() - 1] + weight);
+        }
+        let total = cdf[cdf.len() - 1];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            // All-zero weights fall back to uniform
+            *value = if total > 0.0 { *value / total } else { i as f32 / weights.len() as f32 };
+        }
+        Distribution1d { weights, cdf, total }
+    }
+
+    /// Density of the cell `index`, relative to the uniform one.
+    fn pdf(&self, index: usize) -> f32 {
+        if self.total > 0.0 {
+            self.weights[index] * self.weights.len() as f32 / self.total
+        } else {
+            1.0
+        }
+    }
+
+    /// Inverts the cdf at `u`, returning the cell and the position in [0, 1).
+    fn sample(&self, u: f32) -> (usize, f32) {
+        // The last cell whose cdf starts at or below u, skipping empty ones
+        let index = self.cdf.partition_point(|&value| value <= u).clamp(1, self.weights.len()) - 1;
+        let (start, end) = (self.cdf[index], self.cdf[index + 1]);
+        let offset = if end > start { ((u - start) / (end - start)).clamp(0.0, 1.0) } else { 0.5 };
+        let position = (index as f32 + offset) / self.weights.len() as f32;
+        (index, position.min(1.0 - f32::EPSILON))
+    }
+}
+
+/// A piecewise-constant density over [0, 1)², sampled as a row (by the
+/// marginal) and then a column within it.
+struct Distribution2d {
+    rows: Vec<Distribution1d>,
+    marginal: Distribution1d,
+}
+
+impl Distribution2d {
+    /// `weights` are row-major, `width` per row.
+    fn new(weights: Vec<f32>, width: usize, height: usize) -> Distribution2d {
+        let rows: Vec<Distribution1d> = (0..height)
+            .map(|y| Distribution1d::new(weights[y * width..(y + 1) * width].to_vec()))
+            .collect();
+        let marginal = Distribution1d::new(rows.iter().map(|row| row.total).collect());
+        Distribution2d { rows, marginal }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.marginal.total <= 0.0
+    }
+
+    fn pdf(&self, x: usize, y: usize) -> f32 {
+        self.marginal.pdf(y) * self.rows[y].pdf(x)
+    }
+
+    /// A point (u, v), with v picking the row.
+    fn sample(&self, u1: f32, u2: f32) -> (f32, f32) {
+        let (y, v) = self.marginal.sample(u1);
+        let (_, u) = self.rows[y].sample(u2);
+        (u, v)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::{Point3, random_unit_vector};
+
+    fn toward(direction: Vec3) -> Ray {
+        Ray::new(Point3::new(0.0, 0.0, 0.0), direction, 0.0)
+    }
+
+    /// A dim map with one bright texel.
+    fn sun_map(rotation: f32) -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, Color::new(0.1, 0.1, 0.1));
+            }
+        }
+        image.set_pixel(8, 2, Color::new(100.0, 80.0, 60.0));
+        EnvironmentMap::new(image, rotation, 2.0)
+    }
+
+    #[test]
+    fn gradient_matches_the_old_sky() {
+        assert_eq!(Environment::SKY.color(toward(Vec3::new(0.0, 3.0, 0.0))), Color::new(0.5, 0.7, 1.0));
+        assert_eq!(Environment::SKY.color(toward(Vec3::new(0.0, -1.0, 0.0))), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(Environment::Constant(Color::new(0.1, 0.2, 0.3)).color(toward(Vec3::new(1.0, 0.0, 0.0))),
+            Color::new(0.1, 0.2, 0.3));
+        assert!(Environment::SKY.random(&mut IndependentSampler::new(0)).is_none());
+        assert_eq!(Environment::SKY.pdf_value(Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn map_directions_round_trip() {
+        let mut sampler = IndependentSampler::new(0);
+        for rotation in [0.0, 90.0, -30.0] {
+            let map = sun_map(rotation);
+            for _ in 0..100 {
+                let direction = random_unit_vector(&mut sampler);
+                let (u, v) = map.uv(direction);
+                assert!((map.direction_at(u, v) - direction).length() < 1e-3);
+            }
+        }
+
+        // The image center looks down -z, or down -x after a quarter turn
+        let map = sun_map(0.0);
+        let (u, v) = map.uv(Vec3::new(0.0, 0.0, -1.0));
+        assert!((u - 0.5).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+        let turned = sun_map(90.0);
+        assert!((turned.direction_at(0.5, 0.5) - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-6);
+
+        // Intensity scales the radiance
+        assert!((map.radiance(Vec3::new(0.0, 0.0, -1.0)) - Color::new(0.2, 0.2, 0.2)).length() < 1e-6);
+    }
+
+    #[test]
+    fn sampling_favors_the_sun_and_matches_the_pdf() {
+        let map = sun_map(45.0);
+        let sun = map.direction_at(8.5 / 16.0, 2.5 / 8.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 20_000;
+        let mut near_sun = 0;
+        for _ in 0..trials {
+            let direction = map.random(&mut sampler).unwrap();
+            assert!((direction.length() - 1.0).abs() < 1e-4);
+            assert!(map.pdf_value(direction) > 0.0);
+            if map.radiance(direction).r() > 1.0 {
+                near_sun += 1;
+            }
+        }
+        assert!(near_sun as f32 / trials as f32 > 0.5);
+        assert!(map.radiance(sun).r() > 1.0);
+
+        // The density integrates to one over the sphere
+        let total: f32 = (0..200_000).map(|_| map.pdf_value(random_unit_vector(&mut sampler))).sum();
+        assert!((total * 4.0 * PI / 200_000.0 - 1.0).abs() < 0.05, "{}", total * 4.0 * PI / 200_000.0);
+    }
+
+    #[test]
+    fn distribution_inverts_its_cdf() {
+        let distribution = Distribution1d::new(vec![0.0, 3.0, 1.0, 0.0]);
+        assert_eq!(distribution.pdf(1), 3.0);
+        assert_eq!(distribution.sample(0.0).0, 1);
+        assert_eq!(distribution.sample(0.74).0, 1);
+        assert_eq!(distribution.sample(0.76).0, 2);
+        assert_eq!(distribution.sample(0.999_999).0, 2);
+        let (_, position) = distribution.sample(0.375);
+        assert!((position - 0.375).abs() < 1e-6);
+
+        let empty = Distribution1d::new(vec![0.0, 0.0]);
+        assert_eq!(empty.pdf(0), 1.0);
+        assert_eq!(empty.sample(0.75).0, 1);
+    }
+}
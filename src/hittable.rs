@@ -1,18 +1,69 @@
+use std::sync::Arc;
+
+use crate::aabb::{Aabb, surrounding_box};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::utility::{dot};
+use crate::sampler::Sampler;
+use crate::utility::dot;
 use crate::vec3::{Point3, Vec3};
 
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f32,
+    pub u: f32,
+    pub v: f32,
     pub front_face: bool,
     pub material: &'a Material
 }
 
-pub trait Hittable: Sync {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+pub trait Hittable: Send + Sync {
+    /// Where the ray first meets the object's surface.
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    /// Where a path following the ray next interacts with the object,
+    /// drawing from the path's sampler for anything random. A solid shape
+    /// returns `hit`; a participating medium scatters somewhere inside its
+    /// boundary, or lets the ray through. Containers pass the sampler on to
+    /// what they hold.
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>>;
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb>;
+
+    /// Solid-angle density with which `random(origin)` returns `direction`.
+    /// Shapes that can't be sampled (the default) return zero.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f32 {
+        0.0
+    }
+
+    /// A direction from `origin` toward a random point on the shape, for
+    /// sampling it as a light.
+    fn random(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+/// Lets one object be shared between the world and the light list.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.as_ref().sample_hit(ray, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        self.as_ref().bounding_box(time0, time1)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.as_ref().random(origin, sampler)
+    }
 }
 
 pub struct HittableList {
@@ -23,15 +74,28 @@ impl HittableList {
     pub fn new(objects: Vec<Box<dyn Hittable>>) -> HittableList {
         HittableList { objects }
     }
-}
 
-impl Hittable for HittableList {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// The closest of the hits that `hit` finds on each object in turn.
+    pub(crate) fn closest<'a>(
+        &'a self, t_max: f32, mut hit: impl FnMut(&'a dyn Hittable, f32) -> Option<HitRecord<'a>>,
+    ) -> Option<HitRecord<'a>> {
         let mut hit_anything: Option<HitRecord> = None;
         let mut closest_so_far: f32 = t_max;
 
         for object in self.objects.iter() {
-            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+            if let Some(hit) = hit(object.as_ref(), closest_so_far) {
                 closest_so_far = hit.t;
                 hit_anything = Some(hit);
             }
@@ -40,6 +104,60 @@ impl Hittable for HittableList {
     }
 }
 
+impl Hittable for HittableList {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.closest(t_max, |object, t_max| object.hit(ray, t_min, t_max))
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.closest(t_max, |object, t_max| object.sample_hit(ray, t_min, t_max, sampler))
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let mut output_box: Option<Aabb> = None;
+
+        for object in self.objects.iter() {
+            let object_box = object.bounding_box(time0, time1)?;
+            output_box = match output_box {
+                Some(current) => Some(surrounding_box(current, object_box)),
+                None => Some(object_box),
+            };
+        }
+        output_box
+    }
+
+    /// Sampling a list picks one of its objects uniformly, so the density is
+    /// the average of theirs.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let total: f32 = self.objects.iter().map(|object| object.pdf_value(origin, direction)).sum();
+        total / self.objects.len() as f32
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let index = ((sampler.next_1d() * self.objects.len() as f32) as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin, sampler)
+    }
+}
+
+/// Converts uniform sampling over a flat shape of the given area into a
+/// solid-angle density, given where the sampled direction hit the shape.
+pub(crate) fn planar_pdf(hit: Option<HitRecord>, direction: Vec3, area: f32) -> f32 {
+    match hit {
+        Some(hit) => {
+            let distance_squared = hit.t * hit.t * direction.length_squared();
+            let cosine = dot(direction, hit.normal).abs() / direction.length();
+            distance_squared / (cosine * area)
+        }
+        None => 0.0,
+    }
+}
+
 impl HitRecord<'_> {
     pub fn set_face_normal(&mut self, ray: Ray, outward_normal: Vec3) {
         self.front_face = dot(ray.direction(), outward_normal) < 0.0;
@@ -0,0 +1,62 @@
+use crate::vec3::Color;
+
+/// A framebuffer of linear radiance values, stored row by row from the top.
+/// Nothing is clamped or gamma corrected here; that happens when the image
+/// is written out (see `output`).
+#[derive(Debug, PartialEq, Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(pixels.len(), width * height, "pixel count doesn't match image size");
+        Image { width, height, pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_row_major() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(2, 1, Color::new(1.0, 2.0, 3.0));
+
+        assert_eq!(image.pixels()[5], Color::new(1.0, 2.0, 3.0));
+        assert_eq!(image.pixel(2, 1), Color::new(1.0, 2.0, 3.0));
+        assert_eq!(image.pixel(0, 0), Color::new(0.0, 0.0, 0.0));
+    }
+}
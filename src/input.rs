@@ -0,0 +1,190 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::image::Image;
+use crate::output::ImageFormat;
+use crate::radiance::read_hdr;
+use crate::vec3::Color;
+
+/// Undoes the gamma 2 encoding `output::to_8bit` applies, so 8-bit images
+/// written by the renderer come back as the linear colors they were made from.
+pub fn from_8bit(rgb: [u8; 3]) -> Color {
+    let linear = |value: u8| {
+        let value = value as f32 / 255.0;
+        value * value
+    };
+    Color::new(linear(rgb[0]), linear(rgb[1]), linear(rgb[2]))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Whitespace-separated header fields and plain-text samples of a PPM file.
+struct PpmTokens<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl PpmTokens<'_> {
+    fn skip_whitespace_and_comments(&mut self) {
+        while self.position < self.bytes.len() {
+            match self.bytes[self.position] {
+                b'#' => {
+                    while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        self.skip_whitespace_and_comments();
+        let start = self.position;
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_digit() {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| invalid_data("expected a number in PPM data"))
+    }
+}
+
+/// Reads a binary (P6) or plain-text (P3) PPM file into linear colors.
+pub fn read_ppm(bytes: &[u8]) -> io::Result<Image> {
+    let binary = match bytes.get(..2) {
+        Some(b"P6") => true,
+        Some(b"P3") => false,
+        _ => return Err(invalid_data("not a P3 or P6 PPM file")),
+    };
+    let mut tokens = PpmTokens { bytes, position: 2 };
+    let width = tokens.number()?;
+    let height = tokens.number()?;
+    let max_value = tokens.number()?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("PPM maximum value must be between 1 and 65535"));
+    }
+
+    let count = width * height * 3;
+    let mut samples = Vec::with_capacity(count);
+    if binary {
+        // A single whitespace byte separates the header from the samples.
+        let start = tokens.position + 1;
+        let size = if max_value > 255 { 2 } else { 1 };
+        let data = bytes
+            .get(start..start + count * size)
+            .ok_or_else(|| invalid_data("PPM pixel data is truncated"))?;
+        if size == 2 {
+            samples.extend(data.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize));
+        } else {
+            samples.extend(data.iter().map(|&value| value as usize));
+        }
+    } else {
+        for _ in 0..count {
+            samples.push(tokens.number()?);
+        }
+    }
+    if samples.iter().any(|&value| value > max_value) {
+        return Err(invalid_data("PPM sample exceeds the maximum value"));
+    }
+
+    let pixels = samples
+        .chunks(3)
+        .map(|rgb| {
+            let scale = |value: usize| (value * 255 / max_value) as u8;
+            from_8bit([scale(rgb[0]), scale(rgb[1]), scale(rgb[2])])
+        })
+        .collect();
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+/// Reads a PNG file into linear colors. Alpha is ignored.
+pub fn read_png<R: Read>(reader: R) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(png_error)?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(invalid_data("unexpanded indexed PNG")),
+    };
+    let pixels = buffer[..info.buffer_size()]
+        .chunks(channels)
+        .map(|texel| match channels {
+            1 | 2 => from_8bit([texel[0], texel[0], texel[0]]),
+            _ => from_8bit([texel[0], texel[1], texel[2]]),
+        })
+        .collect();
+    Ok(Image::from_pixels(info.width as usize, info.height as usize, pixels))
+}
+
+fn png_error(error: png::DecodingError) -> io::Error {
+    match error {
+        png::DecodingError::IoError(error) => error,
+        other => invalid_data(&other.to_string()),
+    }
+}
+
+/// Loads an image, picking the reader from the file extension.
+pub fn load(path: &Path) -> io::Result<Image> {
+    match ImageFormat::from_path(path) {
+        Some(ImageFormat::Ppm) => read_ppm(&fs::read(path)?),
+        Some(ImageFormat::Png) => read_png(io::BufReader::new(fs::File::open(path)?)),
+        Some(ImageFormat::Hdr) => read_hdr(&fs::read(path)?),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "only PNG, PPM and HDR images can be read")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{write_png, write_ppm};
+
+    fn test_image() -> Image {
+        Image::from_pixels(2, 1, vec![Color::new(0.25, 0.0, 1.0), Color::new(1.0, 1.0, 1.0)])
+    }
+
+    fn assert_close(a: &Image, b: &Image) {
+        assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+        for (x, y) in a.pixels().iter().zip(b.pixels()) {
+            assert!((*x - *y).length() < 0.01, "{:?} != {:?}", x, y);
+        }
+    }
+
+    #[test]
+    fn ppm_round_trip() {
+        let mut bytes = vec![];
+        write_ppm(&test_image(), &mut bytes).unwrap();
+
+        assert_close(&read_ppm(&bytes).unwrap(), &test_image());
+    }
+
+    #[test]
+    fn ascii_ppm_with_comments() {
+        let image = read_ppm(b"P3\n# a comment\n2 1\n15\n15 0 15\n0 15 0\n").unwrap();
+
+        assert_eq!(image.pixel(0, 0), Color::new(1.0, 0.0, 1.0));
+        assert_eq!(image.pixel(1, 0), Color::new(0.0, 1.0, 0.0));
+        assert!(read_ppm(b"P3\n2 1\n255\n1 2 3\n").is_err());
+        assert!(read_ppm(b"P5\n1 1\n255\n0").is_err());
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut bytes = vec![];
+        write_png(&test_image(), &mut bytes).unwrap();
+
+        assert_close(&read_png(bytes.as_slice()).unwrap(), &test_image());
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::Transform;
+use crate::utility::unit_vector;
+
+/// Places a shared object in the world through an affine transform, so the
+/// same geometry can appear many times without being copied.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Instance { object, transform }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// Hits the object with `ray` moved into object space, then moves the
+    /// record back out.
+    fn hit_local<'a>(
+        &'a self, ray: Ray, hit: impl FnOnce(&'a dyn Hittable, Ray) -> Option<HitRecord<'a>>,
+    ) -> Option<HitRecord<'a>> {
+        // The object-space direction is left unnormalized so that t means the
+        // same thing on both sides of the transform.
+        let to_object = self.transform.inverse();
+        let local = Ray { origin: to_object.point(ray.origin()), direction: to_object.vector(ray.direction()), ..ray };
+
+        let mut record = hit(self.object.as_ref(), local)?;
+        record.p = self.transform.point(record.p);
+        // The normal already faces against the local ray, and an invertible
+        // transform keeps it facing against the world ray too.
+        record.normal = unit_vector(self.transform.normal(record.normal));
+        Some(record)
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.hit_local(ray, |object, local| object.hit(local, t_min, t_max))
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit_local(ray, |object, local| object.sample_hit(local, t_min, t_max, sampler))
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let local = self.object.bounding_box(time0, time1)?;
+        Some(self.transform.bounding_box(local))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::quad::BoxShape;
+    use crate::sphere::Sphere;
+    use crate::utility::INFINITY;
+    use crate::vec3::{Color, Point3, Vec3};
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Material::lambertian(Color::new(1.0, 1.0, 1.0))))
+    }
+
+    // Loose enough to absorb the padding on flat boxes
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn translated_copies_share_geometry() {
+        let sphere = unit_sphere();
+        let left = Instance::new(sphere.clone(), Transform::translate(Vec3::new(-3.0, 0.0, 0.0)));
+        let right = Instance::new(sphere, Transform::translate(Vec3::new(3.0, 0.0, 0.0)));
+        let ray = Ray::new(Point3::new(3.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        assert!(left.hit(ray, 0.001, INFINITY).is_none());
+        let hit = right.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 4.0);
+        assert_close(hit.p, Point3::new(3.0, 0.0, 1.0));
+        assert_close(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn scaled_sphere_normals_use_inverse_transpose() {
+        // Squash the sphere into an ellipsoid twice as wide as it is tall
+        let ellipsoid = Instance::new(unit_sphere(), Transform::scale(Vec3::new(2.0, 1.0, 1.0)));
+        let p = Point3::new(2f32.sqrt(), 2f32.sqrt() / 2.0, 0.0);
+        let ray = Ray::new(p + Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+
+        let hit = ellipsoid.hit(ray, 0.001, INFINITY).unwrap();
+        assert_close(hit.p, p);
+        // Gradient of x^2/4 + y^2 at p, normalized
+        assert_close(hit.normal, unit_vector(Vec3::new(p.x() / 2.0, 2.0 * p.y(), 0.0)));
+
+        let bbox = ellipsoid.bounding_box(0.0, 1.0).unwrap();
+        assert_close(bbox.max(), Point3::new(2.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn nested_instances_compose() {
+        let material = Material::lambertian(Color::new(1.0, 1.0, 1.0));
+        let cube: Arc<dyn Hittable> = Arc::new(BoxShape::new(
+            Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0), material,
+        ));
+        let rotated: Arc<dyn Hittable> = Arc::new(Instance::new(cube, Transform::rotate_y(90.0)));
+        let placed = Instance::new(rotated, Transform::translate(Vec3::new(10.0, 0.0, 0.0)));
+
+        // rotate_y(90) takes the unit cube to x in [0, 1], z in [-1, 0]
+        let bbox = placed.bounding_box(0.0, 1.0).unwrap();
+        assert_close(bbox.min(), Point3::new(10.0, 0.0, -1.0));
+        assert_close(bbox.max(), Point3::new(11.0, 1.0, 0.0));
+
+        let ray = Ray::new(Point3::new(10.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = placed.hit(ray, 0.001, INFINITY).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+    }
+}
@@ -0,0 +1,58 @@
+pub mod aabb;
+pub mod bsdf;
+pub mod bvh;
+pub mod camera;
+pub mod daylight;
+pub mod environment;
+pub mod hittable;
+pub mod image;
+pub mod input;
+pub mod instance;
+pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod obj;
+pub mod onb;
+pub mod openexr;
+pub mod output;
+pub mod presets;
+pub mod principled;
+pub mod quad;
+pub mod radiance;
+pub mod ray;
+pub mod rect;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
+pub mod utility;
+pub mod vec3;
+
+pub use crate::bsdf::{BsdfSample, Lobes};
+pub use crate::bvh::BvhNode;
+pub use crate::camera::{Camera, CameraSettings};
+pub use crate::daylight::{Daylight, DaylightSettings};
+pub use crate::environment::{Environment, EnvironmentMap};
+pub use crate::hittable::{HitRecord, Hittable, HittableList};
+pub use crate::image::Image;
+pub use crate::instance::Instance;
+pub use crate::material::Material;
+pub use crate::medium::ConstantMedium;
+pub use crate::microfacet::ComplexIor;
+pub use crate::output::ImageFormat;
+pub use crate::quad::{BoxShape, Quad};
+pub use crate::ray::Ray;
+pub use crate::rect::{XyRect, XzRect, YzRect};
+pub use crate::renderer::{Integrator, InvalidSetting, Progress, RenderSettings, Renderer};
+pub use crate::sampler::{IndependentSampler, Sampler, SamplerKind};
+pub use crate::scene::{Scene, SceneError, load_scene};
+pub use crate::spectrum::Ior;
+pub use crate::sphere::{MovingSphere, Sphere};
+pub use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+pub use crate::transform::Transform;
+pub use crate::triangle::{MeshError, Triangle, TriangleMesh};
+pub use crate::vec3::{Color, Point3, Vec3};
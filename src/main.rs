@@ -1,187 +1,112 @@
-mod camera;
-mod ray;
-mod utility;
-mod vec3;
-mod sphere;
-mod hittable;
-mod material;
-
-use crate::camera::{Camera};
-use crate::hittable::{Hittable, HittableList};
-use crate::material::{Material};
-use crate::sphere::{Sphere, MovingSphere};
-use crate::utility::{
-    INFINITY, unit_vector,
-    random_double, clamp,
-    random_double_range,
-};
-use crate::vec3::{Vec3, Color, Point3};
-
-use rayon::prelude::*;
-use std::fs;
-
-
-fn ray_color(ray: ray::Ray, world: &hittable::HittableList, depth: usize) -> vec3::Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
+mod cli;
 
-    if let Some(hit) = world.hit(ray, 0.001, INFINITY) {
-        if let Some(scatter_tuple) = hit.material.scatter(&ray, &hit) {
-            let (scattered, attenuation, hit) = scatter_tuple;
+use std::path::Path;
+use std::time::Duration;
 
-            if hit {
-                return attenuation * ray_color(scattered, world, depth -1 );
-            }
-            return Color::new(0.0, 0.0, 0.0);
-        }
-    }
+use clap::Parser;
 
-    let unit_direciton = unit_vector(ray.direction());
-    let t = 0.5 * (unit_direciton.y() + 1.0);
-    return (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0);
-}
+use river::{BvhNode, Renderer, Scene, Vec3, output, presets};
 
-fn scene() -> HittableList {
-    let material_ground = Material::Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5)
-    };
+use crate::cli::{Cli, Command, RenderOptions};
 
-    let mut objects: Vec<Box<dyn Hittable>> = vec![];
 
-    objects.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, material_ground)));
+fn load_scene(name: &str) -> Result<Scene, String> {
+    let path = Path::new(name);
+    if path.is_file() {
+        return river::load_scene(path).map_err(|error| error.to_string());
+    }
 
-    for i in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_double();
-            let center: Point3 = Point3::new(
-                i as f32 + 0.9*random_double(),
-                0.2,
-                b as f32 + 0.9*random_double()
-            );
+    presets::by_name(name).ok_or_else(|| {
+        format!(
+            "'{}' is neither a scene file nor a built-in scene ({})",
+            name,
+            presets::NAMES.join(", ")
+        )
+    })
+}
 
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let albedo: Color;
-                let fuzz: f32;
+fn run(options: &RenderOptions, frames: Option<(usize, Vec3)>) -> Result<(), String> {
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|error| error.to_string())?;
+    }
 
-                if choose_mat < 0.8 {
-                    // diffuse
-                    albedo = Color::random() * Color::random();
-                    let sphere_material = Material::Lambertian { albedo };
-                    let center2 = center + Vec3::new(0.0, random_double_range(0.0, 0.5), 0.0);
+    let mut scene = load_scene(&options.scene)?;
+    options.apply(&mut scene.settings);
+    scene.settings.validate().map_err(|error| error.to_string())?;
 
-                    objects.push(Box::new(
-                        MovingSphere::new(center, center2, 0.0, 1.0, 0.2, sphere_material)
-                    ));
-                }
-                else if choose_mat < 0.95 {
-                    // metal
-                    albedo = Color::random_range(0.5, 1.0);
-                    fuzz = random_double();
-                    let sphere_material = Material::Metal{ albedo, fuzz };
-                    objects.push(Box::new(
-                        Sphere::new(center, 0.2, sphere_material)
-                    ));
+    let settings = scene.settings;
+    let mut camera_settings = scene.camera_settings;
+    let world = BvhNode::from_list(scene.world, camera_settings.time0, camera_settings.time1);
+
+    let (frame_count, step) = match frames {
+        Some((count, step)) => (count, step),
+        None => (1, Vec3::new(0.0, 0.0, 0.0)),
+    };
+
+    let mut renderer = Renderer::new(settings);
+    renderer.environment = scene.environment;
+    renderer.lights = scene.lights;
+
+    for frame in 1..frame_count + 1 {
+        println!("Starting frame: {}", frame);
+        let camera = camera_settings.build(settings.aspect_ratio);
+        let file_name = options.output_path(frames.as_ref().map(|_| frame));
+        let mut last_snapshot = Duration::ZERO;
+        let progress = match options.snapshot_interval {
+            Some(interval) => renderer.render_progressive(&world, &camera, |progress| {
+                if progress.elapsed < last_snapshot + interval {
+                    return;
                 }
-                else {
-                    // glass
-                    let sphere_material = Material::Dielectric { index_of_refraction: 1.5 };
-                    objects.push(Box::new(
-                        Sphere::new(center, 0.2, sphere_material)
-                    ));
+                last_snapshot = progress.elapsed;
+                println!(
+                    "Writing snapshot after {:.1}s, {:.1} samples per pixel: {}",
+                    progress.elapsed.as_secs_f32(), progress.average_samples(), file_name.display()
+                );
+                // A failed snapshot shouldn't throw away the render; the final
+                // write reports the error if it persists
+                if let Err(error) = output::save(&progress.image, options.output_format(), &file_name) {
+                    eprintln!("Warning: {}: {}", file_name.display(), error);
                 }
-            }
+            }),
+            None => renderer.render_to_completion(&world, &camera),
+        };
+        if settings.adaptive_threshold.is_some() || settings.time_limit.is_some() {
+            println!(
+                "Rendered {} passes in {:.1}s, {:.1} samples per pixel",
+                progress.passes, progress.elapsed.as_secs_f32(), progress.average_samples()
+            );
         }
-    }
 
-    let material_1 = Material::Dielectric {
-        index_of_refraction: 1.5
-    };
-    let material_2 = Material::Lambertian {
-        albedo: Color::new(0.4, 0.2, 0.1)
-    };
-    let material_3 = Material::Metal {
-        albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.0
-    };
+        println!("Writing frame: {}", file_name.display());
+        output::save(&progress.image, options.output_format(), &file_name)
+            .map_err(|error| format!("{}: {}", file_name.display(), error))?;
 
-    objects.push(Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material_1)));
-    objects.push(Box::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material_2)));
-    objects.push(Box::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material_3)));
-
-    HittableList::new(objects)
-}
+        if let Some((file_name, format)) = options.heatmap_output(frames.as_ref().map(|_| frame)) {
+            println!("Writing sample heatmap: {}", file_name.display());
+            output::save(&renderer.heatmap(&progress.sample_counts), format, &file_name)
+                .map_err(|error| format!("{}: {}", file_name.display(), error))?;
+        }
 
-fn balls_on_plain(iterations: usize) {
-    let world = scene();
-
-    let mut x = 13.0;
-    let mut z = 3.0;
-
-    let x_increment = 0.05;
-    let z_increment = 0.1;
-
-    let aspect_ratio: f32 = 16.0 / 9.0;
-    let image_width: usize = 400;
-    let image_height: usize = ((image_width as f32) / aspect_ratio) as usize;
-    let samples_per_pizel: usize = 100;
-    let max_depth: usize = 50;
-
-    for iteration in 1..iterations + 1 {
-        println!("Starting iteration: {}", iteration);
-        let look_from: Point3 = Point3::new(x, 2.0, z);
-        x += x_increment;
-        z += z_increment;
-        let look_at: Point3 = Point3::new(0.0, 0.0, 0.0);
-        let vup: Vec3 = Vec3::new(0.0, 1.0, 0.0);
-        let dist_to_focus: f32 = 10.0;
-        let aperture: f32 = 0.1;
-
-        let camera = Camera::new(
-            look_from, look_at, vup,
-            20.0, aspect_ratio, aperture, dist_to_focus,
-            0.0, 1.0
-        );
-
-        let pixels = (0..image_height)
-            .into_par_iter()
-            .rev()
-            .map(|j| {
-                (0..image_width)
-                    .into_par_iter()
-                    .map(|i| {
-                        let mut col = Color::new(0.0, 0.0, 0.0);
-                        for _ in 0..samples_per_pizel {
-                            let u = (i as f32 + random_double()) / (image_width as f32 - 1.0);
-                            let v = (j as f32 + random_double()) / (image_height as f32 - 1.0);
-                            let ray = camera.get_ray(u, v);
-                            col += ray_color(ray, &world, max_depth);
-                        }
-                        col = col / samples_per_pizel as f32;
-                        col = Color::new(col.x().sqrt(), col.y().sqrt(), col.z().sqrt());
-                        let ir = 255.99 * clamp(col.x(), 0.0, 0.999);
-                        let ig = 255.99 * clamp(col.y(), 0.0, 0.999);
-                        let ib = 255.99 * clamp(col.z(), 0.0, 0.999);
-                        format!("{} {} {}\n", ir as i32, ig as i32, ib as i32)
-                    })
-                .collect::<Vec<String>>()
-                .join("")
-            })
-        .collect::<Vec<String>>()
-        .join("");
-
-        let mut pic = format!("P3\n{} {}\n255\n", image_width, image_height);
-        pic = format!("{}{}", &pic, pixels);
-
-        let file_name = format!("output-{}.ppm", iteration);
-
-        println!("Writing iteration: {}", file_name);
-        if fs::write(file_name, pic).is_err() {
-            eprintln!("Error generating image");
-        };
+        camera_settings.look_from += step;
     }
+    Ok(())
 }
 
-
 fn main() {
-    balls_on_plain(1);
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Command::Render { options } => run(options, None),
+        Command::Animate { options, frames, step } => {
+            run(options, Some((*frames, *step)))
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("Error: {}", error);
+        std::process::exit(1);
+    }
 }
@@ -1,60 +1,188 @@
+use std::sync::Arc;
+
+use crate::bsdf::{BsdfSample, Lobes};
 use crate::hittable::HitRecord;
+use crate::microfacet::{ComplexIor, Ggx, RoughConductor, RoughDielectric};
+use crate::onb::Onb;
+use crate::principled::Principled;
 use crate::ray::Ray;
-use crate::utility::{random_double};
+use crate::sampler::Sampler;
+use crate::spectrum::Ior;
+use crate::texture::{SolidColor, Texture};
+use crate::utility::PI;
 use crate::vec3::{
-    Color, Vec3,
+    Color, Point3, Vec3,
     dot, random_unit_vector, reflect,
     unit_vector, random_unit_in_sphere,
-    refract
+    refract, random_cosine_direction
 };
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum Material {
     Metal {
-        albedo: Color,
+        albedo: Arc<dyn Texture>,
         fuzz: f32
     },
 
     Lambertian {
-        albedo: Color
+        albedo: Arc<dyn Texture>
     },
 
+    /// Smooth glass. Light travelling inside is attenuated by `absorption`,
+    /// the Beer–Lambert coefficient per unit length; black for clear glass.
+    /// Spectral renders split light by wavelength when the index varies.
     Dielectric {
-        index_of_refraction: f32
-    }
+        index_of_refraction: Ior,
+        absorption: Color
+    },
+
+    DiffuseLight {
+        emit: Arc<dyn Texture>
+    },
+
+    /// Scatters equally in every direction; the phase function of a
+    /// participating medium.
+    Isotropic {
+        albedo: Arc<dyn Texture>
+    },
+
+    /// A rough metal with a GGX microfacet surface; see `ComplexIor` for
+    /// measured presets.
+    Conductor {
+        ior: ComplexIor,
+        roughness: f32
+    },
+
+    /// Frosted glass with a GGX microfacet surface.
+    RoughDielectric {
+        index_of_refraction: f32,
+        roughness: f32
+    },
+
+    /// One material with sliders for everything else; see `Principled`.
+    Principled(Arc<Principled>)
 }
 
 impl Material {
-    pub fn scatter(self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color, bool)> {
+    pub fn lambertian(albedo: Color) -> Self {
+        Material::Lambertian { albedo: Arc::new(SolidColor::new(albedo)) }
+    }
+
+    pub fn metal(albedo: Color, fuzz: f32) -> Self {
+        Material::Metal { albedo: Arc::new(SolidColor::new(albedo)), fuzz }
+    }
+
+    pub fn dielectric(index_of_refraction: impl Into<Ior>) -> Self {
+        Material::Dielectric { index_of_refraction: index_of_refraction.into(), absorption: Color::new(0.0, 0.0, 0.0) }
+    }
+
+    /// Glass that lets `color` through after light has travelled `distance`
+    /// inside it, so thicker parts look deeper.
+    pub fn colored_glass(index_of_refraction: impl Into<Ior>, color: Color, distance: f32) -> Self {
+        let coefficient = |c: f32| -c.clamp(1e-6, 1.0).ln() / distance;
+        Material::Dielectric {
+            index_of_refraction: index_of_refraction.into(),
+            absorption: Color::new(coefficient(color.r()), coefficient(color.g()), coefficient(color.b())),
+        }
+    }
+
+    pub fn diffuse_light(emit: Color) -> Self {
+        Material::DiffuseLight { emit: Arc::new(SolidColor::new(emit)) }
+    }
+
+    pub fn isotropic(albedo: Color) -> Self {
+        Material::Isotropic { albedo: Arc::new(SolidColor::new(albedo)) }
+    }
+
+    pub fn conductor(ior: ComplexIor, roughness: f32) -> Self {
+        Material::Conductor { ior, roughness }
+    }
+
+    pub fn rough_dielectric(index_of_refraction: f32, roughness: f32) -> Self {
+        Material::RoughDielectric { index_of_refraction, roughness }
+    }
+
+    pub fn principled(principled: Principled) -> Self {
+        Material::Principled(Arc::new(principled))
+    }
+
+    /// The microfacet lobe of the rough materials, in the frame of `rec`'s
+    /// normal.
+    fn microfacet(&self, rec: &HitRecord) -> Option<(Onb, Microfacet)> {
+        let lobe = match self {
+            Material::Conductor { ior, roughness } => Microfacet::Conductor(RoughConductor {
+                distribution: Ggx::from_roughness(*roughness),
+                ior: *ior,
+            }),
+            Material::RoughDielectric { index_of_refraction, roughness } => {
+                Microfacet::Dielectric(RoughDielectric {
+                    distribution: Ggx::from_roughness(*roughness),
+                    eta: if rec.front_face { *index_of_refraction } else { 1.0 / index_of_refraction },
+                })
+            }
+            _ => return None,
+        };
+        Some((Onb::from_w(rec.normal), lobe))
+    }
+
+    /// The lobes this material scatters into.
+    pub fn lobes(&self) -> Lobes {
+        match self {
+            Material::Lambertian { .. } => Lobes::DIFFUSE | Lobes::REFLECTION,
+            // Fuzzed reflections have no closed-form density, so they are
+            // treated like a mirror: sampled, never evaluated.
+            Material::Metal { .. } => Lobes::SPECULAR | Lobes::REFLECTION,
+            Material::Dielectric { .. } => Lobes::SPECULAR | Lobes::REFLECTION | Lobes::TRANSMISSION,
+            Material::DiffuseLight { .. } => Lobes::NONE,
+            Material::Isotropic { .. } => Lobes::DIFFUSE | Lobes::REFLECTION | Lobes::TRANSMISSION,
+            Material::Conductor { .. } => Lobes::GLOSSY | Lobes::REFLECTION,
+            Material::RoughDielectric { .. } => Lobes::GLOSSY | Lobes::REFLECTION | Lobes::TRANSMISSION,
+            Material::Principled(principled) => principled.lobes(),
+        }
+    }
+
+    /// Draws a direction for light arriving at `rec` and leaving back along
+    /// `ray`, the ray that found it. `None` means the path is absorbed.
+    pub fn sample(&self, rec: &HitRecord, ray: Ray, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let wo = -unit_vector(ray.direction());
         match self {
             Material::Metal { albedo, fuzz } => {
-                let reflected: Vec3 = reflect(unit_vector(r_in.direction()), rec.normal);
-                let scattered = Ray::new(rec.p, reflected + fuzz * random_unit_in_sphere(), r_in.time());
-                let attenuation = albedo;
-                Some((scattered, attenuation, dot(scattered.direction(), rec.normal) > 0.0))
+                let reflected = reflect(-wo, rec.normal) + *fuzz * random_unit_in_sphere(sampler);
+                if dot(reflected, rec.normal) <= 0.0 {
+                    return None;
+                }
+                Some(BsdfSample {
+                    direction: unit_vector(reflected),
+                    weight: albedo.value(rec.u, rec.v, rec.p),
+                    pdf: 0.0,
+                    lobe: Lobes::SPECULAR | Lobes::REFLECTION,
+                })
             }
 
             Material::Lambertian { albedo } => {
-                let mut scatter_direction = rec.normal + random_unit_vector();
-
-                if scatter_direction.near_zero() {
-                    scatter_direction = rec.normal;
+                let direction = Onb::from_w(rec.normal).local(random_cosine_direction(sampler));
+                let pdf = dot(direction, rec.normal) / PI;
+                if pdf <= 0.0 {
+                    return None;
                 }
-
-                let scattered = Ray::new(rec.p, scatter_direction, r_in.time());
-                let attenuation = albedo;
-                Some((scattered, attenuation, true))
+                // The cosine and the 1/pi cancel against the pdf
+                Some(BsdfSample {
+                    direction,
+                    weight: albedo.value(rec.u, rec.v, rec.p),
+                    pdf,
+                    lobe: Lobes::DIFFUSE | Lobes::REFLECTION,
+                })
             }
 
-            Material::Dielectric { index_of_refraction } => {
-                let attenuation = Color::new(1.0, 1.0, 1.0);
+            Material::Dielectric { index_of_refraction, absorption } => {
+                let index_of_refraction = index_of_refraction.at(ray.wavelength());
                 let refraction_ratio = if rec.front_face {
                     1.0 / index_of_refraction
                 } else {
                     index_of_refraction
                 };
 
-                let unit_direction = unit_vector(r_in.direction());
+                let unit_direction = -wo;
 
                 let dot_product = dot(-unit_direction, rec.normal);
                 let cos_theta: f32 = if dot_product < 1.0 {
@@ -66,17 +194,126 @@ impl Material {
                 let sin_theta: f32 = (1.0 - cos_theta * cos_theta).sqrt();
 
                 let cannot_refract: bool = refraction_ratio * sin_theta > 1.0;
-                let direction: Vec3;
+                let (direction, lobe) = if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler.next_1d() {
+                    (reflect(unit_direction, rec.normal), Lobes::REFLECTION)
+                } else {
+                    (refract(unit_direction, rec.normal, refraction_ratio), Lobes::TRANSMISSION)
+                };
 
-                if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_double() {
-                    direction = reflect(unit_direction, rec.normal);
+                // Hitting the surface from inside means the ray has just
+                // crossed the glass, over `t` lengths of its direction.
+                let weight = if rec.front_face {
+                    Color::new(1.0, 1.0, 1.0)
                 } else {
-                    direction = refract(unit_direction, rec.normal, refraction_ratio);
-                }
+                    let distance = rec.t * ray.direction().length();
+                    let transmittance = |sigma: f32| (-sigma * distance).exp();
+                    Color::new(transmittance(absorption.r()), transmittance(absorption.g()), transmittance(absorption.b()))
+                };
+
+                Some(BsdfSample {
+                    direction: unit_vector(direction),
+                    weight,
+                    pdf: 0.0,
+                    lobe: Lobes::SPECULAR | lobe,
+                })
+            }
+
+            Material::DiffuseLight { .. } => None,
 
-                let scattered = Ray::new(rec.p, direction, r_in.time());
-                Some((scattered, attenuation, true))
+            Material::Isotropic { albedo } => {
+                // Scattering back toward the viewer counts as reflection,
+                // carrying on past the particle as transmission
+                let direction = random_unit_vector(sampler);
+                let lobe = if dot(direction, wo) > 0.0 { Lobes::REFLECTION } else { Lobes::TRANSMISSION };
+                Some(BsdfSample {
+                    direction,
+                    weight: albedo.value(rec.u, rec.v, rec.p),
+                    pdf: 1.0 / (4.0 * PI),
+                    lobe: Lobes::DIFFUSE | lobe,
+                })
             }
+
+            Material::Conductor { .. } | Material::RoughDielectric { .. } => {
+                let (frame, lobe) = self.microfacet(rec)?;
+                let sample = lobe.sample(frame.to_local(wo), sampler)?;
+                Some(BsdfSample { direction: frame.local(sample.direction), ..sample })
+            }
+
+            Material::Principled(principled) => principled.sample(rec, wo, sampler),
+        }
+    }
+
+    /// The BSDF times |cos| of the angle between `wi` and the normal, for
+    /// light arriving from `wi` and leaving toward `wo` (both unit vectors).
+    /// Specular lobes contribute nothing here.
+    pub fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if let Some((frame, lobe)) = self.microfacet(rec) {
+            return lobe.eval(frame.to_local(wo), frame.to_local(wi));
+        }
+        match self {
+            Material::Lambertian { albedo } => {
+                let cosine = dot(wi, rec.normal);
+                if cosine > 0.0 {
+                    albedo.value(rec.u, rec.v, rec.p) * (cosine / PI)
+                } else {
+                    Color::new(0.0, 0.0, 0.0)
+                }
+            }
+            Material::Isotropic { albedo } => albedo.value(rec.u, rec.v, rec.p) / (4.0 * PI),
+            Material::Principled(principled) => principled.eval(rec, wo, wi),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Solid-angle density with which `sample` returns `wi`, ignoring
+    /// specular lobes.
+    pub fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f32 {
+        if let Some((frame, lobe)) = self.microfacet(rec) {
+            return lobe.pdf(frame.to_local(wo), frame.to_local(wi));
+        }
+        match self {
+            Material::Lambertian { .. } => (dot(wi, rec.normal) / PI).max(0.0),
+            Material::Isotropic { .. } => 1.0 / (4.0 * PI),
+            Material::Principled(principled) => principled.pdf(rec, wo, wi),
+            _ => 0.0,
+        }
+    }
+
+    /// Light given off at a surface point; black for everything but lights.
+    pub fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
+        match self {
+            Material::DiffuseLight { emit } => emit.value(u, v, p),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+
+/// The rough materials' lobes, which work in a local frame.
+enum Microfacet {
+    Conductor(RoughConductor),
+    Dielectric(RoughDielectric),
+}
+
+impl Microfacet {
+    fn sample(&self, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        match self {
+            Microfacet::Conductor(conductor) => conductor.sample(wo, sampler),
+            Microfacet::Dielectric(dielectric) => dielectric.sample(wo, sampler),
+        }
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        match self {
+            Microfacet::Conductor(conductor) => conductor.eval(wo, wi),
+            Microfacet::Dielectric(dielectric) => dielectric.eval(wo, wi),
+        }
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        match self {
+            Microfacet::Conductor(conductor) => conductor.pdf(wo, wi),
+            Microfacet::Dielectric(dielectric) => dielectric.pdf(wo, wi),
         }
     }
 }
@@ -88,3 +325,178 @@ fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
     r0 *= r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    /// A ray that reaches `record`'s point heading against `wo`.
+    fn arriving(wo: Vec3) -> Ray {
+        Ray::new(Point3::new(0.0, 0.0, 0.0) + wo, -wo, 0.0)
+    }
+
+    fn record(material: &Material) -> HitRecord<'_> {
+        HitRecord {
+            p: Point3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material,
+        }
+    }
+
+    #[test]
+    fn lambertian_samples_match_eval_and_pdf() {
+        let material = Material::lambertian(Color::new(0.5, 0.6, 0.7));
+        let rec = record(&material);
+        let wo = unit_vector(Vec3::new(1.0, 0.0, 1.0));
+
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let sample = material.sample(&rec, arriving(wo), &mut sampler).unwrap();
+            assert!(dot(sample.direction, rec.normal) > 0.0);
+            assert!((material.pdf(&rec, wo, sample.direction) - sample.pdf).abs() < 1e-5);
+            let weight = material.eval(&rec, wo, sample.direction) / sample.pdf;
+            assert!((weight - sample.weight).length() < 1e-4);
+        }
+
+        // Nothing scatters through the surface
+        assert_eq!(material.pdf(&rec, wo, Vec3::new(0.0, 0.0, -1.0)), 0.0);
+        assert_eq!(material.eval(&rec, wo, Vec3::new(0.0, 0.0, -1.0)), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn lambertian_pdf_integrates_to_one() {
+        let material = Material::lambertian(Color::new(1.0, 1.0, 1.0));
+        let rec = record(&material);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+
+        // Uniform directions have density 1 / (4 pi)
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 100_000;
+        let total: f32 = (0..trials).map(|_| material.pdf(&rec, wo, random_unit_vector(&mut sampler))).sum();
+        assert!((total * 4.0 * PI / trials as f32 - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn mirrors_are_specular() {
+        let material = Material::metal(Color::new(0.9, 0.8, 0.7), 0.0);
+        let rec = record(&material);
+        let wo = unit_vector(Vec3::new(1.0, 0.0, 1.0));
+
+        let sample = material.sample(&rec, arriving(wo), &mut IndependentSampler::new(0)).unwrap();
+        assert!((sample.direction - unit_vector(Vec3::new(-1.0, 0.0, 1.0))).length() < 1e-6);
+        assert_eq!(sample.weight, Color::new(0.9, 0.8, 0.7));
+        assert!(sample.lobe.contains(Lobes::SPECULAR | Lobes::REFLECTION));
+        assert!(!material.lobes().has_smooth());
+        assert_eq!(material.pdf(&rec, wo, sample.direction), 0.0);
+    }
+
+    #[test]
+    fn glass_reflects_a_few_percent_head_on() {
+        let material = Material::dielectric(1.5);
+        let rec = record(&material);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 20000;
+        let mut reflected = 0;
+        for _ in 0..trials {
+            let sample = material.sample(&rec, arriving(wo), &mut sampler).unwrap();
+            assert_eq!(sample.weight, Color::new(1.0, 1.0, 1.0));
+            if sample.lobe.contains(Lobes::REFLECTION) {
+                assert!((sample.direction - wo).length() < 1e-6);
+                reflected += 1;
+            } else {
+                assert!(sample.lobe.contains(Lobes::SPECULAR | Lobes::TRANSMISSION));
+                assert!((sample.direction + wo).length() < 1e-6);
+            }
+        }
+        // ((1.5 - 1) / (1.5 + 1))^2
+        assert!((reflected as f32 / trials as f32 - 0.04).abs() < 0.01);
+    }
+
+    #[test]
+    fn colored_glass_absorbs_on_the_way_out() {
+        let material = Material::colored_glass(1.5, Color::new(0.5, 0.25, 1.0), 2.0);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let mut sampler = IndependentSampler::new(0);
+
+        // Entering is free
+        let entering = record(&material);
+        assert_eq!(material.sample(&entering, arriving(wo), &mut sampler).unwrap().weight, Color::new(1.0, 1.0, 1.0));
+
+        // After two units inside, the exit lets the color through, whether
+        // the ray refracts out or reflects back in
+        let exiting = HitRecord { t: 2.0, front_face: false, ..record(&material) };
+        for _ in 0..100 {
+            let weight = material.sample(&exiting, arriving(wo), &mut sampler).unwrap().weight;
+            assert!((weight - Color::new(0.5, 0.25, 1.0)).length() < 1e-5);
+        }
+
+        // and twice the distance squares it
+        let deeper = HitRecord { t: 4.0, ..exiting };
+        let weight = material.sample(&deeper, arriving(wo), &mut sampler).unwrap().weight;
+        assert!((weight - Color::new(0.25, 0.0625, 1.0)).length() < 1e-5);
+
+        // as does a ray twice as long covering the same t
+        let weight = material.sample(&exiting, arriving(2.0 * wo), &mut sampler).unwrap().weight;
+        assert!((weight - Color::new(0.25, 0.0625, 1.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn dispersive_glass_bends_blue_more_than_red() {
+        let material = Material::dielectric(Ior::DIAMOND);
+        let rec = record(&material);
+        let wo = unit_vector(Vec3::new(1.0, 0.0, 1.0));
+
+        // The angle of the refracted ray away from the inward normal
+        let mut sampler = IndependentSampler::new(0);
+        let mut refracted_sine = |wavelength: Option<f32>| loop {
+            let ray = match wavelength {
+                Some(wavelength) => arriving(wo).with_wavelength(wavelength),
+                None => arriving(wo),
+            };
+            let sample = material.sample(&rec, ray, &mut sampler).unwrap();
+            if sample.lobe.contains(Lobes::TRANSMISSION) {
+                break sample.direction.x().abs();
+            }
+        };
+        let (blue, red) = (refracted_sine(Some(450.0)), refracted_sine(Some(650.0)));
+        assert!(blue < red);
+        assert!((blue * Ior::DIAMOND.at(Some(450.0)) - wo.x()).abs() < 1e-5);
+        assert!((red * Ior::DIAMOND.at(Some(650.0)) - wo.x()).abs() < 1e-5);
+
+        // Without a wavelength the d-line index applies
+        let plain = refracted_sine(None);
+        assert!((plain * Ior::DIAMOND.at(None) - wo.x()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn isotropic_samples_carry_the_advertised_lobes() {
+        let material = Material::isotropic(Color::new(0.5, 0.5, 0.5));
+        let rec = record(&material);
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let sample = material.sample(&rec, arriving(wo), &mut sampler).unwrap();
+            assert!(material.lobes().contains(sample.lobe));
+            let lobe = if dot(sample.direction, wo) > 0.0 { Lobes::REFLECTION } else { Lobes::TRANSMISSION };
+            assert_eq!(sample.lobe, Lobes::DIFFUSE | lobe);
+        }
+    }
+
+    #[test]
+    fn lights_absorb() {
+        let material = Material::diffuse_light(Color::new(1.0, 1.0, 1.0));
+        let rec = record(&material);
+
+        assert!(material.sample(&rec, arriving(Vec3::new(0.0, 0.0, 1.0)), &mut IndependentSampler::new(0)).is_none());
+        assert_eq!(material.lobes(), Lobes::NONE);
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
+use crate::sampler::Sampler;
+use crate::utility::INFINITY;
+use crate::vec3::{Color, Vec3};
+
+/// A volume of uniform density filling a closed, convex boundary. Rays
+/// passing through it scatter at an exponentially distributed distance, so
+/// thicker or denser regions look more opaque. The distance is drawn from the
+/// path's sampler, so it is `sample_hit` that scatters; `hit` only finds the
+/// boundary.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f32,
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f32, albedo: Arc<dyn Texture>) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Material::Isotropic { albedo },
+        }
+    }
+
+    pub fn from_color(boundary: Arc<dyn Hittable>, density: f32, albedo: Color) -> Self {
+        ConstantMedium::new(boundary, density, Arc::new(SolidColor::new(albedo)))
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let boundary = self.boundary.hit(ray, t_min, t_max)?;
+        Some(HitRecord { material: &self.phase_function, ..boundary })
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        // Find where the ray's line enters and leaves the boundary, even if
+        // the entry lies behind the origin (the ray starts inside).
+        let entry = self.boundary.hit(ray, -INFINITY, INFINITY)?;
+        let exit = self.boundary.hit(ray, entry.t + 0.0001, INFINITY)?;
+
+        let enter_t = entry.t.max(t_min).max(0.0);
+        let exit_t = exit.t.min(t_max);
+        if enter_t >= exit_t {
+            return None;
+        }
+
+        let ray_length = ray.direction().length();
+        let distance_inside = (exit_t - enter_t) * ray_length;
+        let hit_distance = self.neg_inv_density * (1.0 - sampler.next_1d()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = enter_t + hit_distance / ray_length;
+        Some(HitRecord {
+            p: ray.at(t),
+            // A volume has no surface; these are never used for shading
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material: &self.phase_function,
+        })
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        self.boundary.bounding_box(time0, time1)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Material::lambertian(Color::new(1.0, 1.0, 1.0))))
+    }
+
+    fn transmission(medium: &ConstantMedium, ray: Ray) -> f32 {
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 20000;
+        let passed = (0..trials)
+            .filter(|_| medium.sample_hit(ray, 0.001, INFINITY, &mut sampler).is_none())
+            .count();
+        passed as f32 / trials as f32
+    }
+
+    #[test]
+    fn transmission_follows_beer_lambert() {
+        let medium = ConstantMedium::from_color(unit_sphere(), 0.5, Color::new(1.0, 1.0, 1.0));
+        // Through the center the path inside is 2 long, whatever the ray's speed.
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -3.0), 0.0);
+
+        assert!((transmission(&medium, ray) - (-1.0f32).exp()).abs() < 0.02);
+    }
+
+    #[test]
+    fn rays_starting_inside_scatter_before_leaving() {
+        let medium = ConstantMedium::from_color(unit_sphere(), 1.0, Color::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.5), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        // Only half a unit of medium lies ahead
+        assert!((transmission(&medium, ray) - (-0.5f32).exp()).abs() < 0.02);
+        let mut sampler = IndependentSampler::new(1);
+        for _ in 0..100 {
+            if let Some(hit) = medium.sample_hit(ray, 0.001, INFINITY, &mut sampler) {
+                assert!(hit.t >= 0.001 && hit.t <= 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn dense_media_scatter_at_the_boundary() {
+        let medium = ConstantMedium::from_color(unit_sphere(), 1e6, Color::new(0.2, 0.4, 0.6));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        let hit = medium.sample_hit(ray, 0.001, INFINITY, &mut sampler).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-3);
+        assert_eq!(hit.material.sample(&hit, ray, &mut sampler).unwrap().weight, Color::new(0.2, 0.4, 0.6));
+        // Nothing is hit if the medium lies beyond t_max
+        assert!(medium.sample_hit(ray, 0.001, 3.0, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn plain_hits_find_the_boundary() {
+        let medium = ConstantMedium::from_color(unit_sphere(), 1e-6, Color::new(0.2, 0.4, 0.6));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        // However thin the medium, `hit` reports where the ray enters it
+        let hit = medium.hit(ray, 0.001, INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-6);
+        assert!(matches!(hit.material, Material::Isotropic { .. }));
+        assert!(medium.sample_hit(ray, 0.001, INFINITY, &mut IndependentSampler::new(0)).is_none());
+    }
+}
@@ -0,0 +1,507 @@
+use crate::bsdf::{BsdfSample, Lobes};
+use crate::sampler::Sampler;
+use crate::utility::{PI, cross};
+use crate::vec3::{Color, Vec3, dot, unit_vector};
+
+/// A metal's complex index of refraction, `eta + i k`, sampled at the red,
+/// green and blue wavelengths.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+impl ComplexIor {
+    pub const GOLD: ComplexIor = ComplexIor {
+        eta: Color { elements: [0.143, 0.374, 1.442] },
+        k: Color { elements: [3.983, 2.385, 1.603] },
+    };
+    pub const COPPER: ComplexIor = ComplexIor {
+        eta: Color { elements: [0.200, 0.924, 1.102] },
+        k: Color { elements: [3.912, 2.452, 2.142] },
+    };
+    pub const ALUMINIUM: ComplexIor = ComplexIor {
+        eta: Color { elements: [1.657, 0.880, 0.521] },
+        k: Color { elements: [9.224, 6.270, 4.837] },
+    };
+
+    /// Looks up one of the presets: "gold", "copper" or "aluminium".
+    pub fn by_name(name: &str) -> Option<ComplexIor> {
+        match name {
+            "gold" => Some(ComplexIor::GOLD),
+            "copper" => Some(ComplexIor::COPPER),
+            "aluminium" | "aluminum" => Some(ComplexIor::ALUMINIUM),
+            _ => None,
+        }
+    }
+
+    /// Fraction of unpolarized light reflected at an angle with cosine
+    /// `cos_theta`, per channel.
+    pub fn reflectance(&self, cos_theta: f32) -> Color {
+        let channel = |i: usize| fresnel_conductor(cos_theta, self.eta.elements[i], self.k.elements[i]);
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+/// Exact Fresnel reflectance of a conductor for unpolarized light.
+fn fresnel_conductor(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta.clamp(0.0, 1.0) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Exact Fresnel reflectance of a dielectric interface for unpolarized light
+/// arriving at an angle with cosine `cos_i`, where `eta` is the index on the
+/// far side over the index on the near side. One under total internal
+/// reflection.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Refracts `wi` (pointing away from the surface) through the microfacet
+/// normal `n` on the same side, where `eta` is the index on the far side over
+/// the index on `wi`'s side. `None` under total internal reflection.
+pub fn refract(wi: Vec3, n: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = dot(n, wi);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wi / eta + (cos_i / eta - cos_t) * n)
+}
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals with Smith
+/// masking-shadowing. All directions are in a local frame whose z axis is
+/// the surface normal.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Ggx {
+    alpha: f32,
+}
+
+impl Ggx {
+    /// Perceptual roughness in [0, 1] is squared into the distribution's
+    /// width, which makes the slider feel roughly linear. Very small widths
+    /// are clamped to keep the math finite.
+    pub fn from_roughness(roughness: f32) -> Ggx {
+        Ggx { alpha: (roughness * roughness).max(1e-3) }
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Density of microfacet normals `h` per unit projected area.
+    pub fn d(&self, h: Vec3) -> f32 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denominator = h.z() * h.z() * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f32::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated fraction of microfacets visible from both directions.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density with which `sample_visible_normal(wo, ..)` returns `h`.
+    pub fn visible_normal_pdf(&self, wo: Vec3, h: Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * dot(wo, h).max(0.0) * self.d(h) / wo.z()
+    }
+
+    /// Draws a microfacet normal in proportion to how much of it `wo` sees
+    /// (Heitz 2018), from two uniform numbers in [0, 1).
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f32, u2: f32) -> Vec3 {
+        // Stretch to a hemisphere configuration
+        let vh = unit_vector(Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+
+        let length_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        // A point on the projected disk, squashed toward the visible half
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // Unstretch
+        unit_vector(Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)))
+    }
+
+    /// Mirrors `wo` off a sampled visible normal. `None` if the result
+    /// points below the surface.
+    pub fn sample_reflection(&self, wo: Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let (u1, u2) = sampler.next_2d();
+        let h = self.sample_visible_normal(wo, u1, u2);
+        let wi = reflect_about(wo, h);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+
+    /// The mirror-microfacet BSDF times the cosine of `wi`, before Fresnel.
+    pub fn reflection(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(wo + wi);
+        self.d(h) * self.g(wo, wi) / (4.0 * wo.z())
+    }
+
+    /// Density with which `sample_reflection(wo)` returns `wi`.
+    pub fn reflection_pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = unit_vector(wo + wi);
+        self.visible_normal_pdf(wo, h) / (4.0 * dot(wo, h))
+    }
+}
+
+
+/// Mirrors `wo` about the microfacet normal `h`.
+fn reflect_about(wo: Vec3, h: Vec3) -> Vec3 {
+    2.0 * dot(wo, h) * h - wo
+}
+
+/// A rough metal: GGX microfacets that each reflect like a perfect mirror,
+/// tinted by the conductor's Fresnel reflectance. Directions are local, with
+/// `wo` above the surface.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RoughConductor {
+    pub distribution: Ggx,
+    pub ior: ComplexIor,
+}
+
+impl RoughConductor {
+    pub fn sample(&self, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let wi = self.distribution.sample_reflection(wo, sampler)?;
+        let h = unit_vector(wo + wi);
+
+        // D and the cosines cancel against the visible normal pdf
+        let ggx = &self.distribution;
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.ior.reflectance(dot(wo, h)) * (ggx.g(wo, wi) / ggx.g1(wo)),
+            pdf: ggx.reflection_pdf(wo, wi),
+            lobe: Lobes::GLOSSY | Lobes::REFLECTION,
+        })
+    }
+
+    /// The BSDF times the cosine of `wi`.
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let value = self.distribution.reflection(wo, wi);
+        if value == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.ior.reflectance(dot(wo, unit_vector(wo + wi))) * value
+    }
+
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        self.distribution.reflection_pdf(wo, wi)
+    }
+}
+
+/// Frosted glass: GGX microfacets that each reflect or refract like a smooth
+/// interface (Walter et al. 2007). `eta` is the index below the surface over
+/// the index above it, where `wo` is. Like `Material::Dielectric`, this
+/// leaves out the 1 / eta^2 scaling of refracted radiance, which cancels
+/// for light passing in and back out of a closed object.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RoughDielectric {
+    pub distribution: Ggx,
+    pub eta: f32,
+}
+
+impl RoughDielectric {
+    pub fn sample(&self, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let (u1, u2) = sampler.next_2d();
+        let h = self.distribution.sample_visible_normal(wo, u1, u2);
+        let reflectance = fresnel_dielectric(dot(wo, h), self.eta);
+
+        // Choosing reflection with probability F cancels F out of the weight
+        let (wi, lobe) = if sampler.next_1d() < reflectance {
+            let wi = reflect_about(wo, h);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            (wi, Lobes::GLOSSY | Lobes::REFLECTION)
+        } else {
+            let wi = refract(wo, h, self.eta)?;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            (wi, Lobes::GLOSSY | Lobes::TRANSMISSION)
+        };
+
+        let ggx = &self.distribution;
+        let weight = ggx.g(wo, wi) / ggx.g1(wo);
+        Some(BsdfSample {
+            direction: wi,
+            weight: Color::new(weight, weight, weight),
+            pdf: self.pdf(wo, wi),
+            lobe,
+        })
+    }
+
+    /// The microfacet normal that takes `wo` to `wi`, facing up, and for
+    /// refraction the Jacobian term's denominator.
+    fn half_vector(&self, wo: Vec3, wi: Vec3) -> Option<(Vec3, f32)> {
+        if wi.z() > 0.0 {
+            return Some((unit_vector(wo + wi), 0.0));
+        }
+        let h = unit_vector(self.eta * wi + wo);
+        let h = if h.z() < 0.0 { -h } else { h };
+        // Only microfacets facing wo and turned away from wi can refract
+        if dot(h, wo) <= 0.0 || dot(h, wi) >= 0.0 {
+            return None;
+        }
+        let denominator = dot(wi, h) + dot(wo, h) / self.eta;
+        Some((h, denominator * denominator))
+    }
+
+    /// The BSDF times the absolute cosine of `wi`.
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let (h, denominator) = match self.half_vector(wo, wi) {
+            Some(half) => half,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let ggx = &self.distribution;
+        let reflectance = fresnel_dielectric(dot(wo, h), self.eta);
+
+        let value = if wi.z() > 0.0 {
+            reflectance * ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z())
+        } else {
+            (1.0 - reflectance) * ggx.d(h) * ggx.g(wo, wi) * dot(wi, h).abs() * dot(wo, h)
+                / (wo.z() * denominator)
+        };
+        Color::new(value, value, value)
+    }
+
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        let (h, denominator) = match self.half_vector(wo, wi) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let reflectance = fresnel_dielectric(dot(wo, h), self.eta);
+        let normal_pdf = self.distribution.visible_normal_pdf(wo, h);
+
+        if wi.z() > 0.0 {
+            reflectance * normal_pdf / (4.0 * dot(wo, h))
+        } else {
+            (1.0 - reflectance) * normal_pdf * dot(wi, h).abs() / denominator
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::random_unit_vector;
+
+    #[test]
+    fn distribution_projects_to_unit_area() {
+        // The integral of D(h) cos(theta_h) over the hemisphere is one.
+        for &roughness in [0.3, 0.6, 1.0].iter() {
+            let ggx = Ggx::from_roughness(roughness);
+            let mut sampler = IndependentSampler::new(0);
+            let trials = 200_000;
+            let total: f32 = (0..trials)
+                .map(|_| {
+                    let h = random_unit_vector(&mut sampler);
+                    ggx.d(h) * h.z().max(0.0)
+                })
+                .sum();
+            let integral = total * 4.0 * PI / trials as f32;
+            assert!((integral - 1.0).abs() < 0.05, "roughness {}: {}", roughness, integral);
+        }
+    }
+
+    #[test]
+    fn visible_normals_follow_their_pdf() {
+        let ggx = Ggx::from_roughness(0.7);
+        let wo = unit_vector(Vec3::new(0.6, 0.2, 0.5));
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 200_000;
+
+        // The pdf integrates to one, and the mean height of the normals it
+        // describes matches that of the sampled ones.
+        let (mut integral, mut expected_z) = (0.0, 0.0);
+        for _ in 0..trials {
+            let h = random_unit_vector(&mut sampler);
+            let pdf = ggx.visible_normal_pdf(wo, h);
+            integral += pdf;
+            expected_z += pdf * h.z();
+        }
+        let scale = 4.0 * PI / trials as f32;
+        assert!((integral * scale - 1.0).abs() < 0.03, "{}", integral * scale);
+
+        let mut sampled_z = 0.0;
+        for _ in 0..trials {
+            let (u1, u2) = sampler.next_2d();
+            let h = ggx.sample_visible_normal(wo, u1, u2);
+            assert!(h.z() > 0.0 && dot(wo, h) >= -1e-4);
+            sampled_z += h.z();
+        }
+        let (expected_z, sampled_z) = (expected_z * scale, sampled_z / trials as f32);
+        assert!((expected_z - sampled_z).abs() < 0.02, "{} != {}", expected_z, sampled_z);
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // Head-on glass reflects ((n - 1) / (n + 1))^2, and grazing light is
+        // reflected completely.
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert!(fresnel_dielectric(0.0, 1.5) > 0.999);
+        // Total internal reflection from inside glass
+        assert_eq!(fresnel_dielectric(0.3, 1.0 / 1.5), 1.0);
+
+        // Metals reflect most light head-on, and gold favors red over blue
+        let gold = ComplexIor::GOLD.reflectance(1.0);
+        assert!(gold.x() > 0.9 && gold.z() < gold.x());
+        let aluminium = ComplexIor::ALUMINIUM.reflectance(1.0);
+        assert!(aluminium.x() > 0.85 && aluminium.z() > 0.85);
+        assert!(ComplexIor::COPPER.reflectance(0.0).z() > 0.999);
+    }
+
+    #[test]
+    fn refraction_bends_toward_the_normal() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let wi = unit_vector(Vec3::new(1.0, 0.0, 1.0));
+
+        let wt = refract(wi, n, 1.5).unwrap();
+        assert!((wt.length() - 1.0).abs() < 1e-5);
+        // Snell: sin(theta_t) = sin(45 degrees) / 1.5
+        assert!((wt.x() + 0.5f32.sqrt() / 1.5).abs() < 1e-5);
+        assert!(wt.z() < 0.0);
+        assert!(refract(wi, n, 1.0 / 1.5).is_none());
+    }
+
+    /// Checks that a lobe's samples are weighted by eval / pdf, and that its
+    /// pdf integrates to the fraction of samples that aren't absorbed.
+    fn check_lobe(
+        sample: impl Fn(Vec3, &mut dyn Sampler) -> Option<BsdfSample>, eval: impl Fn(Vec3, Vec3) -> Color, pdf: impl Fn(Vec3, Vec3) -> f32,
+    ) {
+        let wo = unit_vector(Vec3::new(0.5, 0.1, 0.8));
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 200_000;
+
+        let mut scattered = 0;
+        for _ in 0..trials {
+            if let Some(sample) = sample(wo, &mut sampler) {
+                scattered += 1;
+                assert!((sample.pdf - pdf(wo, sample.direction)).abs() <= 1e-3 * sample.pdf);
+                let expected = eval(wo, sample.direction) / sample.pdf;
+                assert!((expected - sample.weight).length() < 1e-3 * (1.0 + expected.length()));
+            }
+        }
+
+        let total: f32 = (0..trials).map(|_| pdf(wo, random_unit_vector(&mut sampler))).sum();
+        let integral = total * 4.0 * PI / trials as f32;
+        let expected = scattered as f32 / trials as f32;
+        assert!((integral - expected).abs() < 0.05, "pdf integrates to {}, expected {}", integral, expected);
+    }
+
+    #[test]
+    fn rough_conductor_is_consistent() {
+        let gold = RoughConductor { distribution: Ggx::from_roughness(0.6), ior: ComplexIor::GOLD };
+        check_lobe(|wo, sampler| gold.sample(wo, sampler), |wo, wi| gold.eval(wo, wi), |wo, wi| gold.pdf(wo, wi));
+    }
+
+    #[test]
+    fn rough_dielectric_is_consistent() {
+        // From outside the glass and from inside it
+        for &eta in [1.5, 1.0 / 1.5].iter() {
+            let glass = RoughDielectric { distribution: Ggx::from_roughness(0.6), eta };
+            check_lobe(|wo, sampler| glass.sample(wo, sampler), |wo, wi| glass.eval(wo, wi), |wo, wi| glass.pdf(wo, wi));
+        }
+    }
+
+    #[test]
+    fn rough_lobes_lose_little_energy() {
+        let wo = unit_vector(Vec3::new(0.3, 0.0, 0.9));
+        let aluminium = RoughConductor { distribution: Ggx::from_roughness(0.3), ior: ComplexIor::ALUMINIUM };
+        let glass = RoughDielectric { distribution: Ggx::from_roughness(0.3), eta: 1.5 };
+
+        let mut sampler = IndependentSampler::new(0);
+        let trials = 20000;
+        let mut reflected = Color::new(0.0, 0.0, 0.0);
+        let mut passed = 0.0;
+        for _ in 0..trials {
+            if let Some(sample) = aluminium.sample(wo, &mut sampler) {
+                reflected += sample.weight;
+            }
+            if let Some(sample) = glass.sample(wo, &mut sampler) {
+                passed += sample.weight.x();
+            }
+        }
+        // Single-scattering microfacets lose a little light at this roughness
+        let reflected = reflected / trials as f32;
+        assert!(reflected.x() < 1.0 && reflected.x() > 0.85, "{:?}", reflected);
+        let passed = passed / trials as f32;
+        assert!(passed <= 1.0 && passed > 0.9, "{}", passed);
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::hittable::{Hittable, HittableList};
+use crate::material::Material;
+use crate::triangle::TriangleMesh;
+use crate::vec3::{Color, Point3, Vec3, luminance};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
+    NoFaces {
+        path: Option<PathBuf>,
+    },
+}
+
+impl ObjError {
+    fn parse(line: usize, message: impl Into<String>) -> ObjError {
+        ObjError::Parse { path: None, line, message: message.into() }
+    }
+
+    fn in_file(self, file: &Path) -> ObjError {
+        match self {
+            ObjError::Parse { path: None, line, message } => {
+                ObjError::Parse { path: Some(file.to_path_buf()), line, message }
+            }
+            ObjError::NoFaces { path: None } => ObjError::NoFaces { path: Some(file.to_path_buf()) },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse { path: Some(path), line, message } => {
+                write!(f, "{}:{}: {}", path.display(), line, message)
+            }
+            ObjError::Parse { path: None, line, message } => write!(f, "line {}: {}", line, message),
+            ObjError::NoFaces { path: Some(path) } => write!(f, "{}: no faces", path.display()),
+            ObjError::NoFaces { path: None } => write!(f, "no faces"),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } | ObjError::NoFaces { .. } => None,
+        }
+    }
+}
+
+/// Loads an OBJ file together with every MTL library it references (resolved
+/// relative to the OBJ file). Faces without a `usemtl`, or naming a material
+/// no library defines, get `default_material`.
+pub fn load_obj(path: &Path, default_material: Material) -> Result<HittableList, ObjError> {
+    let source = read(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut tokens = strip_comment(line).split_whitespace();
+        if tokens.next() != Some("mtllib") {
+            continue;
+        }
+
+        let names: Vec<&str> = tokens.collect();
+        if names.is_empty() {
+            return Err(ObjError::parse(index + 1, "mtllib needs a file name").in_file(path));
+        }
+        for name in names {
+            let mtl_path = directory.join(name);
+            let library = parse_mtl(&read(&mtl_path)?).map_err(|e| e.in_file(&mtl_path))?;
+            materials.extend(library);
+        }
+    }
+
+    parse_obj(&source, &materials, default_material).map_err(|e| e.in_file(path))
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io { path: path.to_path_buf(), error })
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+fn parse_floats(line: usize, tokens: &[&str], min: usize, max: usize) -> Result<Vec<f32>, ObjError> {
+    if tokens.len() < min || tokens.len() > max {
+        return Err(ObjError::parse(
+            line,
+            format!("expected {} to {} numbers, found {}", min, max, tokens.len()),
+        ));
+    }
+    tokens
+        .iter()
+        .map(|token| {
+            token
+                .parse::<f32>()
+                .map_err(|_| ObjError::parse(line, format!("invalid number '{}'", token)))
+        })
+        .collect()
+}
+
+/// Turns a 1-based (or negative, relative to the end) OBJ index into a
+/// 0-based index into a list of `count` elements.
+fn resolve_index(line: usize, token: &str, count: usize) -> Result<usize, ObjError> {
+    let index: i64 = token
+        .parse()
+        .map_err(|_| ObjError::parse(line, format!("invalid index '{}'", token)))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else if index < 0 {
+        count as i64 + index
+    } else {
+        return Err(ObjError::parse(line, "index 0 is not allowed"));
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::parse(line, format!("index {} out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+type Corner = (usize, Option<usize>, Option<usize>);
+
+/// Faces sharing a material and the same set of vertex attributes end up in
+/// the same mesh, so a face without normals never forces flat shading onto
+/// faces that have them.
+#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+struct GroupKey {
+    material: Option<usize>,
+    has_uvs: bool,
+    has_normals: bool,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+    indices: Vec<[usize; 3]>,
+    vertex_lookup: HashMap<Corner, usize>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, corner: Corner, positions: &[Point3], uvs: &[(f32, f32)], normals: &[Vec3]) -> usize {
+        if let Some(&index) = self.vertex_lookup.get(&corner) {
+            return index;
+        }
+
+        let (position, uv, normal) = corner;
+        self.positions.push(positions[position]);
+        if let Some(uv) = uv {
+            self.uvs.push(uvs[uv]);
+        }
+        if let Some(normal) = normal {
+            self.normals.push(normals[normal]);
+        }
+
+        let index = self.positions.len() - 1;
+        self.vertex_lookup.insert(corner, index);
+        index
+    }
+}
+
+/// Parses OBJ source into one `TriangleMesh` per material. Polygons are fan
+/// triangulated; faces missing normals are flat shaded.
+pub fn parse_obj(
+    source: &str, materials: &HashMap<String, Material>, default_material: Material,
+) -> Result<HittableList, ObjError> {
+    let mut positions: Vec<Point3> = vec![];
+    let mut uvs: Vec<(f32, f32)> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+
+    let mut material_names: Vec<&str> = vec![];
+    let mut current_material: Option<usize> = None;
+
+    // Each group remembers the line of its first face, for error messages.
+    let mut group_order: Vec<(GroupKey, usize)> = vec![];
+    let mut groups: HashMap<GroupKey, MeshBuilder> = HashMap::new();
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens: Vec<&str> = strip_comment(raw_line).split_whitespace().collect();
+        let (keyword, arguments) = match tokens.split_first() {
+            Some((keyword, arguments)) => (*keyword, arguments),
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let values = parse_floats(line, arguments, 3, 4)?;
+                positions.push(Point3::new(values[0], values[1], values[2]));
+            }
+            "vt" => {
+                let values = parse_floats(line, arguments, 1, 3)?;
+                uvs.push((values[0], values.get(1).copied().unwrap_or(0.0)));
+            }
+            "vn" => {
+                let values = parse_floats(line, arguments, 3, 3)?;
+                normals.push(Vec3::new(values[0], values[1], values[2]));
+            }
+            "usemtl" => {
+                let name = arguments
+                    .first()
+                    .ok_or_else(|| ObjError::parse(line, "usemtl needs a material name"))?;
+                if !materials.contains_key(*name) {
+                    eprintln!("Warning: line {}: unknown material '{}', using the default", line, name);
+                    current_material = None;
+                    continue;
+                }
+                current_material = match material_names.iter().position(|known| known == name) {
+                    Some(position) => Some(position),
+                    None => {
+                        material_names.push(name);
+                        Some(material_names.len() - 1)
+                    }
+                };
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(ObjError::parse(line, "a face needs at least three vertices"));
+                }
+
+                let mut corners: Vec<Corner> = vec![];
+                for argument in arguments {
+                    let parts: Vec<&str> = argument.split('/').collect();
+                    if parts.len() > 3 {
+                        return Err(ObjError::parse(line, format!("invalid face vertex '{}'", argument)));
+                    }
+
+                    let position = resolve_index(line, parts[0], positions.len())?;
+                    let uv = match parts.get(1) {
+                        Some(part) if !part.is_empty() => Some(resolve_index(line, part, uvs.len())?),
+                        _ => None,
+                    };
+                    let normal = match parts.get(2) {
+                        Some(part) if !part.is_empty() => Some(resolve_index(line, part, normals.len())?),
+                        _ => None,
+                    };
+                    corners.push((position, uv, normal));
+                }
+
+                let key = GroupKey {
+                    material: current_material,
+                    has_uvs: corners.iter().all(|corner| corner.1.is_some()),
+                    has_normals: corners.iter().all(|corner| corner.2.is_some()),
+                };
+                let corners: Vec<Corner> = corners
+                    .into_iter()
+                    .map(|(position, uv, normal)| {
+                        (
+                            position,
+                            if key.has_uvs { uv } else { None },
+                            if key.has_normals { normal } else { None },
+                        )
+                    })
+                    .collect();
+
+                if !groups.contains_key(&key) {
+                    group_order.push((key, line));
+                }
+                let builder = groups.entry(key).or_default();
+
+                let first = builder.vertex(corners[0], &positions, &uvs, &normals);
+                for pair in corners[1..].windows(2) {
+                    let second = builder.vertex(pair[0], &positions, &uvs, &normals);
+                    let third = builder.vertex(pair[1], &positions, &uvs, &normals);
+                    builder.indices.push([first, second, third]);
+                }
+            }
+            // Objects, groups, smoothing groups and library references
+            // (handled by load_obj) don't change the geometry we build.
+            _ => {}
+        }
+    }
+
+    if group_order.is_empty() {
+        return Err(ObjError::NoFaces { path: None });
+    }
+
+    let mut objects: Vec<Box<dyn Hittable>> = vec![];
+    for (key, line) in group_order {
+        let builder = groups.remove(&key).unwrap();
+        let material = match key.material {
+            Some(index) => materials[material_names[index]].clone(),
+            None => default_material.clone(),
+        };
+
+        let mesh = TriangleMesh::new(
+            builder.positions,
+            builder.indices,
+            if key.has_normals { Some(builder.normals) } else { None },
+            if key.has_uvs { Some(builder.uvs) } else { None },
+            material,
+        )
+        .map_err(|error| ObjError::parse(line, error.to_string()))?;
+        objects.push(Box::new(mesh));
+    }
+
+    Ok(HittableList::new(objects))
+}
+
+fn parse_color(line: usize, arguments: &[&str]) -> Result<Color, ObjError> {
+    let values = parse_floats(line, arguments, 1, 3)?;
+    if values.len() == 2 {
+        return Err(ObjError::parse(line, "expected 1 or 3 color components"));
+    }
+    if values.len() == 1 {
+        return Ok(Color::new(values[0], values[0], values[0]));
+    }
+    Ok(Color::new(values[0], values[1], values[2]))
+}
+
+struct MtlEntry {
+    diffuse: Color,
+    specular: Color,
+    shininess: f32,
+    dissolve: f32,
+    index_of_refraction: f32,
+    illum: u32,
+}
+
+impl MtlEntry {
+    fn new() -> MtlEntry {
+        MtlEntry {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            dissolve: 1.0,
+            index_of_refraction: 1.5,
+            illum: 2,
+        }
+    }
+
+    /// Transparent entries (dissolve below one or one of the refraction
+    /// illumination models) become glass, entries whose specular color
+    /// outweighs the diffuse one become metal, the rest are diffuse.
+    fn to_material(&self) -> Material {
+        let refracts = matches!(self.illum, 4 | 6 | 7 | 9);
+        if self.dissolve < 1.0 || refracts {
+            return Material::dielectric(self.index_of_refraction);
+        }
+
+        if luminance(self.specular) > luminance(self.diffuse) {
+            // Blinn-Phong exponent to an approximate roughness
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().min(1.0);
+            return Material::metal(self.specular, fuzz);
+        }
+
+        Material::lambertian(self.diffuse)
+    }
+}
+
+/// Parses MTL source into materials keyed by their `newmtl` name.
+pub fn parse_mtl(source: &str) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let tokens: Vec<&str> = strip_comment(raw_line).split_whitespace().collect();
+        let (keyword, arguments) = match tokens.split_first() {
+            Some((keyword, arguments)) => (*keyword, arguments),
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = arguments
+                .first()
+                .ok_or_else(|| ObjError::parse(line, "newmtl needs a material name"))?;
+            if let Some((name, entry)) = current.take() {
+                materials.insert(name, entry.to_material());
+            }
+            current = Some((name.to_string(), MtlEntry::new()));
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some((_, entry)) => entry,
+            None => return Err(ObjError::parse(line, format!("'{}' before any newmtl", keyword))),
+        };
+
+        match keyword {
+            "Kd" => entry.diffuse = parse_color(line, arguments)?,
+            "Ks" => entry.specular = parse_color(line, arguments)?,
+            "Ns" => entry.shininess = parse_floats(line, arguments, 1, 1)?[0],
+            "Ni" => entry.index_of_refraction = parse_floats(line, arguments, 1, 1)?[0],
+            "d" => entry.dissolve = parse_floats(line, arguments, 1, 1)?[0],
+            "Tr" => entry.dissolve = 1.0 - parse_floats(line, arguments, 1, 1)?[0],
+            "illum" => {
+                let token = arguments
+                    .first()
+                    .ok_or_else(|| ObjError::parse(line, "illum needs a value"))?;
+                entry.illum = token
+                    .parse()
+                    .map_err(|_| ObjError::parse(line, format!("invalid illumination model '{}'", token)))?;
+            }
+            // Ambient, emissive and texture maps have no counterpart in Material.
+            _ => {}
+        }
+    }
+
+    if let Some((name, entry)) = current {
+        materials.insert(name, entry.to_material());
+    }
+
+    Ok(materials)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::spectrum::Ior;
+    use crate::utility::INFINITY;
+
+    fn grey() -> Material {
+        Material::lambertian(Color::new(0.5, 0.5, 0.5))
+    }
+
+    fn down(x: f32, z: f32) -> Ray {
+        Ray::new(Point3::new(x, 1.0, z), Vec3::new(0.0, -1.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn quad_is_fan_triangulated() {
+        let source = "
+            v -1 0 -1
+            v 1 0 -1
+            v 1 0 1
+            v -1 0 1
+            f 1 2 3 4
+        ";
+        let list = parse_obj(source, &HashMap::new(), grey()).unwrap();
+
+        assert!(list.hit(down(-0.5, 0.5), 0.001, INFINITY).is_some());
+        assert!(list.hit(down(0.5, -0.5), 0.001, INFINITY).is_some());
+        assert!(list.hit(down(2.0, 0.0), 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn negative_indices_and_slashes() {
+        let source = "
+            v -1 0 -1
+            v 1 0 -1
+            v 0 0 1
+            vt 0 0
+            vt 1 0
+            vt 0.5 1
+            vn 0 1 0
+            f -3/-3/-1 -2/-2/-1 -1/-1/-1
+        ";
+        let list = parse_obj(source, &HashMap::new(), grey()).unwrap();
+        let hit = list.hit(down(0.0, 0.0), 0.001, INFINITY).unwrap();
+
+        assert_eq!(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!((hit.u - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn missing_normals_are_flat_shaded() {
+        let source = "
+            v -1 0 -1
+            v 0 0 1
+            v 1 0 -1
+            f 1//  2 3
+        ";
+        let list = parse_obj(source, &HashMap::new(), grey()).unwrap();
+        let hit = list.hit(down(0.0, 0.0), 0.001, INFINITY).unwrap();
+
+        assert!((hit.normal.y() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        let cases = [
+            ("v 0 0 0\nv 1 0 0\nf 1 2 3\n", 3),
+            ("v 0 0\n", 1),
+            ("v 0 0 0\n\nv 1 0 zero\n", 3),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n", 4),
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3),
+        ];
+
+        for (source, expected_line) in cases.iter() {
+            match parse_obj(source, &HashMap::new(), grey()) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(line, *expected_line, "{}", source),
+                _ => panic!("expected a parse error for {:?}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn mtl_maps_onto_materials() {
+        let source = "
+            newmtl clay
+            Kd 0.8 0.3 0.2
+            Ks 0.04 0.04 0.04
+
+            newmtl chrome
+            Kd 0.1 0.1 0.1
+            Ks 0.9 0.9 0.9
+            Ns 1000
+
+            newmtl glass
+            Kd 1 1 1
+            Ni 1.45
+            d 0.1
+        ";
+        let materials = parse_mtl(source).unwrap();
+
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        match &materials["clay"] {
+            Material::Lambertian { albedo } => assert_eq!(albedo.value(0.0, 0.0, origin), Color::new(0.8, 0.3, 0.2)),
+            _ => panic!("clay should be lambertian"),
+        }
+        match &materials["chrome"] {
+            Material::Metal { albedo, fuzz } => {
+                assert_eq!(albedo.value(0.0, 0.0, origin), Color::new(0.9, 0.9, 0.9));
+                assert!(*fuzz < 0.1);
+            }
+            _ => panic!("chrome should be metal"),
+        }
+        match &materials["glass"] {
+            Material::Dielectric { index_of_refraction, .. } => assert_eq!(*index_of_refraction, Ior::Constant(1.45)),
+            _ => panic!("glass should be dielectric"),
+        }
+    }
+
+    #[test]
+    fn mtl_errors_carry_line_numbers() {
+        match parse_mtl("Kd 1 1 1\n") {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 1),
+            _ => panic!("expected a parse error"),
+        }
+        match parse_mtl("newmtl a\nKd 1 1\n") {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn materials_split_meshes() {
+        let mut materials = HashMap::new();
+        materials.insert("red".to_string(), Material::lambertian(Color::new(1.0, 0.0, 0.0)));
+
+        let source = "
+            v -1 0 -1
+            v 0 0 1
+            v 1 0 -1
+            v 2 0 -1
+            v 2 0 1
+            f 1 2 3
+            usemtl red
+            f 3 2 5 4
+        ";
+        let list = parse_obj(source, &materials, grey()).unwrap();
+
+        let hit = list.hit(down(0.0, 0.0), 0.001, INFINITY).unwrap();
+        match hit.material {
+            Material::Lambertian { albedo } => assert_eq!(albedo.value(hit.u, hit.v, hit.p), Color::new(0.5, 0.5, 0.5)),
+            _ => panic!("expected the default material"),
+        }
+        let hit = list.hit(down(1.5, 0.0), 0.001, INFINITY).unwrap();
+        match hit.material {
+            Material::Lambertian { albedo } => assert_eq!(albedo.value(hit.u, hit.v, hit.p), Color::new(1.0, 0.0, 0.0)),
+            _ => panic!("expected the red material"),
+        }
+    }
+
+    #[test]
+    fn unknown_materials_fall_back_to_the_default() {
+        let source = "
+            v -1 0 -1
+            v 0 0 1
+            v 1 0 -1
+            usemtl missing
+            f 1 2 3
+        ";
+        let list = parse_obj(source, &HashMap::new(), grey()).unwrap();
+
+        let hit = list.hit(down(0.0, 0.0), 0.001, INFINITY).unwrap();
+        match hit.material {
+            Material::Lambertian { albedo } => assert_eq!(albedo.value(hit.u, hit.v, hit.p), Color::new(0.5, 0.5, 0.5)),
+            _ => panic!("expected the default material"),
+        }
+    }
+
+    #[test]
+    fn files_without_faces_are_rejected() {
+        for source in ["", "v 0 0 0\nv 1 0 0\nv 0 1 0\n"] {
+            assert!(matches!(parse_obj(source, &HashMap::new(), grey()), Err(ObjError::NoFaces { .. })));
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::utility::{cross, dot, unit_vector};
+use crate::vec3::Vec3;
+
+/// An orthonormal basis whose `w` axis follows a given direction, for
+/// turning directions sampled around +z into world space.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: Vec3) -> Onb {
+        let w = unit_vector(n);
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = unit_vector(cross(w, a));
+        let u = cross(w, v);
+        Onb { u, v, w }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    /// The world-space vector with coordinates `a` in this basis.
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    /// The coordinates of the world-space vector `a` in this basis; the
+    /// inverse of `local`.
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(dot(a, self.u), dot(a, self.v), dot(a, self.w))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for n in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(-0.3, 2.0, 0.5)].iter() {
+            let basis = Onb::from_w(*n);
+            assert!((basis.w() - unit_vector(*n)).length() < 1e-6);
+            assert!(dot(basis.u(), basis.v()).abs() < 1e-6);
+            assert!(dot(basis.u(), basis.w()).abs() < 1e-6);
+            assert!((basis.u().length() - 1.0).abs() < 1e-6);
+            assert!((basis.v().length() - 1.0).abs() < 1e-6);
+            assert!((basis.local(Vec3::new(0.0, 0.0, 2.0)) - 2.0 * basis.w()).length() < 1e-6);
+            let a = Vec3::new(0.3, -0.7, 1.1);
+            assert!((basis.local(basis.to_local(a)) - a).length() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::{self, Write};
+
+use half::f16;
+
+use crate::image::Image;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+
+const PIXEL_TYPE_HALF: i32 = 1;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+impl ExrPrecision {
+    fn bytes(&self) -> usize {
+        match self {
+            ExrPrecision::Half => 2,
+            ExrPrecision::Float => 4,
+        }
+    }
+}
+
+/// One RGB image stored in the file. The unnamed layer becomes the plain
+/// `R`, `G`, `B` channels; named layers become `name.R`, `name.G`, `name.B`.
+pub struct ExrLayer<'a> {
+    pub name: &'a str,
+    pub image: &'a Image,
+}
+
+struct Channel<'a> {
+    name: String,
+    image: &'a Image,
+    component: usize,
+}
+
+fn attribute<W: Write>(writer: &mut W, name: &str, kind: &str, value: &[u8]) -> io::Result<()> {
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(kind.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(&(value.len() as i32).to_le_bytes())?;
+    writer.write_all(value)
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+    [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+/// Writes an uncompressed scanline OpenEXR file holding the unclamped linear
+/// values of every layer. All layers must have the same size.
+pub fn write_exr<W: Write>(layers: &[ExrLayer], precision: ExrPrecision, writer: &mut W) -> io::Result<()> {
+    let first = layers
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "an EXR file needs at least one layer"))?;
+    let (width, height) = (first.image.width(), first.image.height());
+    if layers.iter().any(|layer| layer.image.width() != width || layer.image.height() != height) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "EXR layers must all have the same size"));
+    }
+
+    let mut channels: Vec<Channel> = vec![];
+    for layer in layers {
+        for (component, suffix) in ["R", "G", "B"].iter().enumerate() {
+            let name = if layer.name.is_empty() {
+                suffix.to_string()
+            } else {
+                format!("{}.{}", layer.name, suffix)
+            };
+            channels.push(Channel { name, image: layer.image, component });
+        }
+    }
+    // Readers expect the channel list, and the data within each scanline,
+    // in alphabetical order.
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    if channels.windows(2).any(|pair| pair[0].name == pair[1].name) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "EXR layer names must be unique"));
+    }
+
+    let pixel_type = match precision {
+        ExrPrecision::Half => PIXEL_TYPE_HALF,
+        ExrPrecision::Float => PIXEL_TYPE_FLOAT,
+    };
+    let mut channel_list = vec![];
+    for channel in channels.iter() {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&pixel_type.to_le_bytes());
+        // pLinear and three reserved bytes, then x and y sampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut header = vec![];
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    attribute(&mut header, "channels", "chlist", &channel_list)?;
+    attribute(&mut header, "compression", "compression", &[0])?;
+    attribute(&mut header, "dataWindow", "box2i", &box2i(width, height))?;
+    attribute(&mut header, "displayWindow", "box2i", &box2i(width, height))?;
+    attribute(&mut header, "lineOrder", "lineOrder", &[0])?;
+    attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes())?;
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8])?;
+    attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes())?;
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // One scanline per chunk: y coordinate, byte count, then the samples.
+    let line_bytes = width * channels.len() * precision.bytes();
+    let chunk_bytes = 8 + line_bytes;
+    let table_end = header.len() + 8 * height;
+    for y in 0..height {
+        let offset = (table_end + y * chunk_bytes) as u64;
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(line_bytes);
+    for y in 0..height {
+        line.clear();
+        for channel in channels.iter() {
+            for x in 0..width {
+                let value = channel.image.pixel(x, y).elements[channel.component];
+                match precision {
+                    ExrPrecision::Half => line.extend_from_slice(&f16::from_f32(value).to_le_bytes()),
+                    ExrPrecision::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(line_bytes as i32).to_le_bytes())?;
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use exr::prelude::{FlatSamples, ReadChannels, ReadLayers, read};
+
+    use crate::vec3::Color;
+
+    fn read_back(bytes: Vec<u8>) -> Vec<(String, FlatSamples)> {
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .all_layers()
+            .all_attributes()
+            .from_buffered(Cursor::new(bytes))
+            .unwrap();
+
+        let layer = &image.layer_data[0];
+        assert_eq!((layer.size.0, layer.size.1), (2, 1));
+        layer
+            .channel_data
+            .list
+            .iter()
+            .map(|channel| (channel.name.to_string(), channel.sample_data.clone()))
+            .collect()
+    }
+
+    fn radiance() -> Image {
+        Image::from_pixels(2, 1, vec![Color::new(12.5, 0.25, -1.0), Color::new(0.0, 1000.0, 3.0)])
+    }
+
+    #[test]
+    fn float_channels_keep_full_range() {
+        let mut bytes = vec![];
+        write_exr(&[ExrLayer { name: "", image: &radiance() }], ExrPrecision::Float, &mut bytes).unwrap();
+
+        let channels = read_back(bytes);
+        let names: Vec<&str> = channels.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["B", "G", "R"]);
+
+        match &channels[2].1 {
+            FlatSamples::F32(red) => assert_eq!(red, &vec![12.5, 0.0]),
+            _ => panic!("expected float samples"),
+        }
+        match &channels[1].1 {
+            FlatSamples::F32(green) => assert_eq!(green, &vec![0.25, 1000.0]),
+            _ => panic!("expected float samples"),
+        }
+    }
+
+    #[test]
+    fn half_channels_and_layers() {
+        let albedo = Image::from_pixels(2, 1, vec![Color::new(0.5, 0.5, 0.5), Color::new(1.0, 0.0, 0.0)]);
+        let layers = [
+            ExrLayer { name: "", image: &radiance() },
+            ExrLayer { name: "albedo", image: &albedo },
+        ];
+        let mut bytes = vec![];
+        write_exr(&layers, ExrPrecision::Half, &mut bytes).unwrap();
+
+        let channels = read_back(bytes);
+        let names: Vec<&str> = channels.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["B", "G", "R", "albedo.B", "albedo.G", "albedo.R"]);
+
+        match &channels[5].1 {
+            FlatSamples::F16(red) => assert_eq!(red, &vec![f16::from_f32(0.5), f16::from_f32(1.0)]),
+            _ => panic!("expected half samples"),
+        }
+        match &channels[2].1 {
+            FlatSamples::F16(red) => assert_eq!(red[0].to_f32(), 12.5),
+            _ => panic!("expected half samples"),
+        }
+    }
+
+    #[test]
+    fn mismatched_layers_are_rejected() {
+        let small = Image::new(1, 1);
+        let layers = [
+            ExrLayer { name: "", image: &radiance() },
+            ExrLayer { name: "small", image: &small },
+        ];
+
+        assert!(write_exr(&layers, ExrPrecision::Half, &mut vec![]).is_err());
+        assert!(write_exr(&[], ExrPrecision::Half, &mut vec![]).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::image::Image;
+use crate::openexr::{ExrLayer, ExrPrecision, write_exr};
+use crate::radiance::write_hdr;
+use crate::utility::clamp;
+use crate::vec3::Color;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ImageFormat {
+    /// Binary PPM (P6)
+    Ppm,
+    /// Plain-text PPM (P3)
+    PpmAscii,
+    Png,
+    /// Radiance RGBE
+    Hdr,
+    /// OpenEXR with half-float channels
+    Exr,
+    /// OpenEXR with 32-bit float channels
+    ExrFloat,
+}
+
+impl ImageFormat {
+    /// Guesses the format from a file extension. Plain-text PPM and float
+    /// EXR share their extensions with other formats, so they are never guessed.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Ppm | ImageFormat::PpmAscii => "ppm",
+            ImageFormat::Png => "png",
+            ImageFormat::Hdr => "hdr",
+            ImageFormat::Exr | ImageFormat::ExrFloat => "exr",
+        }
+    }
+}
+
+/// Gamma corrects (gamma 2) and quantizes one linear color to 8 bits.
+pub fn to_8bit(color: Color) -> [u8; 3] {
+    let ir = 255.99 * clamp(color.x().sqrt(), 0.0, 0.999);
+    let ig = 255.99 * clamp(color.y().sqrt(), 0.0, 0.999);
+    let ib = 255.99 * clamp(color.z().sqrt(), 0.0, 0.999);
+    [ir as u8, ig as u8, ib as u8]
+}
+
+/// Interleaved 8-bit RGB bytes for the whole image, top row first.
+pub fn to_rgb8(image: &Image) -> Vec<u8> {
+    image.pixels().iter().flat_map(|&color| to_8bit(color)).collect()
+}
+
+pub fn write_ppm<W: Write>(image: &Image, writer: &mut W) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    writer.write_all(&to_rgb8(image))
+}
+
+pub fn write_ppm_ascii<W: Write>(image: &Image, writer: &mut W) -> io::Result<()> {
+    write!(writer, "P3\n{} {}\n255\n", image.width(), image.height())?;
+    for &color in image.pixels() {
+        let [r, g, b] = to_8bit(color);
+        writeln!(writer, "{} {} {}", r, g, b)?;
+    }
+    Ok(())
+}
+
+pub fn write_png<W: Write>(image: &Image, writer: &mut W) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut png_writer = encoder.write_header().map_err(png_error)?;
+    png_writer.write_image_data(&to_rgb8(image)).map_err(png_error)?;
+    png_writer.finish().map_err(png_error)
+}
+
+fn png_error(error: png::EncodingError) -> io::Error {
+    match error {
+        png::EncodingError::IoError(error) => error,
+        other => io::Error::other(other),
+    }
+}
+
+pub fn write<W: Write>(image: &Image, format: ImageFormat, writer: &mut W) -> io::Result<()> {
+    match format {
+        ImageFormat::Ppm => write_ppm(image, writer),
+        ImageFormat::PpmAscii => write_ppm_ascii(image, writer),
+        ImageFormat::Png => write_png(image, writer),
+        ImageFormat::Hdr => write_hdr(image, writer),
+        ImageFormat::Exr => write_exr(&[ExrLayer { name: "", image }], ExrPrecision::Half, writer),
+        ImageFormat::ExrFloat => write_exr(&[ExrLayer { name: "", image }], ExrPrecision::Float, writer),
+    }
+}
+
+pub fn save(image: &Image, format: ImageFormat, path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(image, format, &mut writer)?;
+    writer.flush()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> Image {
+        Image::from_pixels(2, 1, vec![Color::new(0.25, 0.0, 4.0), Color::new(1.0, 1.0, 1.0)])
+    }
+
+    #[test]
+    fn quantization_applies_gamma_and_clamps() {
+        assert_eq!(to_8bit(Color::new(0.25, 0.0, 4.0)), [127, 0, 255]);
+        assert_eq!(to_8bit(Color::new(-1.0, 1.0, 0.0)), [0, 255, 0]);
+    }
+
+    #[test]
+    fn binary_ppm() {
+        let mut bytes = vec![];
+        write_ppm(&test_image(), &mut bytes).unwrap();
+
+        assert_eq!(&bytes[..11], b"P6\n2 1\n255\n");
+        assert_eq!(&bytes[11..], &[127, 0, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn ascii_ppm() {
+        let mut bytes = vec![];
+        write_ppm_ascii(&test_image(), &mut bytes).unwrap();
+
+        assert_eq!(String::from_utf8(bytes).unwrap(), "P3\n2 1\n255\n127 0 255\n255 255 255\n");
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut bytes = vec![];
+        write_png(&test_image(), &mut bytes).unwrap();
+
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(&buffer[..info.buffer_size()], &[127, 0, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn formats_from_extension() {
+        assert_eq!(ImageFormat::from_path(Path::new("a/out.PNG")), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path(Path::new("out.ppm")), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_path(Path::new("out.hdr")), Some(ImageFormat::Hdr));
+        assert_eq!(ImageFormat::from_path(Path::new("out.exr")), Some(ImageFormat::Exr));
+        assert_eq!(ImageFormat::from_path(Path::new("out.txt")), None);
+        assert_eq!(ImageFormat::from_path(Path::new("out")), None);
+    }
+}
@@ -0,0 +1,277 @@
+use std::sync::Arc;
+
+use crate::camera::CameraSettings;
+use crate::environment::Environment;
+use crate::hittable::{Hittable, HittableList};
+use crate::instance::Instance;
+use crate::material::Material;
+use crate::medium::ConstantMedium;
+use crate::quad::BoxShape;
+use crate::rect::{XyRect, XzRect, YzRect};
+use crate::renderer::RenderSettings;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::scene::Scene;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, NoiseStyle, NoiseTexture};
+use crate::transform::Transform;
+use crate::vec3::{Color, Point3, Vec3};
+
+/// Names accepted by `by_name`.
+pub const NAMES: &[&str] = &["random", "simple_light", "textures", "cornell_box", "cornell_smoke"];
+
+pub fn by_name(name: &str) -> Option<Scene> {
+    match name {
+        "random" => Some(random_spheres()),
+        "simple_light" => Some(simple_light()),
+        "textures" => Some(textures()),
+        "cornell_box" => Some(cornell_box()),
+        "cornell_smoke" => Some(cornell_smoke()),
+        _ => None,
+    }
+}
+
+/// The final scene of "Ray Tracing in One Weekend" with bouncing diffuse
+/// balls. The layout comes from a fixed seed, so it is the same every run.
+pub fn random_spheres() -> Scene {
+    let mut sampler = IndependentSampler::new(0);
+    let material_ground = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+
+    let mut objects: Vec<Box<dyn Hittable>> = vec![];
+
+    objects.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, material_ground)));
+
+    for i in -11..11 {
+        for b in -11..11 {
+            let choose_mat = sampler.next_1d();
+            let center: Point3 = Point3::new(
+                i as f32 + 0.9*sampler.next_1d(),
+                0.2,
+                b as f32 + 0.9*sampler.next_1d()
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let albedo: Color;
+                let fuzz: f32;
+
+                if choose_mat < 0.8 {
+                    // diffuse
+                    albedo = Color::random(&mut sampler) * Color::random(&mut sampler);
+                    let sphere_material = Material::lambertian(albedo);
+                    let center2 = center + Vec3::new(0.0, sampler.range(0.0, 0.5), 0.0);
+
+                    objects.push(Box::new(
+                        MovingSphere::new(center, center2, 0.0, 1.0, 0.2, sphere_material)
+                    ));
+                }
+                else if choose_mat < 0.95 {
+                    // metal
+                    albedo = Color::random_range(&mut sampler, 0.5, 1.0);
+                    fuzz = sampler.next_1d();
+                    let sphere_material = Material::metal(albedo, fuzz);
+                    objects.push(Box::new(
+                        Sphere::new(center, 0.2, sphere_material)
+                    ));
+                }
+                else {
+                    // glass
+                    let sphere_material = Material::dielectric(1.5);
+                    objects.push(Box::new(
+                        Sphere::new(center, 0.2, sphere_material)
+                    ));
+                }
+            }
+        }
+    }
+
+    let material_1 = Material::dielectric(1.5);
+    let material_2 = Material::lambertian(Color::new(0.4, 0.2, 0.1));
+    let material_3 = Material::metal(Color::new(0.7, 0.6, 0.5), 0.0);
+
+    objects.push(Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material_1)));
+    objects.push(Box::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material_2)));
+    objects.push(Box::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material_3)));
+
+    let settings = RenderSettings::default();
+    let camera_settings = CameraSettings {
+        look_from: Point3::new(13.0, 2.0, 3.0),
+        look_at: Point3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.1,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+    };
+
+    Scene {
+        world: HittableList::new(objects),
+        camera_settings,
+        settings,
+        environment: Environment::SKY,
+        lights: HittableList::new(vec![]),
+    }
+}
+
+/// Two spheres lit only by a glowing sphere above them, against black.
+pub fn simple_light() -> Scene {
+    let ground = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+    let ball = Material::lambertian(Color::new(0.7, 0.3, 0.2));
+    let light: Arc<dyn Hittable> = Arc::new(Sphere::new(
+        Point3::new(0.0, 7.0, 0.0), 2.0, Material::diffuse_light(Color::new(4.0, 4.0, 4.0)),
+    ));
+
+    let objects: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)),
+        Box::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, ball)),
+        Box::new(light.clone()),
+    ];
+
+    let settings = RenderSettings {
+        samples_per_pixel: 400,
+        ..RenderSettings::default()
+    };
+    let camera_settings = CameraSettings {
+        look_from: Point3::new(26.0, 3.0, 6.0),
+        look_at: Point3::new(0.0, 2.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.0,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+    };
+
+    Scene {
+        world: HittableList::new(objects),
+        camera_settings,
+        settings,
+        environment: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+        lights: HittableList::new(vec![Box::new(light)]),
+    }
+}
+
+/// A checkered ground with marble and turbulent noise spheres.
+pub fn textures() -> Scene {
+    let checker = CheckerTexture::from_colors(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9), 1.0);
+    let ground = Material::Lambertian { albedo: Arc::new(checker) };
+    let marble = Material::Lambertian {
+        albedo: Arc::new(NoiseTexture::new(Color::new(1.0, 1.0, 1.0), 4.0, NoiseStyle::Marble)),
+    };
+    let smoke = Material::Metal {
+        albedo: Arc::new(NoiseTexture::new(Color::new(0.9, 0.6, 0.3), 2.0, NoiseStyle::Turbulence)),
+        fuzz: 0.3,
+    };
+
+    let objects: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground)),
+        Box::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, marble)),
+        Box::new(Sphere::new(Point3::new(0.0, 1.0, 4.0), 1.0, smoke)),
+    ];
+
+    let settings = RenderSettings::default();
+    let camera_settings = CameraSettings {
+        look_from: Point3::new(13.0, 2.0, 3.0),
+        look_at: Point3::new(0.0, 1.5, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 30.0,
+        aperture: 0.0,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+    };
+
+    Scene {
+        world: HittableList::new(objects),
+        camera_settings,
+        settings,
+        environment: Environment::SKY,
+        lights: HittableList::new(vec![]),
+    }
+}
+
+/// The empty Cornell box: red and green side walls, a white floor, ceiling and
+/// back wall, and a ceiling light.
+fn cornell_walls(light: Box<dyn Hittable>) -> Vec<Box<dyn Hittable>> {
+    let red = Material::lambertian(Color::new(0.65, 0.05, 0.05));
+    let white = Material::lambertian(Color::new(0.73, 0.73, 0.73));
+    let green = Material::lambertian(Color::new(0.12, 0.45, 0.15));
+
+    vec![
+        Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)),
+        Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)),
+        light,
+        Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, white.clone())),
+        Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white.clone())),
+        Box::new(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white)),
+    ]
+}
+
+/// The two rotated blocks of the Cornell box, tall one first.
+fn cornell_blocks(material: Material) -> [Instance; 2] {
+    [
+        Instance::new(
+            Arc::new(BoxShape::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), material.clone())),
+            Transform::rotate_y(15.0).then(&Transform::translate(Vec3::new(265.0, 0.0, 295.0))),
+        ),
+        Instance::new(
+            Arc::new(BoxShape::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), material)),
+            Transform::rotate_y(-18.0).then(&Transform::translate(Vec3::new(130.0, 0.0, 65.0))),
+        ),
+    ]
+}
+
+/// The camera and settings every Cornell box variant shares, with `light`
+/// (already among `objects`) sampled directly.
+fn cornell_scene(objects: Vec<Box<dyn Hittable>>, light: Arc<dyn Hittable>) -> Scene {
+    let settings = RenderSettings {
+        image_width: 600,
+        aspect_ratio: 1.0,
+        samples_per_pixel: 200,
+        max_depth: 50,
+        ..RenderSettings::default()
+    };
+    let camera_settings = CameraSettings {
+        look_from: Point3::new(278.0, 278.0, -800.0),
+        look_at: Point3::new(278.0, 278.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 40.0,
+        aperture: 0.0,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+    };
+
+    Scene {
+        world: HittableList::new(objects),
+        camera_settings,
+        settings,
+        environment: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+        lights: HittableList::new(vec![Box::new(light)]),
+    }
+}
+
+/// The Cornell box: red and green side walls, a square ceiling light and two
+/// rotated white blocks.
+pub fn cornell_box() -> Scene {
+    let light: Arc<dyn Hittable> = Arc::new(XzRect::new(
+        213.0, 343.0, 227.0, 332.0, 554.0, Material::diffuse_light(Color::new(15.0, 15.0, 15.0)),
+    ));
+    let mut objects = cornell_walls(Box::new(light.clone()));
+    for block in cornell_blocks(Material::lambertian(Color::new(0.73, 0.73, 0.73))) {
+        objects.push(Box::new(block));
+    }
+    cornell_scene(objects, light)
+}
+
+/// The Cornell box with its blocks replaced by black and white smoke, under
+/// a larger, dimmer light.
+pub fn cornell_smoke() -> Scene {
+    let light: Arc<dyn Hittable> = Arc::new(XzRect::new(
+        113.0, 443.0, 127.0, 432.0, 554.0, Material::diffuse_light(Color::new(7.0, 7.0, 7.0)),
+    ));
+    let mut objects = cornell_walls(Box::new(light.clone()));
+    let [tall, short] = cornell_blocks(Material::lambertian(Color::new(0.73, 0.73, 0.73)));
+    objects.push(Box::new(ConstantMedium::from_color(Arc::new(tall), 0.01, Color::new(0.0, 0.0, 0.0))));
+    objects.push(Box::new(ConstantMedium::from_color(Arc::new(short), 0.01, Color::new(1.0, 1.0, 1.0))));
+    cornell_scene(objects, light)
+}
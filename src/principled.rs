@@ -0,0 +1,381 @@
+use std::sync::Arc;
+
+use crate::bsdf::{BsdfSample, Lobes};
+use crate::hittable::HitRecord;
+use crate::microfacet::{Ggx, RoughDielectric};
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::texture::{SolidColor, Texture};
+use crate::utility::PI;
+use crate::vec3::{Color, Vec3, dot, luminance, random_cosine_direction, unit_vector};
+
+/// Roughness of the clear coat, which is meant to look like varnish.
+const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+/// A Disney-style "principled" material: one set of artist-friendly sliders
+/// that covers plastics, metals, glass, varnished and cloth-like surfaces.
+/// Every parameter is a texture; the scalar ones read the average of the
+/// texture's channels, clamped to [0, 1].
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    /// Blends from a dielectric to a metal tinted by the base color
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Strength of the dielectric highlight; 0.5 is the usual 4% reflectance
+    pub specular: Arc<dyn Texture>,
+    /// A second, glossy white coat on top
+    pub clearcoat: Arc<dyn Texture>,
+    /// A soft white rim at grazing angles, as on cloth
+    pub sheen: Arc<dyn Texture>,
+    /// Blends the diffuse base into glass tinted by the base color
+    pub transmission: Arc<dyn Texture>,
+    pub index_of_refraction: f32,
+}
+
+fn constant(value: f32) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(Color::new(value, value, value)))
+}
+
+fn scalar(texture: &Arc<dyn Texture>, rec: &HitRecord) -> f32 {
+    let value = texture.value(rec.u, rec.v, rec.p);
+    ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0)
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick(f0: Color, cosine: f32) -> Color {
+    f0 + schlick_weight(cosine) * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+impl Principled {
+    /// A rough dielectric of the given color; change the other fields with
+    /// struct update syntax.
+    pub fn new(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            index_of_refraction: 1.5,
+        }
+    }
+
+    pub fn from_color(base_color: Color) -> Principled {
+        Principled::new(Arc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn lobes(&self) -> Lobes {
+        Lobes::DIFFUSE | Lobes::GLOSSY | Lobes::REFLECTION | Lobes::TRANSMISSION
+    }
+
+    /// Looks the textures up at `rec` and sets up the lobes there.
+    fn at(&self, rec: &HitRecord) -> Layers {
+        let base_color = self.base_color.value(rec.u, rec.v, rec.p);
+        let metallic = scalar(&self.metallic, rec);
+        let roughness = scalar(&self.roughness, rec);
+        let transmission = scalar(&self.transmission, rec);
+
+        let dielectric_f0 = 0.08 * scalar(&self.specular, rec);
+        Layers {
+            frame: Onb::from_w(rec.normal),
+            base_color,
+            roughness,
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            glass: (1.0 - metallic) * transmission,
+            specular_f0: (1.0 - metallic) * Color::new(dielectric_f0, dielectric_f0, dielectric_f0)
+                + metallic * base_color,
+            dielectric_f0,
+            clearcoat: scalar(&self.clearcoat, rec),
+            sheen: scalar(&self.sheen, rec),
+            distribution: Ggx::from_roughness(roughness),
+            glass_lobe: RoughDielectric {
+                distribution: Ggx::from_roughness(roughness),
+                eta: if rec.front_face { self.index_of_refraction } else { 1.0 / self.index_of_refraction },
+            },
+            clearcoat_distribution: Ggx::from_roughness(CLEARCOAT_ROUGHNESS),
+        }
+    }
+
+    pub fn sample(&self, rec: &HitRecord, wo: Vec3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let layers = self.at(rec);
+        let local_wo = layers.frame.to_local(wo);
+        if local_wo.z() <= 0.0 {
+            return None;
+        }
+
+        // Pick one lobe, then weigh the direction by the whole mixture
+        let probabilities = layers.probabilities(local_wo);
+        let mut choice = sampler.next_1d();
+        let mut chosen = Lobe::Diffuse;
+        for (lobe, probability) in LOBES.iter().zip(probabilities.iter()) {
+            if *probability > 0.0 {
+                chosen = *lobe;
+                if choice < *probability {
+                    break;
+                }
+                choice -= probability;
+            }
+        }
+
+        let (wi, lobe) = match chosen {
+            Lobe::Diffuse => (random_cosine_direction(sampler), Lobes::DIFFUSE | Lobes::REFLECTION),
+            Lobe::Specular => (layers.distribution.sample_reflection(local_wo, sampler)?, Lobes::GLOSSY | Lobes::REFLECTION),
+            Lobe::Clearcoat => {
+                (layers.clearcoat_distribution.sample_reflection(local_wo, sampler)?, Lobes::GLOSSY | Lobes::REFLECTION)
+            }
+            Lobe::Glass => {
+                let sample = layers.glass_lobe.sample(local_wo, sampler)?;
+                (sample.direction, sample.lobe)
+            }
+        };
+
+        let pdf = layers.pdf(local_wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            direction: layers.frame.local(wi),
+            weight: layers.eval(local_wo, wi) / pdf,
+            pdf,
+            lobe,
+        })
+    }
+
+    pub fn eval(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let layers = self.at(rec);
+        layers.eval(layers.frame.to_local(wo), layers.frame.to_local(wi))
+    }
+
+    pub fn pdf(&self, rec: &HitRecord, wo: Vec3, wi: Vec3) -> f32 {
+        let layers = self.at(rec);
+        layers.pdf(layers.frame.to_local(wo), layers.frame.to_local(wi))
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Glass,
+}
+
+const LOBES: [Lobe; 4] = [Lobe::Diffuse, Lobe::Specular, Lobe::Clearcoat, Lobe::Glass];
+
+/// A principled material's parameters at one point, in the local frame of
+/// the surface there.
+struct Layers {
+    frame: Onb,
+    base_color: Color,
+    roughness: f32,
+    /// How much of the base is diffuse and how much is glass; the rest is metal
+    diffuse: f32,
+    glass: f32,
+    specular_f0: Color,
+    dielectric_f0: f32,
+    clearcoat: f32,
+    sheen: f32,
+    distribution: Ggx,
+    glass_lobe: RoughDielectric,
+    clearcoat_distribution: Ggx,
+}
+
+impl Layers {
+    /// Light the clear coat reflects away before it reaches the base.
+    fn clearcoat_fresnel(&self, cosine: f32) -> f32 {
+        self.clearcoat * (0.04 + 0.96 * schlick_weight(cosine))
+    }
+
+    /// How likely each of `LOBES` is to be sampled, roughly in proportion to
+    /// how much light it reflects toward `wo`.
+    fn probabilities(&self, wo: Vec3) -> [f32; 4] {
+        let coat = self.clearcoat_fresnel(wo.z());
+        let under = 1.0 - coat;
+        let weights = [
+            under * self.diffuse * (luminance(self.base_color) + self.sheen).max(0.05),
+            under * (1.0 - self.glass) * luminance(schlick(self.specular_f0, wo.z())),
+            coat,
+            under * self.glass,
+        ];
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 {
+            return [1.0, 0.0, 0.0, 0.0];
+        }
+        [weights[0] / total, weights[1] / total, weights[2] / total, weights[3] / total]
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let mut value = Color::new(0.0, 0.0, 0.0);
+        if wo.z() <= 0.0 {
+            return value;
+        }
+        let under = 1.0 - self.clearcoat_fresnel(wo.z());
+
+        if wi.z() > 0.0 {
+            let h = unit_vector(wo + wi);
+            let cos_d = dot(wi, h);
+
+            // Disney's diffuse, with retro-reflection that grows with roughness,
+            // dimmed by what the dielectric highlight already reflected
+            if self.diffuse > 0.0 {
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z())) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+                let specular = self.dielectric_f0 + (1.0 - self.dielectric_f0) * schlick_weight(wo.z());
+                let diffuse = self.base_color * (retro / PI) + Color::new(1.0, 1.0, 1.0) * (self.sheen * schlick_weight(cos_d));
+                value += (under * self.diffuse * (1.0 - specular) * wi.z()) * diffuse;
+            }
+
+            let specular = self.distribution.reflection(wo, wi);
+            if specular > 0.0 && self.glass < 1.0 {
+                value += (under * (1.0 - self.glass) * specular) * schlick(self.specular_f0, cos_d);
+            }
+
+            let coat = self.clearcoat_distribution.reflection(wo, wi);
+            if coat > 0.0 {
+                value += Color::new(1.0, 1.0, 1.0) * (self.clearcoat_fresnel(cos_d) * coat);
+            }
+        }
+
+        if self.glass > 0.0 {
+            // Tint only what passes through the glass
+            let glass = self.glass_lobe.eval(wo, wi);
+            let tint = if wi.z() < 0.0 { self.base_color } else { Color::new(1.0, 1.0, 1.0) };
+            value += (under * self.glass) * glass * tint;
+        }
+        value
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        let [diffuse, specular, clearcoat, glass] = self.probabilities(wo);
+        let mut pdf = 0.0;
+        if wi.z() > 0.0 {
+            pdf += diffuse * wi.z() / PI;
+            pdf += specular * self.distribution.reflection_pdf(wo, wi);
+            pdf += clearcoat * self.clearcoat_distribution.reflection_pdf(wo, wi);
+        }
+        if glass > 0.0 {
+            pdf += glass * self.glass_lobe.pdf(wo, wi);
+        }
+        pdf
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::material::Material;
+    use crate::vec3::{Point3, random_unit_vector};
+
+    fn record(material: &Material) -> HitRecord<'_> {
+        HitRecord {
+            p: Point3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material,
+        }
+    }
+
+    #[test]
+    fn mixture_pdf_matches_lobe_selection() {
+        // Rough lobes only, and without the needle-sharp clear coat: a
+        // uniform estimate of the integral would hardly ever find the peaks.
+        let base = Principled { roughness: constant(0.7), ..Principled::from_color(Color::new(0.8, 0.4, 0.2)) };
+        let variants = [
+            base.clone(),
+            Principled { metallic: constant(1.0), ..base.clone() },
+            Principled { sheen: constant(0.5), roughness: constant(0.9), ..base.clone() },
+            Principled { transmission: constant(1.0), ..base.clone() },
+            Principled { metallic: constant(0.5), transmission: constant(0.5), ..base },
+        ];
+        let wo = unit_vector(Vec3::new(0.4, -0.2, 0.9));
+
+        for principled in variants.iter() {
+            let material = Material::principled(principled.clone());
+            let rec = record(&material);
+
+            let mut sampler = IndependentSampler::new(0);
+            let trials = 50_000;
+            let mut scattered = 0;
+            for _ in 0..trials {
+                if let Some(sample) = principled.sample(&rec, wo, &mut sampler) {
+                    scattered += 1;
+                    assert!(sample.pdf > 0.0 && sample.weight.elements.iter().all(|w| w.is_finite() && *w >= 0.0));
+                    assert!((principled.pdf(&rec, wo, sample.direction) - sample.pdf).abs() <= 1e-3 * sample.pdf);
+                }
+            }
+
+            // The mixture pdf integrates to the fraction of samples kept
+            let uniform_trials = 8 * trials;
+            let total: f32 = (0..uniform_trials).map(|_| principled.pdf(&rec, wo, random_unit_vector(&mut sampler))).sum();
+            let integral = total * 4.0 * PI / uniform_trials as f32;
+            let expected = scattered as f32 / trials as f32;
+            assert!((integral - expected).abs() < 0.05, "pdf integrates to {}, expected {}", integral, expected);
+        }
+    }
+
+    #[test]
+    fn white_furnace_loses_no_more_than_it_should() {
+        // A white base never reflects more light than arrives
+        let wo = unit_vector(Vec3::new(0.3, 0.0, 0.9));
+        let white = Principled::from_color(Color::new(1.0, 1.0, 1.0));
+        for principled in [
+            white.clone(),
+            Principled { metallic: constant(1.0), ..white.clone() },
+            Principled { transmission: constant(1.0), ..white.clone() },
+            Principled { clearcoat: constant(1.0), ..white },
+        ].iter() {
+            let material = Material::principled(principled.clone());
+            let rec = record(&material);
+            let mut sampler = IndependentSampler::new(0);
+            let trials = 50_000;
+            let mut total = Color::new(0.0, 0.0, 0.0);
+            for _ in 0..trials {
+                if let Some(sample) = principled.sample(&rec, wo, &mut sampler) {
+                    total += sample.weight;
+                }
+            }
+            let albedo = total / trials as f32;
+            assert!(albedo.x() < 1.02 && albedo.x() > 0.7, "{:?}", albedo);
+        }
+    }
+
+    #[test]
+    fn scalar_parameters_follow_textures() {
+        // A glossy surface whose metallic slider is a checker: one square
+        // is metal, the other diffuse.
+        let checker = Arc::new(crate::texture::CheckerTexture::from_colors(
+            Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 1.0,
+        ));
+        let principled = Principled {
+            metallic: checker,
+            roughness: constant(0.1),
+            ..Principled::from_color(Color::new(0.9, 0.9, 0.9))
+        };
+        let material = Material::principled(principled.clone());
+        let mut rec = record(&material);
+
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let off_specular = unit_vector(Vec3::new(1.0, 0.0, 0.3));
+        let mut values = vec![];
+        for &x in [0.5, 1.5].iter() {
+            rec.p = Point3::new(x, 0.5, 0.5);
+            values.push(principled.eval(&rec, wo, off_specular).x());
+        }
+        // Diffuse surfaces scatter far from the mirror direction, metals don't
+        assert!((values[0] < 0.01) != (values[1] < 0.01), "{:?}", values);
+    }
+}
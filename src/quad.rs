@@ -0,0 +1,229 @@
+use crate::aabb::{Aabb, surrounding_box};
+use crate::hittable::{HitRecord, Hittable, HittableList, planar_pdf};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utility::{INFINITY, cross, dot, unit_vector};
+use crate::vec3::{Point3, Vec3};
+
+/// A parallelogram with corner `q` and edges `u` and `v`. It faces along
+/// `u x v`, and its (u, v) coordinates run from 0 to 1 along the two edges.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f32,
+    w: Vec3,
+    area: f32,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Material) -> Self {
+        let n = cross(u, v);
+        let normal = unit_vector(n);
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: dot(normal, q),
+            w: n / dot(n, n),
+            area: n.length(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denominator = dot(self.normal, ray.direction());
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - dot(self.normal, ray.origin())) / denominator;
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+
+        // Express the hit point in the quad's own (u, v) frame
+        let p = ray.at(t);
+        let planar = p - self.q;
+        let alpha = dot(self.w, cross(planar, self.v));
+        let beta = dot(self.w, cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut record = HitRecord {
+            p,
+            normal: self.normal,
+            t,
+            u: alpha,
+            v: beta,
+            front_face: false,
+            material: &self.material,
+        };
+        record.set_face_normal(ray, self.normal);
+
+        Some(record)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        let diagonal0 = Aabb::new(self.q, self.q + self.u + self.v);
+        let diagonal1 = Aabb::new(self.q + self.u, self.q + self.v);
+        Some(surrounding_box(sorted(diagonal0), sorted(diagonal1)).padded())
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        planar_pdf(self.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY), direction, self.area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.next_2d();
+        self.q + a * self.u + b * self.v - origin
+    }
+}
+
+/// The same box with each axis's bounds in increasing order.
+fn sorted(aabb: Aabb) -> Aabb {
+    let (a, b) = (aabb.min(), aabb.max());
+    Aabb::new(
+        Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+        Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+    )
+}
+
+/// An axis-aligned box between two opposite corners, made of six quads
+/// that all face outward.
+pub struct BoxShape {
+    sides: HittableList,
+}
+
+impl BoxShape {
+    pub fn new(a: Point3, b: Point3, material: Material) -> Self {
+        let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        let sides: Vec<Box<dyn Hittable>> = vec![
+            // front, right, back, left, top, bottom
+            Box::new(Quad::new(Point3::new(min.x(), min.y(), max.z()), dx, dy, material.clone())),
+            Box::new(Quad::new(Point3::new(max.x(), min.y(), max.z()), -dz, dy, material.clone())),
+            Box::new(Quad::new(Point3::new(max.x(), min.y(), min.z()), -dx, dy, material.clone())),
+            Box::new(Quad::new(Point3::new(min.x(), min.y(), min.z()), dz, dy, material.clone())),
+            Box::new(Quad::new(Point3::new(min.x(), max.y(), max.z()), dx, -dz, material.clone())),
+            Box::new(Quad::new(Point3::new(min.x(), min.y(), min.z()), dx, dz, material)),
+        ];
+
+        BoxShape { sides: HittableList::new(sides) }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.sides.hit(ray, t_min, t_max)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        self.sides.bounding_box(time0, time1)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::utility::INFINITY;
+    use crate::vec3::Color;
+
+    fn white() -> Material {
+        Material::lambertian(Color::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn slanted_quad_uv() {
+        // A parallelogram sheared along x, facing +z
+        let quad = Quad::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), white());
+        let toward = |x: f32, y: f32| Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let hit = quad.hit(toward(1.5, 0.5), 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert!((hit.u - 0.5).abs() < 1e-6 && (hit.v - 0.5).abs() < 1e-6);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // Inside the bounding rectangle but outside the parallelogram
+        assert!(quad.hit(toward(0.2, 0.9), 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn quad_pdf_matches_solid_angle() {
+        // A 0.02 x 0.02 light 2 units above the origin subtends about
+        // area / distance^2 steradians straight up.
+        let light = Quad::new(Point3::new(-0.01, 2.0, -0.01), Vec3::new(0.02, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.02), white());
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        let pdf = light.pdf_value(origin, Vec3::new(0.0, 1.0, 0.0));
+        assert!((pdf - 4.0 / 0.0004).abs() < 1e-3 * pdf);
+        assert_eq!(light.pdf_value(origin, Vec3::new(1.0, 1.0, 0.0)), 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let direction = light.random(origin, &mut sampler);
+            assert!(light.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY).is_some());
+        }
+    }
+
+    #[test]
+    fn quad_box_covers_all_corners() {
+        let quad = Quad::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(-2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 3.0), white());
+        let bbox = quad.bounding_box(0.0, 1.0).unwrap();
+
+        assert!(bbox.min().x() < -0.99 && bbox.max().x() > 0.99);
+        assert!(bbox.min().z() < 0.0 && bbox.max().z() > 2.99);
+        assert!(bbox.min().y() < 0.0 && bbox.max().y() > 0.0);
+    }
+
+    #[test]
+    fn box_sides_face_outward() {
+        let shape = BoxShape::new(Point3::new(1.0, 1.0, 1.0), Point3::new(-1.0, -1.0, -1.0), white());
+        let directions = [
+            Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0),
+        ];
+
+        for &direction in directions.iter() {
+            // From outside, each face is hit on its front
+            let outside = Ray::new(5.0 * direction, -direction, 0.0);
+            let hit = shape.hit(outside, 0.001, INFINITY).unwrap();
+            assert_eq!(hit.t, 4.0);
+            assert!(hit.front_face);
+            assert_eq!(hit.normal, direction);
+
+            // From the center, each face is hit on its back
+            let inside = Ray::new(Point3::new(0.0, 0.0, 0.0), direction, 0.0);
+            let hit = shape.hit(inside, 0.001, INFINITY).unwrap();
+            assert_eq!(hit.t, 1.0);
+            assert!(!hit.front_face);
+        }
+
+        let bbox = shape.bounding_box(0.0, 1.0).unwrap();
+        assert!(bbox.min().x() <= -1.0 && bbox.max().x() >= 1.0);
+    }
+}
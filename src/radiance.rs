@@ -0,0 +1,329 @@
+use std::io::{self, Write};
+
+use crate::image::Image;
+use crate::vec3::Color;
+
+// Scanlines outside this range can't be run-length encoded.
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+
+const MIN_RUN: usize = 4;
+const MAX_RUN: usize = 127;
+const MAX_LITERAL: usize = 128;
+
+/// Shared-exponent encoding of a linear color. Negative and non-finite
+/// components are written as zero.
+pub fn to_rgbe(color: Color) -> [u8; 4] {
+    let sanitize = |value: f32| if value.is_finite() && value > 0.0 { value } else { 0.0 };
+    let (r, g, b) = (sanitize(color.r()), sanitize(color.g()), sanitize(color.b()));
+    let v = r.max(g).max(b);
+
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // v = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let exponent = ((v.to_bits() >> 23) & 0xff) as i32 - 126;
+    let scale = 256.0 / 2f32.powi(exponent);
+
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+/// Decodes a shared-exponent color, reading each mantissa at the center of
+/// its bucket.
+pub fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    Color::new(
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale,
+    )
+}
+
+fn write_rle_component(values: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < values.len() {
+        // Find the next run worth encoding
+        let mut run_start = i;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = 1;
+            while run_start + run_length < values.len()
+                && run_length < MAX_RUN
+                && values[run_start + run_length] == values[run_start]
+            {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        // Everything before it goes out as literals
+        while i < run_start.min(values.len()) {
+            let count = (run_start - i).min(MAX_LITERAL);
+            out.push(count as u8);
+            out.extend_from_slice(&values[i..i + count]);
+            i += count;
+        }
+
+        if run_start < values.len() && run_length >= MIN_RUN {
+            out.push((128 + run_length) as u8);
+            out.push(values[run_start]);
+            i = run_start + run_length;
+        }
+    }
+}
+
+/// Writes a Radiance RGBE (.hdr) file with unclamped linear radiance.
+/// Scanlines are run-length encoded when the width allows it.
+pub fn write_hdr<W: Write>(image: &Image, writer: &mut W) -> io::Result<()> {
+    let (width, height) = (image.width(), image.height());
+    write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+    let encode_lines = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width);
+    let mut line = Vec::with_capacity(width * 4 + 4);
+    let mut component = Vec::with_capacity(width);
+
+    for y in 0..height {
+        let pixels: Vec<[u8; 4]> = (0..width).map(|x| to_rgbe(image.pixel(x, y))).collect();
+        line.clear();
+
+        if encode_lines {
+            line.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+            for channel in 0..4 {
+                component.clear();
+                component.extend(pixels.iter().map(|pixel| pixel[channel]));
+                write_rle_component(&component, &mut line);
+            }
+        } else {
+            line.extend(pixels.iter().flatten());
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads one run-length encoded component of a scanline into `line`.
+fn read_rle_component(data: &mut &[u8], line: &mut [[u8; 4]], channel: usize) -> io::Result<()> {
+    let truncated = || invalid_data("HDR scanline is truncated");
+    let mut x = 0;
+    while x < line.len() {
+        let count = *data.first().ok_or_else(truncated)? as usize;
+        if count > 128 {
+            let count = count - 128;
+            let value = *data.get(1).ok_or_else(truncated)?;
+            let pixels = line.get_mut(x..x + count).ok_or_else(|| invalid_data("HDR run overflows its scanline"))?;
+            for pixel in pixels {
+                pixel[channel] = value;
+            }
+            x += count;
+            *data = &data[2..];
+        } else {
+            if count == 0 {
+                return Err(invalid_data("HDR scanline has an empty literal"));
+            }
+            let values = data.get(1..1 + count).ok_or_else(truncated)?;
+            let pixels = line.get_mut(x..x + count).ok_or_else(|| invalid_data("HDR run overflows its scanline"))?;
+            for (pixel, value) in pixels.iter_mut().zip(values) {
+                pixel[channel] = *value;
+            }
+            x += count;
+            *data = &data[1 + count..];
+        }
+    }
+    Ok(())
+}
+
+/// Reads a Radiance RGBE (.hdr) file into linear colors. Scanlines may be
+/// flat or run-length encoded; only the usual top-to-bottom `-Y h +X w`
+/// orientation is supported.
+pub fn read_hdr(bytes: &[u8]) -> io::Result<Image> {
+    if !bytes.starts_with(b"#?") {
+        return Err(invalid_data("not a Radiance HDR file"));
+    }
+
+    // Header lines run until an empty one, then comes the resolution
+    let mut lines = bytes.split(|&byte| byte == b'\n');
+    let mut position = 0;
+    for line in lines.by_ref() {
+        position += line.len() + 1;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            if format != b"32-bit_rle_rgbe" {
+                return Err(invalid_data("only 32-bit_rle_rgbe HDR files are supported"));
+            }
+        }
+    }
+    let resolution = lines.next().ok_or_else(|| invalid_data("HDR resolution is missing"))?;
+    position += resolution.len() + 1;
+    let resolution = std::str::from_utf8(resolution).map_err(|_| invalid_data("HDR resolution is not text"))?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|_| invalid_data("bad HDR height"))?,
+            width.parse::<usize>().map_err(|_| invalid_data("bad HDR width"))?,
+        ),
+        _ => return Err(invalid_data("unsupported HDR orientation")),
+    };
+
+    let mut data = bytes.get(position..).unwrap_or(&[]);
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut line = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        let encoded = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width)
+            && data.len() >= 4
+            && data[0] == 2
+            && data[1] == 2
+            && data[2] & 0x80 == 0;
+        if encoded {
+            if ((data[2] as usize) << 8) | data[3] as usize != width {
+                return Err(invalid_data("HDR scanline width doesn't match the image"));
+            }
+            data = &data[4..];
+            for channel in 0..4 {
+                read_rle_component(&mut data, &mut line, channel)?;
+            }
+        } else {
+            let flat = data.get(..width * 4).ok_or_else(|| invalid_data("HDR pixel data is truncated"))?;
+            for (pixel, chunk) in line.iter_mut().zip(flat.chunks(4)) {
+                pixel.copy_from_slice(chunk);
+            }
+            data = &data[width * 4..];
+        }
+        pixels.extend(line.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Byte-exact reader for checking the encoder.
+    fn decode(bytes: &[u8]) -> (usize, usize, Vec<[u8; 4]>) {
+        let header_end = bytes.windows(2).position(|pair| pair == b"\n\n").unwrap() + 2;
+        let resolution_end = header_end + bytes[header_end..].iter().position(|&b| b == b'\n').unwrap();
+        let resolution = std::str::from_utf8(&bytes[header_end..resolution_end]).unwrap();
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+        let height: usize = parts[1].parse().unwrap();
+        let width: usize = parts[3].parse().unwrap();
+
+        let mut data = &bytes[resolution_end + 1..];
+        let mut pixels = vec![];
+        for _ in 0..height {
+            if !(MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width) {
+                for chunk in data[..width * 4].chunks(4) {
+                    pixels.push([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                }
+                data = &data[width * 4..];
+                continue;
+            }
+
+            assert_eq!(&data[..2], &[2, 2]);
+            assert_eq!(((data[2] as usize) << 8) | data[3] as usize, width);
+            data = &data[4..];
+
+            let mut line = vec![[0u8; 4]; width];
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = data[0] as usize;
+                    if count > 128 {
+                        for pixel in line[x..x + count - 128].iter_mut() {
+                            pixel[channel] = data[1];
+                        }
+                        x += count - 128;
+                        data = &data[2..];
+                    } else {
+                        for (offset, pixel) in line[x..x + count].iter_mut().enumerate() {
+                            pixel[channel] = data[1 + offset];
+                        }
+                        x += count;
+                        data = &data[1 + count..];
+                    }
+                }
+            }
+            pixels.extend(line);
+        }
+        assert!(data.is_empty());
+        (width, height, pixels)
+    }
+
+    #[test]
+    fn rgbe_keeps_dynamic_range() {
+        for value in [0.001, 0.5, 1.0, 3.75, 1000.0, 65536.0].iter() {
+            let decoded = from_rgbe(to_rgbe(Color::new(*value, *value * 0.5, 0.0)));
+            assert!((decoded.r() - value).abs() / value < 0.01, "{} became {}", value, decoded.r());
+            assert!((decoded.g() - value * 0.5).abs() / value < 0.01);
+        }
+        assert_eq!(to_rgbe(Color::new(0.0, -1.0, f32::NAN)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let mut pixels = vec![];
+        for x in 0..300 {
+            let value = if x < 150 { 2.0 } else { (x % 7) as f32 * 0.1 };
+            pixels.push(Color::new(value, 0.25, x as f32));
+        }
+        let image = Image::from_pixels(100, 3, pixels);
+
+        let mut bytes = vec![];
+        write_hdr(&image, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"#?RADIANCE\n"));
+
+        let (width, height, decoded) = decode(&bytes);
+        assert_eq!((width, height), (100, 3));
+        let expected: Vec<[u8; 4]> = image.pixels().iter().map(|&color| to_rgbe(color)).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn read_back_what_was_written() {
+        for width in [2, 100] {
+            let pixels = (0..width * 3).map(|i| Color::new(i as f32 * 0.5, 0.01, if i % 4 == 0 { 7.0 } else { 0.0 })).collect();
+            let image = Image::from_pixels(width, 3, pixels);
+
+            let mut bytes = vec![];
+            write_hdr(&image, &mut bytes).unwrap();
+            let read = read_hdr(&bytes).unwrap();
+
+            assert_eq!((read.width(), read.height()), (width, 3));
+            for (a, b) in image.pixels().iter().zip(read.pixels()) {
+                assert!((*a - *b).length() <= 0.01 * a.length().max(0.01), "{:?} != {:?}", a, b);
+            }
+        }
+
+        assert!(read_hdr(b"P6\n").is_err());
+        assert!(read_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(read_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(read_hdr(b"#?RADIANCE\n\n-Y 2 +X 1\n\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn narrow_images_are_written_flat() {
+        let image = Image::from_pixels(2, 1, vec![Color::new(1.0, 1.0, 1.0), Color::new(4.0, 0.0, 0.0)]);
+
+        let mut bytes = vec![];
+        write_hdr(&image, &mut bytes).unwrap();
+        let (_, _, decoded) = decode(&bytes);
+
+        assert_eq!(decoded, vec![[128, 128, 128, 129], [128, 0, 0, 131]]);
+    }
+}
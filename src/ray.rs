@@ -4,18 +4,27 @@ use crate::vec3::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
-    pub time: f32
+    pub time: f32,
+    /// Wavelength in nanometres carried by spectral rendering; `None` for
+    /// RGB rays
+    pub wavelength: Option<f32>
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3, time: f32) -> Ray {
         Ray {
-            origin: origin,
-            direction: direction,
-            time: time
+            origin,
+            direction,
+            time,
+            wavelength: None
         }
     }
 
+    /// The same ray, carrying a single wavelength.
+    pub fn with_wavelength(self, wavelength: f32) -> Ray {
+        Ray { wavelength: Some(wavelength), ..self }
+    }
+
     pub fn origin(&self) -> Vec3 {
         self.origin
     }
@@ -28,6 +37,10 @@ impl Ray {
         self.time
     }
 
+    pub fn wavelength(&self) -> Option<f32> {
+        self.wavelength
+    }
+
     pub fn at(&self, t: f32) -> Vec3 {
         self.origin + t * self.direction
     }
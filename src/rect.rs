@@ -0,0 +1,247 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, planar_pdf};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utility::INFINITY;
+use crate::vec3::{Point3, Vec3};
+
+/// Intersects the ray with the rectangle [a0, a1] x [b0, b1] lying in the
+/// plane where axis `k` equals `k_value`. Returns the ray parameter and the
+/// rectangle's (u, v) at the hit point.
+#[allow(clippy::too_many_arguments)]
+fn hit_rect(
+    ray: Ray, axes: (usize, usize, usize),
+    a0: f32, a1: f32, b0: f32, b1: f32, k_value: f32,
+    t_min: f32, t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let (a, b, k) = axes;
+    let origin = ray.origin().elements;
+    let direction = ray.direction().elements;
+
+    // Parallel rays give an infinite or NaN t, which the range checks reject.
+    let t = (k_value - origin[k]) / direction[k];
+    if !(t_min..=t_max).contains(&t) {
+        return None;
+    }
+
+    let hit_a = origin[a] + t * direction[a];
+    let hit_b = origin[b] + t * direction[b];
+    if !(a0..=a1).contains(&hit_a) || !(b0..=b1).contains(&hit_b) {
+        return None;
+    }
+
+    Some((t, (hit_a - a0) / (a1 - a0), (hit_b - b0) / (b1 - b0)))
+}
+
+fn record<'a>(ray: Ray, t: f32, u: f32, v: f32, outward_normal: Vec3, material: &'a Material) -> HitRecord<'a> {
+    let mut record = HitRecord {
+        p: ray.at(t),
+        normal: outward_normal,
+        t,
+        u,
+        v,
+        front_face: false,
+        material,
+    };
+    record.set_face_normal(ray, outward_normal);
+    record
+}
+
+/// A rectangle in the plane z = k, facing +z.
+pub struct XyRect {
+    x0: f32,
+    x1: f32,
+    y0: f32,
+    y1: f32,
+    k: f32,
+    material: Material,
+}
+
+impl XyRect {
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Material) -> Self {
+        XyRect { x0, x1, y0, y1, k, material }
+    }
+}
+
+impl Hittable for XyRect {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, u, v) = hit_rect(ray, (0, 1, 2), self.x0, self.x1, self.y0, self.y1, self.k, t_min, t_max)?;
+        Some(record(ray, t, u, v, Vec3::new(0.0, 0.0, 1.0), &self.material))
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(Aabb::new(Point3::new(self.x0, self.y0, self.k), Point3::new(self.x1, self.y1, self.k)).padded())
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        planar_pdf(self.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY), direction, area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        Point3::new(sampler.range(self.x0, self.x1), sampler.range(self.y0, self.y1), self.k) - origin
+    }
+}
+
+/// A rectangle in the plane y = k, facing +y.
+pub struct XzRect {
+    x0: f32,
+    x1: f32,
+    z0: f32,
+    z1: f32,
+    k: f32,
+    material: Material,
+}
+
+impl XzRect {
+    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Material) -> Self {
+        XzRect { x0, x1, z0, z1, k, material }
+    }
+}
+
+impl Hittable for XzRect {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, u, v) = hit_rect(ray, (0, 2, 1), self.x0, self.x1, self.z0, self.z1, self.k, t_min, t_max)?;
+        Some(record(ray, t, u, v, Vec3::new(0.0, 1.0, 0.0), &self.material))
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(Aabb::new(Point3::new(self.x0, self.k, self.z0), Point3::new(self.x1, self.k, self.z1)).padded())
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        planar_pdf(self.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY), direction, area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        Point3::new(sampler.range(self.x0, self.x1), self.k, sampler.range(self.z0, self.z1)) - origin
+    }
+}
+
+/// A rectangle in the plane x = k, facing +x.
+pub struct YzRect {
+    y0: f32,
+    y1: f32,
+    z0: f32,
+    z1: f32,
+    k: f32,
+    material: Material,
+}
+
+impl YzRect {
+    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Material) -> Self {
+        YzRect { y0, y1, z0, z1, k, material }
+    }
+}
+
+impl Hittable for YzRect {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, u, v) = hit_rect(ray, (1, 2, 0), self.y0, self.y1, self.z0, self.z1, self.k, t_min, t_max)?;
+        Some(record(ray, t, u, v, Vec3::new(1.0, 0.0, 0.0), &self.material))
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(Aabb::new(Point3::new(self.k, self.y0, self.z0), Point3::new(self.k, self.y1, self.z1)).padded())
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        planar_pdf(self.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY), direction, area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        Point3::new(self.k, sampler.range(self.y0, self.y1), sampler.range(self.z0, self.z1)) - origin
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::utility::INFINITY;
+    use crate::vec3::Color;
+
+    fn white() -> Material {
+        Material::lambertian(Color::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn xy_rect_hits_inside_only() {
+        let rect = XyRect::new(0.0, 2.0, 0.0, 4.0, -1.0, white());
+        let toward = |x: f32, y: f32| Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let hit = rect.hit(toward(0.5, 3.0), 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+        assert_eq!((hit.u, hit.v), (0.25, 0.75));
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // Seen from the -z side, the normal is flipped toward the ray
+        let back = Ray::new(Point3::new(0.5, 3.0, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit = rect.hit(back, 0.001, INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(rect.hit(toward(2.5, 3.0), 0.001, INFINITY).is_none());
+        assert!(rect.hit(toward(0.5, 3.0), 0.001, 0.5).is_none());
+    }
+
+    #[test]
+    fn xz_and_yz_rects() {
+        let floor = XzRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, white());
+        let down = Ray::new(Point3::new(0.5, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = floor.hit(down, 0.001, INFINITY).unwrap();
+        assert!(hit.front_face);
+        assert_eq!((hit.u, hit.v), (0.75, 0.5));
+
+        let wall = YzRect::new(0.0, 1.0, 0.0, 1.0, 3.0, white());
+        let across = Ray::new(Point3::new(0.0, 0.25, 0.5), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let hit = wall.hit(across, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 3.0);
+        assert_eq!((hit.u, hit.v), (0.25, 0.5));
+
+        // A ray running along the plane never hits it
+        let parallel = Ray::new(Point3::new(3.0, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(wall.hit(parallel, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn rects_sample_their_own_area() {
+        let light = XzRect::new(-1.0, 1.0, -1.0, 1.0, 3.0, white());
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let direction = light.random(origin, &mut sampler);
+            assert!(light.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY).is_some());
+        }
+        // Straight up: distance^2 / (cos * area)
+        assert!((light.pdf_value(origin, Vec3::new(0.0, 1.0, 0.0)) - 9.0 / 4.0).abs() < 1e-5);
+        assert_eq!(light.pdf_value(origin, Vec3::new(0.0, -1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn flat_rect_boxes_are_padded() {
+        let rect = XzRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, white());
+        let bbox = rect.bounding_box(0.0, 1.0).unwrap();
+
+        assert!(bbox.min().y() < 0.0 && bbox.max().y() > 0.0);
+        let down = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!(bbox.hit(down, 0.001, INFINITY));
+    }
+}
@@ -0,0 +1,805 @@
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::bsdf::Lobes;
+use crate::camera::Camera;
+use crate::environment::Environment;
+use crate::hittable::{Hittable, HittableList};
+use crate::image::Image;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::spectrum::{at_wavelength, sample_wavelength, wavelength_to_rgb};
+use crate::utility::{INFINITY, clamp, unit_vector};
+use crate::vec3::{Color, luminance};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub aspect_ratio: f32,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    pub integrator: Integrator,
+    /// Traces one wavelength per camera ray instead of RGB, so dispersive
+    /// glass splits light into colors. Scenes are still described in RGB:
+    /// every reflectance, emission and sky color a path meets is upsampled
+    /// to a smooth spectrum and read at its wavelength (see
+    /// `spectrum::at_wavelength`)
+    pub spectral: bool,
+    /// Picks the random numbers; the same seed gives the same image, however
+    /// many threads render it
+    pub seed: u64,
+    /// How the random numbers are spread over each pixel's samples
+    pub sampler: SamplerKind,
+    /// When set, a pixel stops taking samples once the standard error of its
+    /// mean luminance, relative to that mean, falls to this; `samples_per_pixel`
+    /// becomes the most any pixel takes
+    pub adaptive_threshold: Option<f32>,
+    /// Samples every pixel takes in each pass over the image: the fewest any
+    /// pixel takes with an adaptive threshold, and how often a progressive
+    /// render can stop or show how far it has got
+    pub min_samples_per_pixel: usize,
+    /// Stops adding samples once the render has taken this long. The first
+    /// pass always finishes, and the image then depends on the speed of the
+    /// machine rather than only on the seed
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 400,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            integrator: Integrator::NextEvent,
+            spectral: false,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive_threshold: None,
+            min_samples_per_pixel: 16,
+            time_limit: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn image_height(&self) -> usize {
+        ((self.image_width as f32) / self.aspect_ratio) as usize
+    }
+
+    /// Checks that a render with these settings makes sense, whether they
+    /// came from a scene file or the command line.
+    pub fn validate(&self) -> Result<(), InvalidSetting> {
+        let invalid = |key, message| Err(InvalidSetting { key, message });
+        if self.image_width < 2 {
+            return invalid("width", "must be at least 2");
+        }
+        if !(self.aspect_ratio.is_finite() && self.aspect_ratio > 0.0) {
+            return invalid("aspect_ratio", "must be a positive number");
+        }
+        if self.image_height() < 2 {
+            return invalid("aspect_ratio", "leaves an image less than 2 pixels tall");
+        }
+        if self.samples_per_pixel == 0 {
+            return invalid("samples_per_pixel", "must be at least 1");
+        }
+        if self.adaptive_threshold.is_some_and(|threshold| !(threshold.is_finite() && threshold > 0.0)) {
+            return invalid("adaptive_threshold", "must be a positive number");
+        }
+        if self.min_samples_per_pixel == 0 {
+            return invalid("min_samples_per_pixel", "must be at least 1");
+        }
+        if self.time_limit.is_some_and(|limit| limit.is_zero()) {
+            return invalid("time_limit", "must be a positive number of seconds");
+        }
+        Ok(())
+    }
+}
+
+/// A `RenderSettings` field no render can use, named by its key in a scene
+/// file's `[render]` table.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct InvalidSetting {
+    pub key: &'static str,
+    pub message: &'static str,
+}
+
+impl fmt::Display for InvalidSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+impl Error for InvalidSetting {}
+
+/// How the radiance along each camera ray is estimated.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Integrator {
+    /// Follows scattered rays only, as `ray_color` does
+    Naive,
+    /// Also samples the renderer's lights at every diffuse bounce and weighs
+    /// both strategies with multiple importance sampling, as `path_color` does
+    NextEvent,
+}
+
+pub fn ray_color(
+    ray: Ray, world: &dyn Hittable, environment: &Environment, depth: usize, sampler: &mut dyn Sampler,
+) -> Color {
+    if depth == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let hit = match world.sample_hit(ray, 0.001, INFINITY, sampler) {
+        Some(hit) => hit,
+        None => return at_wavelength(environment.color(ray), ray.wavelength()),
+    };
+
+    let emitted = at_wavelength(hit.material.emitted(hit.u, hit.v, hit.p), ray.wavelength());
+
+    match hit.material.sample(&hit, ray, sampler) {
+        Some(sample) => {
+            let scattered = Ray { origin: hit.p, direction: sample.direction, ..ray };
+            let weight = at_wavelength(sample.weight, ray.wavelength());
+            emitted + weight * ray_color(scattered, world, environment, depth - 1, sampler)
+        }
+        None => emitted,
+    }
+}
+
+/// The power heuristic (with exponent two) weight for a sample drawn with
+/// density `pdf` when `other_pdf` could also have produced it.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// Estimates the same radiance as `ray_color`, but at every non-specular bounce
+/// also sends shadow rays toward a point sampled on `lights` and toward a
+/// direction sampled from the environment, when it can be sampled. Light
+/// found by more than one strategy is weighted with the power heuristic, so
+/// nothing is counted twice. With no lights and an environment that can't
+/// be sampled this is exactly `ray_color`.
+pub fn path_color(
+    ray: Ray, world: &dyn Hittable, lights: &dyn Hittable, environment: &Environment, max_depth: usize,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let mut radiance = Color::new(0.0, 0.0, 0.0);
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut ray = ray;
+    // Every color met along the way is seen at the path's wavelength, if any
+    let wavelength = ray.wavelength();
+    let spectral = |color: Color| at_wavelength(color, wavelength);
+    // Density of the bounce that produced `ray`; none after the camera or a
+    // mirror-like bounce, which light sampling can't reproduce.
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for depth in 0..max_depth {
+        let hit = match world.sample_hit(ray, 0.001, INFINITY, sampler) {
+            Some(hit) => hit,
+            None => {
+                let weight = match bsdf_pdf {
+                    Some(pdf) => power_heuristic(pdf, environment.pdf_value(ray.direction())),
+                    None => 1.0,
+                };
+                radiance += weight * throughput * spectral(environment.color(ray));
+                break;
+            }
+        };
+
+        let emitted = spectral(hit.material.emitted(hit.u, hit.v, hit.p));
+        let weight = match bsdf_pdf {
+            Some(pdf) => power_heuristic(pdf, lights.pdf_value(ray.origin(), ray.direction())),
+            None => 1.0,
+        };
+        radiance += weight * throughput * emitted;
+
+        let wo = -unit_vector(ray.direction());
+        let sample = match hit.material.sample(&hit, ray, sampler) {
+            Some(sample) => sample,
+            None => break,
+        };
+
+        // The shadow ray finds the light that the next bounce would otherwise
+        // have to stumble on, so skip it when there won't be a next bounce.
+        if hit.material.lobes().has_smooth() && depth + 1 < max_depth {
+            let direction = lights.random(hit.p, sampler);
+            let light_pdf = lights.pdf_value(hit.p, direction);
+            if light_pdf > 0.0 {
+                let wi = unit_vector(direction);
+                let shadow = Ray { origin: hit.p, direction, ..ray };
+                if let Some(light) = world.sample_hit(shadow, 0.001, INFINITY, sampler) {
+                    let light_emitted = spectral(light.material.emitted(light.u, light.v, light.p));
+                    let weight = power_heuristic(light_pdf, hit.material.pdf(&hit, wo, wi));
+                    radiance += weight / light_pdf * throughput * spectral(hit.material.eval(&hit, wo, wi)) * light_emitted;
+                }
+            }
+
+            if let Some(wi) = environment.random(sampler) {
+                let environment_pdf = environment.pdf_value(wi);
+                let shadow = Ray { origin: hit.p, direction: wi, ..ray };
+                if environment_pdf > 0.0 && world.sample_hit(shadow, 0.001, INFINITY, sampler).is_none() {
+                    let weight = power_heuristic(environment_pdf, hit.material.pdf(&hit, wo, wi));
+                    radiance += weight / environment_pdf
+                        * throughput
+                        * spectral(hit.material.eval(&hit, wo, wi))
+                        * spectral(environment.color(shadow));
+                }
+            }
+        }
+
+        bsdf_pdf = if sample.lobe.contains(Lobes::SPECULAR) { None } else { Some(sample.pdf) };
+        throughput *= spectral(sample.weight);
+        ray = Ray { origin: hit.p, direction: sample.direction, ..ray };
+    }
+    radiance
+}
+
+pub struct Renderer {
+    pub settings: RenderSettings,
+    /// What rays that escape the scene see
+    pub environment: Environment,
+    /// Emitters to sample directly; they must also be part of the world.
+    pub lights: HittableList,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Renderer {
+        Renderer { settings, environment: Environment::SKY, lights: HittableList::new(vec![]) }
+    }
+
+    /// Renders the averaged linear radiance of every pixel.
+    pub fn render(&self, world: &dyn Hittable, camera: &Camera) -> Image {
+        self.render_with_sample_counts(world, camera).0
+    }
+
+    /// Renders like `render`, also returning how many samples each pixel
+    /// took, row by row from the top like the image.
+    pub fn render_with_sample_counts(&self, world: &dyn Hittable, camera: &Camera) -> (Image, Vec<usize>) {
+        let progress = self.render_to_completion(world, camera);
+        (progress.image, progress.sample_counts)
+    }
+
+    /// Renders like `render_progressive` without looking at the passes. With
+    /// neither an adaptive threshold nor a time limit there is nothing to
+    /// decide between passes, so every pixel takes all its samples in one;
+    /// the image is the same either way.
+    pub fn render_to_completion(&self, world: &dyn Hittable, camera: &Camera) -> Progress {
+        let batch = if self.settings.adaptive_threshold.is_none() && self.settings.time_limit.is_none() {
+            self.settings.samples_per_pixel
+        } else {
+            self.settings.min_samples_per_pixel
+        };
+        self.render_passes(world, camera, batch, |_| {})
+    }
+
+    /// Renders the image in passes of `min_samples_per_pixel` samples, adding
+    /// each pass to what the pixels already have. It stops once every pixel
+    /// has `samples_per_pixel` samples or has converged under the adaptive
+    /// threshold, or once the time limit has run out, and returns the final
+    /// image; `on_pass` sees the image after every pass before that.
+    pub fn render_progressive(
+        &self, world: &dyn Hittable, camera: &Camera, on_pass: impl FnMut(&Progress),
+    ) -> Progress {
+        self.render_passes(world, camera, self.settings.min_samples_per_pixel, on_pass)
+    }
+
+    fn render_passes(
+        &self, world: &dyn Hittable, camera: &Camera, batch: usize, mut on_pass: impl FnMut(&Progress),
+    ) -> Progress {
+        let start = Instant::now();
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height();
+        let samples_per_pixel = self.settings.samples_per_pixel;
+        let threshold = self.settings.adaptive_threshold;
+        let batch = batch.max(1);
+        let out_of_time = || self.settings.time_limit.is_some_and(|limit| start.elapsed() >= limit);
+
+        let mut estimates: Vec<PixelEstimate> = (0..image_width * image_height).map(|_| PixelEstimate::new()).collect();
+        let mut active = vec![true; estimates.len()];
+        let mut passes = 0;
+        loop {
+            estimates.par_iter_mut().zip(active.par_iter()).enumerate().for_each(|(index, (estimate, &active))| {
+                // Pixels skipped for lack of time keep the samples they have
+                if !active || (estimate.count > 0 && out_of_time()) {
+                    return;
+                }
+                let (i, j) = (index % image_width, image_height - 1 - index / image_width);
+                let mut sampler = self.settings.sampler.build(self.settings.seed, samples_per_pixel);
+                for sample in estimate.count..(estimate.count + batch).min(samples_per_pixel) {
+                    sampler.start_sample(i, j, sample);
+                    estimate.add(self.sample_pixel(world, camera, i, j, sampler.as_mut()));
+                }
+            });
+            passes += 1;
+
+            active = estimates
+                .iter()
+                .map(|estimate| {
+                    estimate.count < samples_per_pixel
+                        && threshold.is_none_or(|threshold| estimate.relative_error() > threshold)
+                })
+                .collect();
+
+            let pixels = estimates.iter().map(PixelEstimate::mean).collect();
+            let progress = Progress {
+                image: Image::from_pixels(image_width, image_height, pixels),
+                sample_counts: estimates.iter().map(|estimate| estimate.count).collect(),
+                passes,
+                elapsed: start.elapsed(),
+            };
+            if !active.contains(&true) || out_of_time() {
+                return progress;
+            }
+            on_pass(&progress);
+        }
+    }
+
+    /// Shows sample counts, like those in `Progress`, as an image
+    /// that runs from black, for pixels that took no samples, through red and
+    /// yellow to white, for pixels that took `samples_per_pixel`.
+    pub fn heatmap(&self, counts: &[usize]) -> Image {
+        let pixels = counts
+            .iter()
+            .map(|&count| {
+                let t = 3.0 * count as f32 / self.settings.samples_per_pixel as f32;
+                let ramp = Color::new(clamp(t, 0.0, 1.0), clamp(t - 1.0, 0.0, 1.0), clamp(t - 2.0, 0.0, 1.0));
+                // Squared, so the colors survive the gamma correction on output
+                ramp * ramp
+            })
+            .collect();
+        Image::from_pixels(self.settings.image_width, self.settings.image_height(), pixels)
+    }
+
+    /// One radiance estimate through pixel (`i`, `j`), using the sample the
+    /// sampler has been started on.
+    fn sample_pixel(
+        &self, world: &dyn Hittable, camera: &Camera, i: usize, j: usize, sampler: &mut dyn Sampler,
+    ) -> Color {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height();
+        let max_depth = self.settings.max_depth;
+
+        let (du, dv) = sampler.next_2d();
+        let u = (i as f32 + du) / (image_width as f32 - 1.0);
+        let v = (j as f32 + dv) / (image_height as f32 - 1.0);
+        let mut ray = camera.get_ray(u, v, sampler);
+        let wavelength = if self.settings.spectral { Some(sample_wavelength(sampler)) } else { None };
+        if let Some(wavelength) = wavelength {
+            ray = ray.with_wavelength(wavelength);
+        }
+        let radiance = match self.settings.integrator {
+            Integrator::Naive => ray_color(ray, world, &self.environment, max_depth, sampler),
+            Integrator::NextEvent => path_color(ray, world, &self.lights, &self.environment, max_depth, sampler),
+        };
+        match wavelength {
+            // The path carried one wavelength's radiance, the same in every
+            // channel; the film sees it in that wavelength's color
+            Some(wavelength) => radiance * wavelength_to_rgb(wavelength),
+            None => radiance,
+        }
+    }
+}
+
+/// How far a progressive render has got.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// The averaged linear radiance of every pixel so far
+    pub image: Image,
+    /// How many samples each pixel has taken, row by row from the top
+    pub sample_counts: Vec<usize>,
+    /// Passes over the image so far
+    pub passes: usize,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The average number of samples a pixel has taken.
+    pub fn average_samples(&self) -> f32 {
+        self.sample_counts.iter().sum::<usize>() as f32 / self.sample_counts.len() as f32
+    }
+}
+
+/// Pixels darker than this have their error judged as if they were this
+/// bright, so noise too dim to see doesn't take every sample.
+const DARK_LUMINANCE: f32 = 0.01;
+
+/// The running mean of one pixel's samples, with the variance of their
+/// luminance kept by Welford's method.
+struct PixelEstimate {
+    count: usize,
+    sum: Color,
+    mean_luminance: f32,
+    squared_deviations: f32,
+}
+
+impl PixelEstimate {
+    fn new() -> PixelEstimate {
+        PixelEstimate { count: 0, sum: Color::new(0.0, 0.0, 0.0), mean_luminance: 0.0, squared_deviations: 0.0 }
+    }
+
+    fn add(&mut self, color: Color) {
+        let luminance = luminance(color);
+        self.count += 1;
+        self.sum += color;
+        let delta = luminance - self.mean_luminance;
+        self.mean_luminance += delta / self.count as f32;
+        self.squared_deviations += delta * (luminance - self.mean_luminance);
+    }
+
+    fn mean(&self) -> Color {
+        self.sum / self.count as f32
+    }
+
+    /// The standard error of the mean luminance over the mean itself.
+    fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return INFINITY;
+        }
+        let variance = self.squared_deviations / (self.count - 1) as f32;
+        (variance / self.count as f32).sqrt() / self.mean_luminance.abs().max(DARK_LUMINANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use std::sync::Arc;
+
+    use crate::camera::CameraSettings;
+    use crate::environment::EnvironmentMap;
+    use crate::image::Image;
+    use crate::hittable::HittableList;
+    use crate::material::Material;
+    use crate::medium::ConstantMedium;
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    fn toward_origin() -> Ray {
+        Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    /// A ball on a floor under a quad light, and the light on its own for
+    /// the renderer to sample.
+    fn test_scene() -> (HittableList, Arc<dyn Hittable>) {
+        let white = Material::lambertian(Color::new(0.7, 0.7, 0.7));
+        let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(-0.5, 2.0, -1.5), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0),
+            Material::diffuse_light(Color::new(4.0, 4.0, 4.0)),
+        ));
+        let world = HittableList::new(vec![
+            Box::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, white.clone())),
+            Box::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, white)),
+            Box::new(light.clone()),
+        ]);
+        (world, light)
+    }
+
+    /// Looks down at the ball of `test_scene` through a 2:1 frame.
+    fn test_camera(aperture: f32) -> Camera {
+        CameraSettings {
+            look_from: Point3::new(0.0, 0.5, 1.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 60.0,
+            aperture,
+            focus_dist: 2.0,
+            time0: 0.0,
+            time1: 1.0,
+        }.build(2.0)
+    }
+
+    #[test]
+    fn lights_emit_without_a_sky() {
+        let mut sampler = IndependentSampler::new(0);
+        let light = Material::diffuse_light(Color::new(2.0, 3.0, 4.0));
+        let world = HittableList::new(vec![Box::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, light))]);
+        let black = Environment::Constant(Color::new(0.0, 0.0, 0.0));
+
+        assert_eq!(ray_color(toward_origin(), &world, &black, 10, &mut sampler), Color::new(2.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn unlit_scene_is_black() {
+        let mut sampler = IndependentSampler::new(0);
+        let white = Material::lambertian(Color::new(1.0, 1.0, 1.0));
+        let world = HittableList::new(vec![
+            Box::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, white.clone())),
+            Box::new(Sphere::new(Point3::new(0.0, -101.0, 0.0), 100.0, white)),
+        ]);
+        let black = Environment::Constant(Color::new(0.0, 0.0, 0.0));
+
+        for _ in 0..100 {
+            assert_eq!(ray_color(toward_origin(), &world, &black, 10, &mut sampler), Color::new(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn white_fog_in_white_surroundings_stays_white() {
+        // An albedo of one loses no energy, so every path that escapes the
+        // fog picks up the full background.
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Material::lambertian(Color::new(0.0, 0.0, 0.0)));
+        let fog = ConstantMedium::from_color(Arc::new(sphere), 2.0, Color::new(1.0, 1.0, 1.0));
+        let world = HittableList::new(vec![Box::new(fog)]);
+        let white = Environment::Constant(Color::new(1.0, 1.0, 1.0));
+
+        let mut sampler = IndependentSampler::new(0);
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..1000 {
+            total += ray_color(toward_origin(), &world, &white, 50, &mut sampler);
+        }
+        assert!(total.x() / 1000.0 > 0.99);
+    }
+
+    #[test]
+    fn misses_see_the_background() {
+        let mut sampler = IndependentSampler::new(0);
+        let world = HittableList::new(vec![]);
+        let solid = Environment::Constant(Color::new(0.1, 0.2, 0.3));
+        let up = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+
+        assert_eq!(ray_color(up, &world, &solid, 10, &mut sampler), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(ray_color(up, &world, &Environment::SKY, 10, &mut sampler), Color::new(0.5, 0.7, 1.0));
+    }
+
+    #[test]
+    fn light_sampling_converges_to_the_naive_estimate() {
+        // A floor, a ball and a fog bank lit by a small sphere and a small
+        // quad, seen from above the floor.
+        let white = Material::lambertian(Color::new(0.7, 0.7, 0.7));
+        let sphere_light: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(-1.0, 3.0, 0.0), 0.8, Material::diffuse_light(Color::new(2.0, 2.0, 2.0)),
+        ));
+        let quad_light: Arc<dyn Hittable> = Arc::new(Quad::new(
+            Point3::new(0.5, 2.5, -1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0),
+            Material::diffuse_light(Color::new(1.0, 2.0, 3.0)),
+        ));
+        let fog = ConstantMedium::from_color(
+            Arc::new(Sphere::new(Point3::new(1.5, 0.5, 0.5), 0.5, white.clone())), 1.0, Color::new(0.9, 0.9, 0.9),
+        );
+        let world = HittableList::new(vec![
+            Box::new(Sphere::new(Point3::new(0.0, -100.0, 0.0), 100.0, white.clone())),
+            Box::new(Sphere::new(Point3::new(0.0, 0.5, -0.5), 0.5, white)),
+            Box::new(fog),
+            Box::new(sphere_light.clone()),
+            Box::new(quad_light.clone()),
+        ]);
+        let lights = HittableList::new(vec![Box::new(sphere_light), Box::new(quad_light)]);
+        let black = Environment::Constant(Color::new(0.0, 0.0, 0.0));
+        let ray = Ray::new(Point3::new(0.0, 1.0, 3.0), Vec3::new(0.0, -0.6, -1.0), 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        let samples = 20_000;
+        let mut naive = Color::new(0.0, 0.0, 0.0);
+        let mut sampled = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            naive += ray_color(ray, &world, &black, 4, &mut sampler);
+            sampled += path_color(ray, &world, &lights, &black, 4, &mut sampler);
+        }
+        let (naive, sampled) = (naive / samples as f32, sampled / samples as f32);
+
+        for axis in 0..3 {
+            let (a, b) = (naive.elements[axis], sampled.elements[axis]);
+            assert!(b > 0.0 && (a - b).abs() < 0.15 * b, "naive {:?}, light sampled {:?}", naive, sampled);
+        }
+    }
+
+    #[test]
+    fn environment_sampling_converges_to_the_naive_estimate() {
+        // A ball on a floor under a dim map with a bright patch of sky
+        let mut image = Image::new(16, 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                image.set_pixel(x, y, Color::new(0.2, 0.3, 0.4));
+            }
+        }
+        image.set_pixel(5, 2, Color::new(40.0, 30.0, 20.0));
+        let environment = Environment::Map(Arc::new(EnvironmentMap::new(image, 30.0, 1.0)));
+
+        let white = Material::lambertian(Color::new(0.7, 0.7, 0.7));
+        let world = HittableList::new(vec![
+            Box::new(Sphere::new(Point3::new(0.0, -100.0, 0.0), 100.0, white.clone())),
+            Box::new(Sphere::new(Point3::new(0.0, 0.5, -0.5), 0.5, white)),
+        ]);
+        let no_lights = HittableList::new(vec![]);
+        let ray = Ray::new(Point3::new(0.0, 1.0, 3.0), Vec3::new(0.0, -0.6, -1.0), 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        let samples = 10_000;
+        let mut naive = Color::new(0.0, 0.0, 0.0);
+        let mut sampled = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            naive += ray_color(ray, &world, &environment, 4, &mut sampler);
+            sampled += path_color(ray, &world, &no_lights, &environment, 4, &mut sampler);
+        }
+        let (naive, sampled) = (naive / samples as f32, sampled / samples as f32);
+
+        for axis in 0..3 {
+            let (a, b) = (naive.elements[axis], sampled.elements[axis]);
+            assert!(b > 0.0 && (a - b).abs() < 0.2 * b, "naive {:?}, environment sampled {:?}", naive, sampled);
+        }
+    }
+
+    #[test]
+    fn without_lights_path_color_matches_ray_color() {
+        let mut sampler = IndependentSampler::new(0);
+        let light = Material::diffuse_light(Color::new(2.0, 3.0, 4.0));
+        let world = HittableList::new(vec![Box::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, light))]);
+        let no_lights = HittableList::new(vec![]);
+        let solid = Environment::Constant(Color::new(0.1, 0.2, 0.3));
+        let up = Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+
+        assert_eq!(path_color(toward_origin(), &world, &no_lights, &solid, 10, &mut sampler), Color::new(2.0, 3.0, 4.0));
+        assert_eq!(path_color(up, &world, &no_lights, &solid, 10, &mut sampler), Color::new(0.1, 0.2, 0.3));
+        // Like ray_color, a depth of zero gathers nothing at all
+        assert_eq!(path_color(up, &world, &no_lights, &solid, 0, &mut sampler), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn spectral_rendering_keeps_colors_without_dispersion() {
+        let world = HittableList::new(vec![]);
+        let camera = CameraSettings {
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: 1.0,
+            time0: 0.0,
+            time1: 1.0,
+        }.build(2.0);
+        let settings = RenderSettings {
+            image_width: 2,
+            aspect_ratio: 2.0,
+            samples_per_pixel: 10_000,
+            spectral: true,
+            ..RenderSettings::default()
+        };
+        let mut renderer = Renderer::new(settings);
+        renderer.environment = Environment::Constant(Color::new(0.2, 0.5, 0.8));
+
+        let image = renderer.render(&world, &camera);
+        for pixel in image.pixels() {
+            assert!((*pixel - Color::new(0.2, 0.5, 0.8)).length() < 0.02, "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn every_sampler_converges_to_the_same_image() {
+        // Under a dim sky
+        let (world, light) = test_scene();
+        let camera = test_camera(0.1);
+        let render = |sampler: SamplerKind, samples_per_pixel: usize| {
+            let settings = RenderSettings {
+                image_width: 4,
+                aspect_ratio: 2.0,
+                samples_per_pixel,
+                max_depth: 5,
+                sampler,
+                ..RenderSettings::default()
+            };
+            let mut renderer = Renderer::new(settings);
+            renderer.environment = Environment::Constant(Color::new(0.1, 0.1, 0.1));
+            renderer.lights = HittableList::new(vec![Box::new(light.clone())]);
+            let image = renderer.render(&world, &camera);
+            image.pixels().iter().fold(Color::new(0.0, 0.0, 0.0), |total, pixel| total + *pixel) / 8.0
+        };
+
+        let reference = render(SamplerKind::Independent, 4096);
+        for sampler in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+            let average = render(sampler, 256);
+            assert!(
+                (average - reference).length() < 0.03 * reference.length(),
+                "{:?}: {:?} against {:?}", sampler, average, reference,
+            );
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_where_the_noise_is() {
+        // The top two rows see only a flat sky
+        let (world, light) = test_scene();
+        let camera = test_camera(0.0);
+        let render = |adaptive_threshold: Option<f32>| {
+            let settings = RenderSettings {
+                image_width: 16,
+                aspect_ratio: 2.0,
+                samples_per_pixel: 512,
+                max_depth: 5,
+                adaptive_threshold,
+                min_samples_per_pixel: 16,
+                ..RenderSettings::default()
+            };
+            let mut renderer = Renderer::new(settings);
+            renderer.environment = Environment::Constant(Color::new(0.1, 0.1, 0.1));
+            renderer.lights = HittableList::new(vec![Box::new(light.clone())]);
+            renderer.render_with_sample_counts(&world, &camera)
+        };
+
+        let (reference, counts) = render(None);
+        assert!(counts.iter().all(|&count| count == 512));
+
+        let (image, counts) = render(Some(0.05));
+        assert!(counts[..32].iter().all(|&count| count == 16), "{:?}", counts);
+        assert!(counts[32..].iter().any(|&count| count > 64), "{:?}", counts);
+        assert!(counts.iter().all(|&count| count % 16 == 0 && count <= 512), "{:?}", counts);
+        for (pixel, expected) in image.pixels().iter().zip(reference.pixels()) {
+            assert!((*pixel - *expected).length() < 0.1 * expected.length(), "{:?} against {:?}", pixel, expected);
+        }
+    }
+
+    #[test]
+    fn progressive_passes_add_up_to_the_whole_render() {
+        let (world, _) = test_scene();
+        let camera = test_camera(0.1);
+        let settings = RenderSettings {
+            image_width: 8,
+            aspect_ratio: 2.0,
+            samples_per_pixel: 20,
+            max_depth: 5,
+            min_samples_per_pixel: 6,
+            ..RenderSettings::default()
+        };
+
+        let mut seen = vec![];
+        let progress = Renderer::new(settings).render_progressive(&world, &camera, |progress| {
+            seen.push((progress.passes, progress.sample_counts[0]));
+        });
+        assert_eq!(seen, vec![(1, 6), (2, 12), (3, 18)]);
+        assert_eq!(progress.passes, 4);
+        assert!(progress.sample_counts.iter().all(|&count| count == 20));
+
+        let whole = Renderer::new(RenderSettings { min_samples_per_pixel: 20, ..settings }).render(&world, &camera);
+        assert_eq!(progress.image, whole);
+        // Without a threshold or a time limit the samples go in one pass
+        let plain = Renderer::new(settings).render_to_completion(&world, &camera);
+        assert_eq!(plain.passes, 1);
+        assert_eq!(plain.image, progress.image);
+
+        let rushed = RenderSettings { time_limit: Some(Duration::ZERO), ..settings };
+        let progress = Renderer::new(rushed).render_progressive(&world, &camera, |_| panic!("ran out of time"));
+        assert_eq!(progress.passes, 1);
+        assert!(progress.sample_counts.iter().all(|&count| count == 6));
+        assert!((progress.average_samples() - 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn heatmap_runs_from_black_to_white() {
+        let settings = RenderSettings { image_width: 2, aspect_ratio: 1.0, samples_per_pixel: 300, ..RenderSettings::default() };
+        let renderer = Renderer::new(settings);
+        let heatmap = renderer.heatmap(&[0, 100, 200, 300]);
+
+        assert_eq!(heatmap.pixel(0, 0), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(heatmap.pixel(1, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(heatmap.pixel(0, 1), Color::new(1.0, 1.0, 0.0));
+        assert_eq!(heatmap.pixel(1, 1), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn settings_are_validated() {
+        assert_eq!(RenderSettings::default().validate(), Ok(()));
+
+        let key = |settings: RenderSettings| settings.validate().unwrap_err().key;
+        let defaults = RenderSettings::default();
+        assert_eq!(key(RenderSettings { image_width: 1, ..defaults }), "width");
+        assert_eq!(key(RenderSettings { aspect_ratio: f32::NAN, ..defaults }), "aspect_ratio");
+        assert_eq!(key(RenderSettings { aspect_ratio: 300.0, ..defaults }), "aspect_ratio");
+        assert_eq!(key(RenderSettings { samples_per_pixel: 0, ..defaults }), "samples_per_pixel");
+        assert_eq!(key(RenderSettings { adaptive_threshold: Some(0.0), ..defaults }), "adaptive_threshold");
+        assert_eq!(key(RenderSettings { adaptive_threshold: Some(f32::NAN), ..defaults }), "adaptive_threshold");
+        assert_eq!(key(RenderSettings { adaptive_threshold: Some(f32::INFINITY), ..defaults }), "adaptive_threshold");
+        assert_eq!(key(RenderSettings { min_samples_per_pixel: 0, ..defaults }), "min_samples_per_pixel");
+        assert_eq!(key(RenderSettings { time_limit: Some(Duration::ZERO), ..defaults }), "time_limit");
+    }
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        assert_eq!(power_heuristic(2.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert!((power_heuristic(1.0, 3.0) + power_heuristic(3.0, 1.0) - 1.0).abs() < 1e-6);
+    }
+}
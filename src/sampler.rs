@@ -0,0 +1,576 @@
+/// Where the random numbers behind a camera sample come from. A sampler is
+/// moved to one sample of one pixel at a time, then hands out its dimensions
+/// in order: the pixel position, the lens, the time, the wavelength and then
+/// whatever each bounce asks for. Every dimension is decorrelated from the
+/// others, so the well-spread samplers can spread each of them out on its
+/// own; results depend only on the seed, never on how the work is split
+/// between threads.
+pub trait Sampler {
+    /// Moves to sample number `index` of the pixel at (`x`, `y`), back at the
+    /// first dimension.
+    fn start_sample(&mut self, x: usize, y: usize, index: usize);
+
+    /// The next dimension, a number in [0, 1).
+    fn next_1d(&mut self) -> f32;
+
+    /// The next two dimensions, a point in [0, 1)².
+    fn next_2d(&mut self) -> (f32, f32);
+
+    /// A number in [min, max).
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_1d()
+    }
+}
+
+/// The samplers the renderer can use for its camera samples.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SamplerKind {
+    /// Independent random numbers
+    Independent,
+    /// One jittered sample per stratum, in every dimension
+    Stratified,
+    /// The Halton sequence, Owen-scrambled per pixel
+    Halton,
+    /// The Sobol sequence, Owen-scrambled and shuffled per pixel and
+    /// dimension
+    Sobol,
+    /// One Sobol sequence shared between neighbouring pixels, so that they
+    /// make opposite errors
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Names accepted by `by_name`, in the order of the variants.
+    pub const NAMES: &'static [&'static str] = &["independent", "stratified", "halton", "sobol", "blue_noise"];
+
+    pub fn by_name(name: &str) -> Option<SamplerKind> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            "blue_noise" => Some(SamplerKind::BlueNoise),
+            _ => None,
+        }
+    }
+
+    /// A sampler of this kind for pixels taking `samples_per_pixel` samples.
+    /// Only the stratified and blue-noise samplers need that count up front;
+    /// they start afresh for any samples beyond it.
+    pub fn build(self, seed: u64, samples_per_pixel: usize) -> Box<dyn Sampler> {
+        let position = SamplePosition { seed, ..SamplePosition::default() };
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { position, samples: samples_per_pixel.max(1) }),
+            SamplerKind::Halton => Box::new(HaltonSampler { position, fallback: IndependentSampler::new(seed) }),
+            SamplerKind::Sobol => Box::new(SobolSampler { position }),
+            SamplerKind::BlueNoise => {
+                Box::new(BlueNoiseSampler { position, samples: samples_per_pixel.max(1) as u64 })
+            }
+        }
+    }
+}
+
+/// The largest f32 below one.
+const ONE_MINUS_EPSILON: f32 = 0.999_999_94;
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+const INCREMENT: u64 = 1_442_695_040_888_963_407;
+
+/// Scrambles a 64-bit value (the SplitMix64 finalizer), so that nearby
+/// seeds give unrelated streams.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |hash, value| mix(hash ^ value))
+}
+
+/// The top 24 bits of `bits` as a number in [0, 1); they fill an f32
+/// mantissa exactly.
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+/// Element `i` of a random permutation of `0..n` chosen by `seed`, without
+/// building the permutation (Kensler 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+/// The sample a sampler is on, and how many dimensions it has handed out.
+#[derive(Debug, Default, Clone)]
+struct SamplePosition {
+    seed: u64,
+    x: u64,
+    y: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl SamplePosition {
+    fn start(&mut self, x: usize, y: usize, index: usize) {
+        self.x = x as u64;
+        self.y = y as u64;
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    /// A hash of the pixel and the current dimension, which then moves on.
+    fn next_dimension(&mut self) -> u64 {
+        let hash = hash(&[self.seed, self.x, self.y, self.dimension]);
+        self.dimension += 1;
+        hash
+    }
+}
+
+/// A stream of independent uniform random numbers: PCG32 (O'Neill 2014),
+/// small and fast with no shared state.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    state: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        let mut sampler = IndependentSampler { seed, state: 0 };
+        sampler.reseed(seed);
+        sampler
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.state = 0;
+        self.next_u32();
+        self.state = self.state.wrapping_add(mix(seed));
+        self.next_u32();
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.reseed(hash(&[self.seed, x as u64, y as u64, index as u64]));
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        to_unit(self.next_u32())
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let u = self.next_1d();
+        (u, self.next_1d())
+    }
+}
+
+/// Splits every dimension into as many strata as there are samples per
+/// pixel (a grid of about as many for pairs) and puts one jittered sample in
+/// each. The strata are handed out in a different random order in every
+/// dimension, which keeps the dimensions independent of each other.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    position: SamplePosition,
+    samples: usize,
+}
+
+impl StratifiedSampler {
+    /// The stratum this sample takes out of `strata`, and a hash for its
+    /// jitter.
+    fn stratum(&mut self, strata: u64) -> (u64, u64) {
+        let samples = self.samples as u64;
+        let round = self.position.index / samples;
+        let hash = hash(&[self.position.next_dimension(), round]);
+        let stratum = permutation_element((self.position.index % samples) as u32, strata as u32, hash as u32);
+        (stratum as u64, mix(hash ^ self.position.index))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.position.start(x, y, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let samples = self.samples as u64;
+        let (stratum, jitter) = self.stratum(samples);
+        ((stratum as f32 + to_unit(jitter as u32)) / samples as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let columns = (self.samples as f32).sqrt().ceil() as u64;
+        let rows = (self.samples as u64).div_ceil(columns);
+        let (stratum, jitter) = self.stratum(columns * rows);
+        let u = (((stratum % columns) as f32 + to_unit(jitter as u32)) / columns as f32).min(ONE_MINUS_EPSILON);
+        let v = (((stratum / columns) as f32 + to_unit((jitter >> 32) as u32)) / rows as f32).min(ONE_MINUS_EPSILON);
+        (u, v)
+    }
+}
+
+/// The first primes, one Halton base per dimension.
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+/// Reverses the base-`base` digits of `index` about the radix point,
+/// permuting each digit by a hash of `seed` and the digits before it (Owen
+/// scrambling).
+fn owen_scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed = 0u64;
+    let mut scale = 1.0f64;
+    // Enough digits to fill an f32 mantissa
+    while scale > 1e-9 {
+        let digit = index % base;
+        index /= base;
+        let digit = permutation_element(digit as u32, base as u32, mix(seed ^ reversed) as u32) as u64;
+        reversed = reversed * base + digit;
+        scale *= inverse_base;
+    }
+    ((reversed as f64 * scale) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, with each dimension in its own prime base and
+/// Owen-scrambled differently in every pixel. High bases spread samples
+/// poorly, so past the 64th dimension it hands out independent numbers.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    position: SamplePosition,
+    fallback: IndependentSampler,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.position.start(x, y, index);
+        self.fallback.start_sample(x, y, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.position.dimension as usize;
+        if dimension >= PRIMES.len() {
+            return self.fallback.next_1d();
+        }
+        let seed = self.position.next_dimension();
+        owen_scrambled_radical_inverse(PRIMES[dimension], self.position.index, seed)
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let u = self.next_1d();
+        (u, self.next_1d())
+    }
+}
+
+/// The first two dimensions of the Sobol sequence.
+fn sobol(index: u32, dimension: usize) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let (mut result, mut direction, mut index) = (0, 1u32 << 31, index);
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen-scrambles the bits of `value` from the top down, with Burley's
+/// (2020) hash-based version of Laine and Karras' permutation.
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// Point `index` of a two-dimensional Sobol sequence that `seed` both
+/// shuffles and scrambles, so that every seed gives an unrelated set of
+/// equally well spread points.
+fn shuffled_scrambled_sobol(index: u64, seed: u64) -> (f32, f32) {
+    let index = nested_uniform_scramble(index as u32, seed as u32);
+    let u = nested_uniform_scramble(sobol(index, 0), mix(seed ^ 1) as u32);
+    let v = nested_uniform_scramble(sobol(index, 1), mix(seed ^ 2) as u32);
+    (to_unit(u), to_unit(v))
+}
+
+/// The first two Sobol dimensions, shuffled and scrambled anew for every
+/// pixel and dimension (Burley 2020), so any number of dimensions are each
+/// as well spread as the first.
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    position: SamplePosition,
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.position.start(x, y, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        shuffled_scrambled_sobol(self.position.index, self.position.next_dimension()).0
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        shuffled_scrambled_sobol(self.position.index, self.position.next_dimension())
+    }
+}
+
+/// Pixels per side of the tiles `BlueNoiseSampler` orders, as a power of two.
+const TILE_LEVELS: u64 = 6;
+
+/// Blue-noise dithered sampling after Ahmed and Wonka (2020): the pixels of
+/// a tile share one scrambled Sobol sequence per dimension and take
+/// consecutive runs of it in a shuffled Morton order, so every 2x2 block of
+/// pixels, every 4x4 block and so on takes a longer run that is itself well
+/// spread. Each pixel is as well sampled as with `SobolSampler`, but
+/// neighbouring pixels err in opposite directions, which leaves fine-grained
+/// noise that is hard to see.
+#[derive(Debug, Clone)]
+pub struct BlueNoiseSampler {
+    position: SamplePosition,
+    samples: u64,
+}
+
+impl BlueNoiseSampler {
+    /// The point of the shared sequence for the next dimension.
+    fn next_points(&mut self) -> (f32, f32) {
+        let position = &mut self.position;
+        let round = position.index / self.samples;
+        let seed = hash(&[
+            position.seed, position.dimension, position.x >> TILE_LEVELS, position.y >> TILE_LEVELS, round,
+        ]);
+        position.dimension += 1;
+
+        // The pixel's place in the tile, from the largest quadrants down,
+        // with the order of the quadrants shuffled at every level
+        let (mut order, mut prefix) = (0, 1);
+        for level in (0..TILE_LEVELS).rev() {
+            let quadrant = ((position.y >> level) & 1) << 1 | ((position.x >> level) & 1);
+            order = order << 2 | permutation_element(quadrant as u32, 4, mix(seed ^ prefix) as u32) as u64;
+            prefix = prefix << 2 | quadrant;
+        }
+        shuffled_scrambled_sobol(order * self.samples + position.index % self.samples, seed)
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.position.start(x, y, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.next_points().0
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        self.next_points()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// The first `dimensions` values of sample `index` at pixel (`x`, `y`).
+    fn draw(sampler: &mut dyn Sampler, x: usize, y: usize, index: usize, dimensions: usize) -> Vec<f32> {
+        sampler.start_sample(x, y, index);
+        (0..dimensions).map(|_| sampler.next_1d()).collect()
+    }
+
+    #[test]
+    fn streams_repeat_exactly() {
+        for kind in KINDS {
+            let mut sampler = kind.build(7, 16);
+            let first = draw(sampler.as_mut(), 12, 5, 3, 100);
+            draw(sampler.as_mut(), 0, 0, 0, 10);
+            assert_eq!(first, draw(sampler.as_mut(), 12, 5, 3, 100), "{:?}", kind);
+            assert_eq!(first, draw(kind.build(7, 16).as_mut(), 12, 5, 3, 100), "{:?}", kind);
+
+            assert_ne!(first, draw(sampler.as_mut(), 12, 5, 4, 100), "{:?}", kind);
+            assert_ne!(first, draw(sampler.as_mut(), 13, 5, 3, 100), "{:?}", kind);
+            assert_ne!(first, draw(kind.build(8, 16).as_mut(), 12, 5, 3, 100), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn dimensions_are_uniform_and_independent() {
+        // Over many pixels, every dimension is uniform and unrelated to the
+        // ones around it, including past the Halton sampler's primes.
+        for kind in KINDS {
+            let mut sampler = kind.build(1, 16);
+            let dimensions = 80;
+            let mut values = vec![vec![]; dimensions];
+            for pixel in 0..256 {
+                for index in 0..16 {
+                    for (dimension, value) in draw(sampler.as_mut(), pixel % 16, pixel / 16, index, dimensions).into_iter().enumerate() {
+                        assert!((0.0..1.0).contains(&value), "{:?}", kind);
+                        values[dimension].push(value);
+                    }
+                }
+            }
+            let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+            for dimension in 0..dimensions {
+                let a = &values[dimension];
+                assert!((mean(a) - 0.5).abs() < 0.02, "{:?} dimension {}: mean {}", kind, dimension, mean(a));
+                let quarter = a.iter().filter(|&&value| value < 0.25).count() as f32 / a.len() as f32;
+                assert!((quarter - 0.25).abs() < 0.03, "{:?} dimension {}", kind, dimension);
+                if dimension > 0 {
+                    let b = &values[dimension - 1];
+                    let product: Vec<f32> = a.iter().zip(b.iter()).map(|(a, b)| (a - 0.5) * (b - 0.5)).collect();
+                    // Independent dimensions have a covariance of zero; fully
+                    // correlated ones 1/12
+                    assert!(mean(&product).abs() < 0.006, "{:?} dimensions {}: {}", kind, dimension, mean(&product));
+                }
+            }
+        }
+    }
+
+    /// The root-mean-square error of per-pixel estimates of the integral of
+    /// `f` over the unit square, drawn from the fourth pair of dimensions
+    /// as a bounce would be.
+    fn rms_error(kind: SamplerKind, samples: usize, f: impl Fn(f32, f32) -> f32, reference: f32) -> f32 {
+        let mut sampler = kind.build(3, samples);
+        let pixels = 256;
+        let mut squared = 0.0;
+        for pixel in 0..pixels {
+            let mut total = 0.0;
+            for index in 0..samples {
+                sampler.start_sample(pixel % 16, pixel / 16, index);
+                for _ in 0..3 {
+                    sampler.next_2d();
+                }
+                let (u, v) = sampler.next_2d();
+                total += f(u, v);
+            }
+            let error = total / samples as f32 - reference;
+            squared += error * error;
+        }
+        (squared / pixels as f32).sqrt()
+    }
+
+    #[test]
+    fn well_spread_samplers_beat_independent_ones() {
+        // A smooth integrand and one with an edge, as a light's penumbra has
+        let smooth = |u: f32, v: f32| (u * v * 3.0).exp();
+        let smooth_reference = 2.752_668;
+        let disk = |u: f32, v: f32| if u * u + v * v < 1.0 { 1.0 } else { 0.0 };
+        let disk_reference = std::f32::consts::PI / 4.0;
+
+        let samples = 64;
+        let independent = rms_error(SamplerKind::Independent, samples, smooth, smooth_reference);
+        let independent_disk = rms_error(SamplerKind::Independent, samples, disk, disk_reference);
+        for kind in &KINDS[1..] {
+            let error = rms_error(*kind, samples, smooth, smooth_reference);
+            // The Halton sequence's high bases spread pairs poorly at this
+            // sample count
+            let limit = if *kind == SamplerKind::Halton { 0.75 } else { 0.25 };
+            assert!(error < limit * independent, "{:?}: {} against {}", kind, error, independent);
+            let error = rms_error(*kind, samples, disk, disk_reference);
+            assert!(error < 0.75 * independent_disk, "{:?}: {} against {}", kind, error, independent_disk);
+        }
+
+        // Sobol points converge faster than the square root of the count
+        let few = rms_error(SamplerKind::Sobol, 16, smooth, smooth_reference);
+        let many = rms_error(SamplerKind::Sobol, 256, smooth, smooth_reference);
+        assert!(many < few / 8.0, "{} then {}", few, many);
+    }
+
+    #[test]
+    fn blue_noise_pushes_errors_apart() {
+        // The errors of neighbouring pixels cancel out, so averaging blocks
+        // of pixels, as the eye does from afar, leaves far less error than
+        // with plain Sobol points, whose errors are unrelated.
+        let blurred_error = |kind: SamplerKind| {
+            let (size, block, samples) = (32, 4, 4);
+            let mut sampler = kind.build(5, samples);
+            let errors: Vec<f32> = (0..size * size)
+                .map(|pixel| {
+                    let (u, v) = (0..samples)
+                        .map(|index| {
+                            sampler.start_sample(pixel % size, pixel / size, index);
+                            sampler.next_2d()
+                        })
+                        .fold((0.0, 0.0), |(u, v), (du, dv)| (u + du, v + dv));
+                    (u / samples as f32 - 0.5) + (v / samples as f32 - 0.5)
+                })
+                .collect();
+            let rms = |values: &[f32]| (values.iter().map(|e| e * e).sum::<f32>() / values.len() as f32).sqrt();
+            let blocks: Vec<f32> = (0..(size / block) * (size / block))
+                .map(|b| {
+                    let (bx, by) = (b % (size / block) * block, b / (size / block) * block);
+                    let total: f32 = (0..block * block).map(|i| errors[(by + i / block) * size + bx + i % block]).sum();
+                    total / (block * block) as f32
+                })
+                .collect();
+            rms(&blocks) / rms(&errors)
+        };
+        // Unrelated errors would shrink to a quarter
+        assert!(blurred_error(SamplerKind::BlueNoise) < 0.1);
+        assert!(blurred_error(SamplerKind::Sobol) > 0.15);
+    }
+
+    #[test]
+    fn permutations_are_permutations() {
+        for &n in [1, 2, 7, 64, 100].iter() {
+            let mut seen: Vec<u32> = (0..n).map(|i| permutation_element(i, n, 12_345)).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..n).collect::<Vec<u32>>());
+        }
+        assert_eq!(SamplerKind::by_name("blue_noise"), Some(SamplerKind::BlueNoise));
+        assert!(SamplerKind::NAMES.iter().all(|name| SamplerKind::by_name(name).is_some()));
+        assert_eq!(SamplerKind::by_name("random"), None);
+    }
+}
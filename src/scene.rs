@@ -0,0 +1,1533 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use toml::value::{Table, Value};
+
+use crate::camera::CameraSettings;
+use crate::daylight::DaylightSettings;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::hittable::{Hittable, HittableList};
+use crate::instance::Instance;
+use crate::material::Material;
+use crate::medium::ConstantMedium;
+use crate::microfacet::ComplexIor;
+use crate::obj::{ObjError, load_obj};
+use crate::principled::Principled;
+use crate::quad::{BoxShape, Quad};
+use crate::rect::{XyRect, XzRect, YzRect};
+use crate::renderer::{Integrator, RenderSettings};
+use crate::sampler::SamplerKind;
+use crate::spectrum::Ior;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseStyle, NoiseTexture, SolidColor, Texture, WrapMode};
+use crate::transform::Transform;
+use crate::triangle::{MeshError, Triangle, TriangleMesh};
+use crate::utility::cross;
+use crate::vec3::{Color, Point3, Vec3};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Syntax(toml::de::Error),
+    Missing {
+        key: String,
+    },
+    WrongType {
+        key: String,
+        expected: &'static str,
+    },
+    Invalid {
+        key: String,
+        message: String,
+    },
+    Obj {
+        key: String,
+        error: ObjError,
+    },
+}
+
+impl SceneError {
+    /// The dotted path of the key the error points at, if any.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            SceneError::Io { .. } | SceneError::Syntax(_) => None,
+            SceneError::Missing { key }
+            | SceneError::WrongType { key, .. }
+            | SceneError::Invalid { key, .. }
+            | SceneError::Obj { key, .. } => Some(key),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(key) = self.key() {
+            write!(f, "{}: ", key)?;
+        }
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Syntax(error) => write!(f, "{}", error),
+            SceneError::Missing { .. } => write!(f, "missing required key"),
+            SceneError::WrongType { expected, .. } => write!(f, "expected {}", expected),
+            SceneError::Invalid { message, .. } => write!(f, "{}", message),
+            SceneError::Obj { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Syntax(error) => Some(error),
+            SceneError::Obj { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub struct Scene {
+    pub world: HittableList,
+    pub camera_settings: CameraSettings,
+    pub settings: RenderSettings,
+    pub environment: Environment,
+    /// Emitters in `world` that the renderer should sample directly.
+    pub lights: HittableList,
+}
+
+/// Reads a TOML scene description. Paths inside it (OBJ models, textures) are resolved
+/// relative to the scene file.
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path)
+        .map_err(|error| SceneError::Io { path: path.to_path_buf(), error })?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&source, directory)
+}
+
+pub fn parse_scene(source: &str, directory: &Path) -> Result<Scene, SceneError> {
+    let root: Value = source.parse().map_err(SceneError::Syntax)?;
+    let root = match &root {
+        Value::Table(table) => Section::new(table, ""),
+        _ => return Err(SceneError::WrongType { key: String::new(), expected: "a table" }),
+    };
+    root.allow_only(&["background", "render", "camera", "materials", "objects"])?;
+
+    let environment = read_environment(&root, directory)?;
+
+    let settings = match root.optional_table("render")? {
+        Some(render) => read_settings(&render)?,
+        None => RenderSettings::default(),
+    };
+    let camera_settings = read_camera(&root.table("camera")?)?;
+
+    let mut materials = HashMap::new();
+    if let Some(section) = root.optional_table("materials")? {
+        for name in section.table.keys() {
+            let material = read_material(&section.table(name)?, directory)?;
+            materials.insert(name.clone(), material);
+        }
+    }
+
+    let mut objects: Vec<Box<dyn Hittable>> = vec![];
+    let mut lights: Vec<Box<dyn Hittable>> = vec![];
+    for object in root.array_of_tables("objects")? {
+        let shape = read_object(&object, &materials, directory)?;
+        if is_sampled_light(&object, &materials) {
+            let shared: Arc<dyn Hittable> = Arc::from(shape);
+            objects.push(Box::new(shared.clone()));
+            lights.push(Box::new(shared));
+        } else {
+            objects.push(shape);
+        }
+    }
+
+    Ok(Scene {
+        world: HittableList::new(objects),
+        camera_settings,
+        settings,
+        environment,
+        lights: HittableList::new(lights),
+    })
+}
+
+/// Untransformed spheres, quads and rectangles made of `diffuse_light` can
+/// be sampled as lights.
+fn is_sampled_light(object: &Section, materials: &HashMap<String, Material>) -> bool {
+    let samplable = matches!(
+        object.table.get("type").and_then(Value::as_str),
+        Some("sphere" | "quad" | "xy_rect" | "xz_rect" | "yz_rect")
+    );
+    if !samplable || object.table.contains_key("transform") {
+        return false;
+    }
+    match object.table.get("material") {
+        Some(Value::String(name)) => matches!(materials.get(name), Some(Material::DiffuseLight { .. })),
+        Some(Value::Table(material)) => material.get("type").and_then(Value::as_str) == Some("diffuse_light"),
+        _ => false,
+    }
+}
+
+/// `background` is "sky" for the default gradient, a color, or a table
+/// describing a gradient, an HDR environment map or a daylight sky.
+fn read_environment(root: &Section, directory: &Path) -> Result<Environment, SceneError> {
+    let background = match root.table.get("background") {
+        None => return Ok(Environment::SKY),
+        Some(Value::String(name)) if name == "sky" => return Ok(Environment::SKY),
+        Some(Value::String(name)) => {
+            return Err(root.invalid("background", &format!("unknown background '{}'", name)));
+        }
+        Some(Value::Table(_)) => root.table("background")?,
+        Some(_) => return Ok(Environment::Constant(root.vec3("background")?)),
+    };
+
+    match background.str("type")? {
+        "gradient" => {
+            background.allow_only(&["type", "bottom", "top"])?;
+            Ok(Environment::Gradient { bottom: background.vec3("bottom")?, top: background.vec3("top")? })
+        }
+        "map" => {
+            background.allow_only(&["type", "path", "rotation", "intensity"])?;
+            let intensity = background.optional_f32("intensity")?.unwrap_or(1.0);
+            if intensity < 0.0 {
+                return Err(background.invalid("intensity", "must not be negative"));
+            }
+            let rotation = background.optional_f32("rotation")?.unwrap_or(0.0);
+            let path = directory.join(background.str("path")?);
+            let map = EnvironmentMap::load(&path, rotation, intensity)
+                .map_err(|error| background.invalid("path", &format!("{}: {}", path.display(), error)))?;
+            Ok(Environment::Map(Arc::new(map)))
+        }
+        "daylight" => {
+            background.allow_only(&["type", "elevation", "azimuth", "turbidity", "sun_radius", "intensity"])?;
+            let defaults = DaylightSettings::default();
+            let settings = DaylightSettings {
+                elevation: background.optional_f32("elevation")?.unwrap_or(defaults.elevation),
+                azimuth: background.optional_f32("azimuth")?.unwrap_or(defaults.azimuth),
+                turbidity: background.optional_f32("turbidity")?.unwrap_or(defaults.turbidity),
+                sun_radius: background.optional_f32("sun_radius")?.unwrap_or(defaults.sun_radius),
+                intensity: background.optional_f32("intensity")?.unwrap_or(defaults.intensity),
+            };
+            if !(0.0..=90.0).contains(&settings.elevation) {
+                return Err(background.invalid("elevation", "must be between 0 and 90 degrees"));
+            }
+            // The range the sky model was fitted over
+            if !(2.0..=10.0).contains(&settings.turbidity) {
+                return Err(background.invalid("turbidity", "must be between 2 and 10"));
+            }
+            if !(settings.sun_radius > 0.0 && settings.sun_radius < 90.0) {
+                return Err(background.invalid("sun_radius", "must be between 0 and 90 degrees"));
+            }
+            if settings.intensity < 0.0 {
+                return Err(background.invalid("intensity", "must not be negative"));
+            }
+            Ok(Environment::Daylight(settings.build()))
+        }
+        other => Err(background.invalid("type", &format!("unknown background type '{}'", other))),
+    }
+}
+
+fn read_settings(render: &Section) -> Result<RenderSettings, SceneError> {
+    render.allow_only(&[
+        "width", "aspect_ratio", "samples_per_pixel", "max_depth", "integrator", "spectral", "seed", "sampler",
+        "adaptive_threshold", "min_samples_per_pixel", "time_limit",
+    ])?;
+    let defaults = RenderSettings::default();
+
+    let settings = RenderSettings {
+        image_width: render.optional_usize("width")?.unwrap_or(defaults.image_width),
+        aspect_ratio: render.optional_f32("aspect_ratio")?.unwrap_or(defaults.aspect_ratio),
+        samples_per_pixel: render.optional_usize("samples_per_pixel")?.unwrap_or(defaults.samples_per_pixel),
+        max_depth: render.optional_usize("max_depth")?.unwrap_or(defaults.max_depth),
+        integrator: match render.table.get("integrator") {
+            None => defaults.integrator,
+            Some(_) => match render.str("integrator")? {
+                "naive" => Integrator::Naive,
+                "next_event" => Integrator::NextEvent,
+                other => return Err(render.invalid("integrator", &format!("unknown integrator '{}'", other))),
+            },
+        },
+        spectral: render.optional_bool("spectral")?.unwrap_or(defaults.spectral),
+        seed: render.optional_usize("seed")?.map_or(defaults.seed, |seed| seed as u64),
+        sampler: match render.table.get("sampler") {
+            None => defaults.sampler,
+            Some(_) => {
+                let name = render.str("sampler")?;
+                SamplerKind::by_name(name).ok_or_else(|| {
+                    render.invalid("sampler", &format!("unknown sampler '{}' (expected one of {})", name, SamplerKind::NAMES.join(", ")))
+                })?
+            }
+        },
+        adaptive_threshold: render.optional_f32("adaptive_threshold")?,
+        min_samples_per_pixel: render
+            .optional_usize("min_samples_per_pixel")?
+            .unwrap_or(defaults.min_samples_per_pixel),
+        time_limit: match render.optional_f32("time_limit")? {
+            Some(seconds) if seconds > 0.0 => Some(
+                Duration::try_from_secs_f32(seconds).map_err(|error| render.invalid("time_limit", &error.to_string()))?,
+            ),
+            Some(_) => return Err(render.invalid("time_limit", "must be a positive number of seconds")),
+            None => defaults.time_limit,
+        },
+    };
+
+    settings.validate().map_err(|error| render.invalid(error.key, error.message))?;
+    Ok(settings)
+}
+
+fn read_camera(camera: &Section) -> Result<CameraSettings, SceneError> {
+    camera.allow_only(&[
+        "look_from", "look_at", "vup", "vfov",
+        "aperture", "focus_dist", "time0", "time1",
+    ])?;
+
+    let look_from = camera.vec3("look_from")?;
+    let look_at = camera.vec3("look_at")?;
+    let vup = camera.optional_vec3("vup")?.unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0));
+    let vfov = camera.f32("vfov")?;
+    let aperture = camera.optional_f32("aperture")?.unwrap_or(0.0);
+    let focus_dist = camera
+        .optional_f32("focus_dist")?
+        .unwrap_or_else(|| (look_from - look_at).length());
+    let time0 = camera.optional_f32("time0")?.unwrap_or(0.0);
+    let time1 = camera.optional_f32("time1")?.unwrap_or(1.0);
+
+    if look_from == look_at {
+        return Err(camera.invalid("look_at", "must differ from look_from"));
+    }
+    if vfov <= 0.0 || vfov >= 180.0 {
+        return Err(camera.invalid("vfov", "must be between 0 and 180 degrees"));
+    }
+    if time1 <= time0 {
+        return Err(camera.invalid("time1", "must be greater than time0"));
+    }
+
+    Ok(CameraSettings {
+        look_from, look_at, vup,
+        vfov, aperture, focus_dist,
+        time0, time1,
+    })
+}
+
+fn read_material(material: &Section, directory: &Path) -> Result<Material, SceneError> {
+    match material.str("type")? {
+        "lambertian" => {
+            material.allow_only(&["type", "albedo"])?;
+            Ok(Material::Lambertian { albedo: read_texture(material, "albedo", directory)? })
+        }
+        "metal" => {
+            material.allow_only(&["type", "albedo", "fuzz"])?;
+            Ok(Material::Metal {
+                albedo: read_texture(material, "albedo", directory)?,
+                fuzz: material.optional_f32("fuzz")?.unwrap_or(0.0),
+            })
+        }
+        "dielectric" => {
+            // An optional color makes colored glass, reaching that color
+            // after `absorption_distance` units inside
+            material.allow_only(&["type", "index_of_refraction", "color", "absorption_distance"])?;
+            let index_of_refraction = read_ior(material)?;
+            let color = match material.optional_vec3("color")? {
+                Some(color) => color,
+                None if material.table.contains_key("absorption_distance") => {
+                    return Err(material.invalid("absorption_distance", "only applies together with a color"));
+                }
+                None => return Ok(Material::dielectric(index_of_refraction)),
+            };
+            if [color.r(), color.g(), color.b()].iter().any(|c| !(0.0..=1.0).contains(c)) {
+                return Err(material.invalid("color", "components must be between 0 and 1"));
+            }
+            let distance = material.optional_f32("absorption_distance")?.unwrap_or(1.0);
+            if distance <= 0.0 {
+                return Err(material.invalid("absorption_distance", "must be positive"));
+            }
+            Ok(Material::colored_glass(index_of_refraction, color, distance))
+        }
+        "diffuse_light" => {
+            material.allow_only(&["type", "emit"])?;
+            Ok(Material::DiffuseLight { emit: read_texture(material, "emit", directory)? })
+        }
+        "isotropic" => {
+            material.allow_only(&["type", "albedo"])?;
+            Ok(Material::Isotropic { albedo: read_texture(material, "albedo", directory)? })
+        }
+        "conductor" => {
+            // Either a named preset or the complex index of refraction itself
+            material.allow_only(&["type", "preset", "eta", "k", "roughness"])?;
+            let ior = match material.table.get("preset") {
+                Some(_) => {
+                    let name = material.str("preset")?;
+                    ComplexIor::by_name(name)
+                        .ok_or_else(|| material.invalid("preset", &format!("unknown conductor '{}'", name)))?
+                }
+                None => ComplexIor { eta: material.vec3("eta")?, k: material.vec3("k")? },
+            };
+            Ok(Material::Conductor { ior, roughness: read_roughness(material)? })
+        }
+        "principled" => {
+            material.allow_only(&[
+                "type", "base_color", "metallic", "roughness", "specular",
+                "clearcoat", "sheen", "transmission", "index_of_refraction",
+            ])?;
+            let defaults = Principled::new(read_texture(material, "base_color", directory)?);
+            Ok(Material::principled(Principled {
+                metallic: read_scalar_texture(material, "metallic", &defaults.metallic, directory)?,
+                roughness: read_scalar_texture(material, "roughness", &defaults.roughness, directory)?,
+                specular: read_scalar_texture(material, "specular", &defaults.specular, directory)?,
+                clearcoat: read_scalar_texture(material, "clearcoat", &defaults.clearcoat, directory)?,
+                sheen: read_scalar_texture(material, "sheen", &defaults.sheen, directory)?,
+                transmission: read_scalar_texture(material, "transmission", &defaults.transmission, directory)?,
+                index_of_refraction: material
+                    .optional_f32("index_of_refraction")?
+                    .unwrap_or(defaults.index_of_refraction),
+                ..defaults
+            }))
+        }
+        "rough_dielectric" => {
+            material.allow_only(&["type", "index_of_refraction", "roughness"])?;
+            Ok(Material::RoughDielectric {
+                index_of_refraction: material.f32("index_of_refraction")?,
+                roughness: read_roughness(material)?,
+            })
+        }
+        other => Err(material.invalid("type", &format!("unknown material type '{}'", other))),
+    }
+}
+
+/// A color parameter is either a constant `[r, g, b]` or a texture table.
+fn read_texture(parent: &Section, key: &str, directory: &Path) -> Result<Arc<dyn Texture>, SceneError> {
+    let texture = match parent.get(key)? {
+        Value::Table(_) => parent.table(key)?,
+        _ => return Ok(Arc::new(SolidColor::new(parent.vec3(key)?))),
+    };
+
+    match texture.str("type")? {
+        "checker" => {
+            texture.allow_only(&["type", "even", "odd", "scale"])?;
+            let scale = texture.optional_f32("scale")?.unwrap_or(1.0);
+            if scale <= 0.0 {
+                return Err(texture.invalid("scale", "must be positive"));
+            }
+            Ok(Arc::new(CheckerTexture::new(
+                read_texture(&texture, "even", directory)?,
+                read_texture(&texture, "odd", directory)?,
+                scale,
+            )))
+        }
+        "image" => {
+            texture.allow_only(&["type", "path", "wrap"])?;
+            let wrap = match texture.table.get("wrap") {
+                None => WrapMode::Repeat,
+                Some(_) => match texture.str("wrap")? {
+                    "repeat" => WrapMode::Repeat,
+                    "clamp" => WrapMode::Clamp,
+                    "mirror" => WrapMode::Mirror,
+                    other => return Err(texture.invalid("wrap", &format!("unknown wrap mode '{}'", other))),
+                },
+            };
+            let path = directory.join(texture.str("path")?);
+            let image = ImageTexture::load(&path, wrap)
+                .map_err(|error| texture.invalid("path", &format!("{}: {}", path.display(), error)))?;
+            Ok(Arc::new(image))
+        }
+        "noise" => {
+            texture.allow_only(&["type", "color", "scale", "style"])?;
+            let style = match texture.table.get("style") {
+                None => NoiseStyle::Smooth,
+                Some(_) => match texture.str("style")? {
+                    "smooth" => NoiseStyle::Smooth,
+                    "turbulence" => NoiseStyle::Turbulence,
+                    "marble" => NoiseStyle::Marble,
+                    other => return Err(texture.invalid("style", &format!("unknown noise style '{}'", other))),
+                },
+            };
+            Ok(Arc::new(NoiseTexture::new(
+                texture.optional_vec3("color")?.unwrap_or_else(|| Color::new(1.0, 1.0, 1.0)),
+                texture.optional_f32("scale")?.unwrap_or(1.0),
+                style,
+            )))
+        }
+        other => Err(texture.invalid("type", &format!("unknown texture type '{}'", other))),
+    }
+}
+
+fn read_roughness(material: &Section) -> Result<f32, SceneError> {
+    let roughness = material.optional_f32("roughness")?.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&roughness) {
+        return Err(material.invalid("roughness", "must be between 0 and 1"));
+    }
+    Ok(roughness)
+}
+
+/// A dielectric's index of refraction: a number, the name of an `Ior`
+/// preset, or a Cauchy or Sellmeier table for dispersion.
+fn read_ior(material: &Section) -> Result<Ior, SceneError> {
+    let key = "index_of_refraction";
+    match material.get(key)? {
+        Value::String(_) => {
+            let name = material.str(key)?;
+            Ior::by_name(name).ok_or_else(|| material.invalid(key, &format!("unknown glass '{}'", name)))
+        }
+        Value::Table(_) => {
+            let ior = material.table(key)?;
+            match ior.str("type")? {
+                "cauchy" => {
+                    ior.allow_only(&["type", "a", "b"])?;
+                    Ok(Ior::Cauchy { a: ior.f32("a")?, b: ior.f32("b")? })
+                }
+                "sellmeier" => {
+                    ior.allow_only(&["type", "b", "c"])?;
+                    Ok(Ior::Sellmeier { b: ior.vec3("b")?.elements, c: ior.vec3("c")?.elements })
+                }
+                other => Err(ior.invalid("type", &format!("unknown dispersion model '{}'", other))),
+            }
+        }
+        _ => Ok(Ior::Constant(material.f32(key)?)),
+    }
+}
+
+/// A slider of the principled material: a number in [0, 1], or any texture.
+fn read_scalar_texture(
+    material: &Section, key: &str, default: &Arc<dyn Texture>, directory: &Path,
+) -> Result<Arc<dyn Texture>, SceneError> {
+    match material.table.get(key) {
+        None => Ok(default.clone()),
+        Some(Value::Integer(_)) | Some(Value::Float(_)) => {
+            let value = material.f32(key)?;
+            if !(0.0..=1.0).contains(&value) {
+                return Err(material.invalid(key, "must be between 0 and 1"));
+            }
+            Ok(Arc::new(SolidColor::new(Color::new(value, value, value))))
+        }
+        Some(_) => read_texture(material, key, directory),
+    }
+}
+
+/// A material is either the name of an entry in `[materials]` or an inline table.
+fn object_material(
+    object: &Section, key: &str, materials: &HashMap<String, Material>, directory: &Path,
+) -> Result<Material, SceneError> {
+    match object.table.get(key) {
+        Some(Value::String(name)) => materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| object.invalid(key, &format!("unknown material '{}'", name))),
+        Some(Value::Table(_)) => read_material(&object.table(key)?, directory),
+        Some(_) => Err(SceneError::WrongType {
+            key: object.key(key),
+            expected: "a material name or table",
+        }),
+        None => Err(SceneError::Missing { key: object.key(key) }),
+    }
+}
+
+/// Any object may carry a `transform` list, which places it through an
+/// `Instance`.
+fn read_object(
+    object: &Section, materials: &HashMap<String, Material>, directory: &Path,
+) -> Result<Box<dyn Hittable>, SceneError> {
+    if !object.table.contains_key("transform") {
+        return read_shape(object, materials, directory);
+    }
+    let transform = read_transform(object, "transform")?;
+
+    let mut shape = object.table.clone();
+    shape.remove("transform");
+    let shape = read_shape(&Section::new(&shape, &object.path), materials, directory)?;
+    Ok(Box::new(Instance::new(Arc::from(shape), transform)))
+}
+
+/// A list of steps applied in order, each a table with a single key:
+/// `translate = [x, y, z]`, `scale = s` or `[x, y, z]`, or
+/// `rotate_x`/`rotate_y`/`rotate_z` in degrees.
+fn read_transform(object: &Section, key: &str) -> Result<Transform, SceneError> {
+    let mut transform = Transform::identity();
+    for step in object.array_of_tables(key)? {
+        let name = match step.table.keys().next() {
+            Some(name) if step.table.len() == 1 => name.as_str(),
+            _ => return Err(SceneError::Invalid {
+                key: step.path.clone(),
+                message: "each transform step needs exactly one key".to_string(),
+            }),
+        };
+        let next = match name {
+            "translate" => Transform::translate(step.vec3(name)?),
+            "scale" => {
+                let factors = match step.get(name)? {
+                    Value::Array(_) => step.vec3(name)?,
+                    _ => {
+                        let factor = step.f32(name)?;
+                        Vec3::new(factor, factor, factor)
+                    }
+                };
+                if factors.elements.contains(&0.0) {
+                    return Err(step.invalid(name, "scale factors must be non-zero"));
+                }
+                Transform::scale(factors)
+            }
+            "rotate_x" => Transform::rotate_x(step.f32(name)?),
+            "rotate_y" => Transform::rotate_y(step.f32(name)?),
+            "rotate_z" => Transform::rotate_z(step.f32(name)?),
+            other => return Err(step.invalid(other, "unknown transform step")),
+        };
+        transform = transform.then(&next);
+    }
+    Ok(transform)
+}
+
+fn read_shape(
+    object: &Section, materials: &HashMap<String, Material>, directory: &Path,
+) -> Result<Box<dyn Hittable>, SceneError> {
+    match object.str("type")? {
+        "sphere" => {
+            object.allow_only(&["type", "center", "radius", "material"])?;
+            Ok(Box::new(Sphere::new(
+                object.vec3("center")?,
+                object.f32("radius")?,
+                object_material(object, "material", materials, directory)?,
+            )))
+        }
+        "moving_sphere" => {
+            object.allow_only(&["type", "center0", "center1", "time0", "time1", "radius", "material"])?;
+            let time0 = object.f32("time0")?;
+            let time1 = object.f32("time1")?;
+            if time1 <= time0 {
+                return Err(object.invalid("time1", "must be greater than time0"));
+            }
+            Ok(Box::new(MovingSphere::new(
+                object.vec3("center0")?,
+                object.vec3("center1")?,
+                time0,
+                time1,
+                object.f32("radius")?,
+                object_material(object, "material", materials, directory)?,
+            )))
+        }
+        "triangle" => {
+            object.allow_only(&["type", "vertices", "material"])?;
+            let vertices = object.vec3_list("vertices")?;
+            if vertices.len() != 3 {
+                return Err(object.invalid("vertices", "a triangle needs exactly three vertices"));
+            }
+            Ok(Box::new(Triangle::new(
+                vertices[0], vertices[1], vertices[2],
+                object_material(object, "material", materials, directory)?,
+            )))
+        }
+        "xy_rect" | "xz_rect" | "yz_rect" => read_rect(object, materials, directory),
+        "quad" => {
+            object.allow_only(&["type", "corner", "u", "v", "material"])?;
+            let (u, v) = (object.vec3("u")?, object.vec3("v")?);
+            if cross(u, v).near_zero() {
+                return Err(object.invalid("v", "must not be parallel to u"));
+            }
+            Ok(Box::new(Quad::new(
+                object.vec3("corner")?,
+                u,
+                v,
+                object_material(object, "material", materials, directory)?,
+            )))
+        }
+        "box" => {
+            object.allow_only(&["type", "min", "max", "material"])?;
+            Ok(Box::new(BoxShape::new(
+                object.vec3("min")?,
+                object.vec3("max")?,
+                object_material(object, "material", materials, directory)?,
+            )))
+        }
+        "constant_medium" => {
+            // The boundary is any other object; only its shape matters.
+            object.allow_only(&["type", "boundary", "density", "albedo"])?;
+            let boundary = read_object(&object.table("boundary")?, materials, directory)?;
+            let density = object.f32("density")?;
+            if density <= 0.0 {
+                return Err(object.invalid("density", "must be positive"));
+            }
+            Ok(Box::new(ConstantMedium::new(
+                Arc::from(boundary),
+                density,
+                read_texture(object, "albedo", directory)?,
+            )))
+        }
+        "mesh" => {
+            object.allow_only(&["type", "positions", "indices", "normals", "uvs", "material"])?;
+            read_mesh(object, materials, directory)
+        }
+        "obj" => {
+            object.allow_only(&["type", "path", "material"])?;
+            let path = directory.join(object.str("path")?);
+            let default_material = match object.table.get("material") {
+                Some(_) => object_material(object, "material", materials, directory)?,
+                None => Material::lambertian(Color::new(0.5, 0.5, 0.5)),
+            };
+            let list = load_obj(&path, default_material)
+                .map_err(|error| SceneError::Obj { key: object.key("path"), error })?;
+            Ok(Box::new(list))
+        }
+        other => Err(object.invalid("type", &format!("unknown object type '{}'", other))),
+    }
+}
+
+/// Axis-aligned rectangles name their two in-plane axes in the type, e.g. an
+/// `xz_rect` takes `x0`, `x1`, `z0`, `z1` and its height `k`.
+fn read_rect(
+    object: &Section, materials: &HashMap<String, Material>, directory: &Path,
+) -> Result<Box<dyn Hittable>, SceneError> {
+    let kind = object.str("type")?;
+    let (a, b) = (&kind[0..1], &kind[1..2]);
+    let keys = [format!("{}0", a), format!("{}1", a), format!("{}0", b), format!("{}1", b)];
+    object.allow_only(&["type", &keys[0], &keys[1], &keys[2], &keys[3], "k", "material"])?;
+
+    let mut bounds = [0.0; 4];
+    for (bound, key) in bounds.iter_mut().zip(keys.iter()) {
+        *bound = object.f32(key)?;
+    }
+    if bounds[1] <= bounds[0] {
+        return Err(object.invalid(&keys[1], &format!("must be greater than {}", keys[0])));
+    }
+    if bounds[3] <= bounds[2] {
+        return Err(object.invalid(&keys[3], &format!("must be greater than {}", keys[2])));
+    }
+    let [a0, a1, b0, b1] = bounds;
+    let k = object.f32("k")?;
+    let material = object_material(object, "material", materials, directory)?;
+
+    Ok(match kind {
+        "xy_rect" => Box::new(XyRect::new(a0, a1, b0, b1, k, material)),
+        "xz_rect" => Box::new(XzRect::new(a0, a1, b0, b1, k, material)),
+        _ => Box::new(YzRect::new(a0, a1, b0, b1, k, material)),
+    })
+}
+
+fn read_mesh(
+    object: &Section, materials: &HashMap<String, Material>, directory: &Path,
+) -> Result<Box<dyn Hittable>, SceneError> {
+    let positions = object.vec3_list("positions")?;
+
+    let indices = match object.table.get("indices") {
+        Some(Value::Array(faces)) => {
+            let mut indices = vec![];
+            for (i, face) in faces.iter().enumerate() {
+                let key = format!("{}[{}]", object.key("indices"), i);
+                let face = match face {
+                    Value::Array(face) if face.len() == 3 => face,
+                    _ => return Err(SceneError::WrongType { key, expected: "an array of three indices" }),
+                };
+                let mut triangle = [0; 3];
+                for (corner, value) in triangle.iter_mut().zip(face) {
+                    *corner = match value {
+                        Value::Integer(index) if *index >= 0 && (*index as usize) < positions.len() => *index as usize,
+                        Value::Integer(_) => {
+                            return Err(SceneError::Invalid { key, message: "index out of range".to_string() })
+                        }
+                        _ => return Err(SceneError::WrongType { key, expected: "an array of three indices" }),
+                    };
+                }
+                indices.push(triangle);
+            }
+            indices
+        }
+        Some(_) => return Err(SceneError::WrongType { key: object.key("indices"), expected: "an array" }),
+        None => return Err(SceneError::Missing { key: object.key("indices") }),
+    };
+
+    let normals = match object.table.get("normals") {
+        Some(_) => Some(object.vec3_list("normals")?),
+        None => None,
+    };
+
+    let uvs = match object.table.get("uvs") {
+        Some(_) => {
+            let mut uvs = vec![];
+            for (i, value) in object.array("uvs")?.iter().enumerate() {
+                let key = format!("{}[{}]", object.key("uvs"), i);
+                match number_list(value, 2) {
+                    Some(uv) => uvs.push((uv[0], uv[1])),
+                    None => return Err(SceneError::WrongType { key, expected: "an array of two numbers" }),
+                }
+            }
+            Some(uvs)
+        }
+        None => None,
+    };
+
+    let material = object_material(object, "material", materials, directory)?;
+    match TriangleMesh::new(positions, indices, normals, uvs, material) {
+        Ok(mesh) => Ok(Box::new(mesh)),
+        Err(error) => {
+            let key = match error {
+                MeshError::NoTriangles | MeshError::IndexOutOfRange { .. } => "indices",
+                MeshError::NormalCount { .. } => "normals",
+                MeshError::UvCount { .. } => "uvs",
+            };
+            Err(object.invalid(key, &error.to_string()))
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<f32> {
+    match value {
+        Value::Float(number) => Some(*number as f32),
+        Value::Integer(number) => Some(*number as f32),
+        _ => None,
+    }
+}
+
+fn number_list(value: &Value, length: usize) -> Option<Vec<f32>> {
+    match value {
+        Value::Array(values) if values.len() == length => values.iter().map(number).collect(),
+        _ => None,
+    }
+}
+
+/// A TOML table together with its dotted path from the root, so every
+/// error can name the key it came from.
+struct Section<'a> {
+    table: &'a Table,
+    path: String,
+}
+
+impl<'a> Section<'a> {
+    fn new(table: &'a Table, path: &str) -> Section<'a> {
+        Section { table, path: path.to_string() }
+    }
+
+    fn key(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn invalid(&self, key: &str, message: &str) -> SceneError {
+        SceneError::Invalid { key: self.key(key), message: message.to_string() }
+    }
+
+    fn allow_only(&self, keys: &[&str]) -> Result<(), SceneError> {
+        match self.table.keys().find(|key| !keys.contains(&key.as_str())) {
+            Some(unknown) => Err(self.invalid(unknown, "unknown key")),
+            None => Ok(()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<&'a Value, SceneError> {
+        self.table.get(key).ok_or_else(|| SceneError::Missing { key: self.key(key) })
+    }
+
+    fn table(&self, key: &str) -> Result<Section<'a>, SceneError> {
+        match self.get(key)? {
+            Value::Table(table) => Ok(Section::new(table, &self.key(key))),
+            _ => Err(SceneError::WrongType { key: self.key(key), expected: "a table" }),
+        }
+    }
+
+    fn optional_table(&self, key: &str) -> Result<Option<Section<'a>>, SceneError> {
+        match self.table.get(key) {
+            Some(_) => self.table(key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn array(&self, key: &str) -> Result<&'a Vec<Value>, SceneError> {
+        match self.get(key)? {
+            Value::Array(values) => Ok(values),
+            _ => Err(SceneError::WrongType { key: self.key(key), expected: "an array" }),
+        }
+    }
+
+    fn array_of_tables(&self, key: &str) -> Result<Vec<Section<'a>>, SceneError> {
+        if !self.table.contains_key(key) {
+            return Ok(vec![]);
+        }
+        self.array(key)?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let path = format!("{}[{}]", self.key(key), i);
+                match value {
+                    Value::Table(table) => Ok(Section::new(table, &path)),
+                    _ => Err(SceneError::WrongType { key: path, expected: "a table" }),
+                }
+            })
+            .collect()
+    }
+
+    fn str(&self, key: &str) -> Result<&'a str, SceneError> {
+        match self.get(key)? {
+            Value::String(value) => Ok(value),
+            _ => Err(SceneError::WrongType { key: self.key(key), expected: "a string" }),
+        }
+    }
+
+    fn f32(&self, key: &str) -> Result<f32, SceneError> {
+        number(self.get(key)?).ok_or_else(|| SceneError::WrongType { key: self.key(key), expected: "a number" })
+    }
+
+    fn optional_f32(&self, key: &str) -> Result<Option<f32>, SceneError> {
+        match self.table.get(key) {
+            Some(_) => self.f32(key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn optional_bool(&self, key: &str) -> Result<Option<bool>, SceneError> {
+        match self.table.get(key) {
+            Some(Value::Boolean(value)) => Ok(Some(*value)),
+            Some(_) => Err(SceneError::WrongType { key: self.key(key), expected: "true or false" }),
+            None => Ok(None),
+        }
+    }
+
+    fn optional_usize(&self, key: &str) -> Result<Option<usize>, SceneError> {
+        match self.table.get(key) {
+            Some(Value::Integer(value)) if *value >= 0 => Ok(Some(*value as usize)),
+            Some(_) => Err(SceneError::WrongType { key: self.key(key), expected: "a non-negative integer" }),
+            None => Ok(None),
+        }
+    }
+
+    fn vec3(&self, key: &str) -> Result<Vec3, SceneError> {
+        number_list(self.get(key)?, 3)
+            .map(|values| Vec3::new(values[0], values[1], values[2]))
+            .ok_or_else(|| SceneError::WrongType { key: self.key(key), expected: "an array of three numbers" })
+    }
+
+    fn optional_vec3(&self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        match self.table.get(key) {
+            Some(_) => self.vec3(key).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn vec3_list(&self, key: &str) -> Result<Vec<Point3>, SceneError> {
+        self.array(key)?
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                number_list(value, 3)
+                    .map(|values| Vec3::new(values[0], values[1], values[2]))
+                    .ok_or_else(|| SceneError::WrongType {
+                        key: format!("{}[{}]", self.key(key), i),
+                        expected: "an array of three numbers",
+                    })
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::image::Image;
+    use crate::radiance::write_hdr;
+    use crate::utility::INFINITY;
+    use crate::ray::Ray;
+
+    const SCENE: &str = r#"
+        [render]
+        width = 200
+        aspect_ratio = 2.0
+        samples_per_pixel = 8
+        max_depth = 10
+        integrator = "naive"
+        spectral = true
+        seed = 42
+        sampler = "halton"
+        adaptive_threshold = 0.05
+        min_samples_per_pixel = 4
+        time_limit = 90
+
+        [camera]
+        look_from = [0, 0, 5]
+        look_at = [0, 0, 0]
+        vfov = 40
+
+        [materials.ground]
+        type = "lambertian"
+        albedo = [0.5, 0.5, 0.5]
+
+        [[objects]]
+        type = "sphere"
+        center = [0, -100.5, 0]
+        radius = 100
+        material = "ground"
+
+        [[objects]]
+        type = "moving_sphere"
+        center0 = [0, 0, 0]
+        center1 = [0, 0.5, 0]
+        time0 = 0
+        time1 = 1
+        radius = 0.5
+        material = { type = "metal", albedo = [0.8, 0.8, 0.8], fuzz = 0.1 }
+
+        [[objects]]
+        type = "mesh"
+        positions = [[2, 0, 0], [3, 0, 0], [2, 1, 0]]
+        indices = [[0, 1, 2]]
+        material = { type = "dielectric", index_of_refraction = 1.5 }
+    "#;
+
+    const CAMERA: &str = "[camera]\nlook_from = [0, 0, 5]\nlook_at = [0, 0, 0]\nvfov = 40\n";
+
+    fn error_key(source: &str) -> String {
+        match parse_scene(source, Path::new("")) {
+            Ok(_) => panic!("expected an error for:\n{}", source),
+            Err(error) => error.key().expect("error should point at a key").to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_full_scene() {
+        let scene = parse_scene(SCENE, Path::new("")).unwrap();
+
+        assert_eq!(scene.settings, RenderSettings {
+            image_width: 200,
+            aspect_ratio: 2.0,
+            samples_per_pixel: 8,
+            max_depth: 10,
+            integrator: Integrator::Naive,
+            spectral: true,
+            seed: 42,
+            sampler: SamplerKind::Halton,
+            adaptive_threshold: Some(0.05),
+            min_samples_per_pixel: 4,
+            time_limit: Some(Duration::from_secs(90)),
+        });
+        assert_eq!(scene.settings.image_height(), 100);
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 4.5);
+        assert!(matches!(hit.material, Material::Metal { .. }));
+
+        let ray = Ray::new(Point3::new(2.2, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        assert!(matches!(hit.material, Material::Dielectric { .. }));
+    }
+
+    #[test]
+    fn background_and_lights() {
+        let scene = parse_scene(
+            "background = [0, 0, 0]\n\n[camera]\nlook_from = [0, 0, 5]\nlook_at = [0, 0, 0]\nvfov = 40\n\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = { type = \"diffuse_light\", emit = [4, 4, 4] }\n",
+            Path::new(""),
+        ).unwrap();
+
+        assert_eq!(scene.environment, Environment::Constant(Color::new(0.0, 0.0, 0.0)));
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.material.emitted(hit.u, hit.v, hit.p), Color::new(4.0, 4.0, 4.0));
+
+        assert_eq!(error_key("background = \"night\"\n"), "background");
+    }
+
+    #[test]
+    fn emitting_shapes_are_sampled_as_lights() {
+        let scene = parse_scene(r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+
+            [[objects]]
+            type = "xz_rect"
+            x0 = -1
+            x1 = 1
+            z0 = -1
+            z1 = 1
+            k = 3
+            material = "lamp"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+
+            # Instances can't be sampled, so this one is only found by chance
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "lamp"
+            transform = [{ translate = [5, 0, 0] }]
+        "#, Path::new("")).unwrap();
+
+        assert_eq!(scene.world.len(), 3);
+        assert_eq!(scene.lights.len(), 1);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        assert!(scene.lights.pdf_value(origin, scene.lights.random(origin, &mut IndependentSampler::new(0))) > 0.0);
+
+        assert_eq!(error_key("[render]\nintegrator = \"bidirectional\"\n"), "render.integrator");
+    }
+
+    #[test]
+    fn rough_materials() {
+        let scene = parse_scene(r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [materials.gold]
+            type = "conductor"
+            preset = "gold"
+            roughness = 0.2
+
+            [materials.custom]
+            type = "conductor"
+            eta = [0.2, 0.9, 1.1]
+            k = [3.9, 2.4, 2.1]
+
+            [materials.frosted]
+            type = "rough_dielectric"
+            index_of_refraction = 1.5
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "gold"
+
+            [[objects]]
+            type = "sphere"
+            center = [3, 0, 0]
+            radius = 1
+            material = "frosted"
+        "#, Path::new("")).unwrap();
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        assert!(matches!(hit.material, Material::Conductor { ior, roughness } if *ior == ComplexIor::GOLD && *roughness == 0.2));
+
+        let ray = Ray::new(Point3::new(3.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        assert!(matches!(hit.material, Material::RoughDielectric { roughness, .. } if *roughness == 0.5));
+
+        assert_eq!(
+            error_key(&format!("{}[materials.m]\ntype = \"conductor\"\npreset = \"tin\"\n", CAMERA)),
+            "materials.m.preset"
+        );
+        assert_eq!(
+            error_key(&format!("{}[materials.m]\ntype = \"rough_dielectric\"\nindex_of_refraction = 1.5\nroughness = 2\n", CAMERA)),
+            "materials.m.roughness"
+        );
+    }
+
+    #[test]
+    fn environments() {
+        let up = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+
+        let scene = parse_scene(
+            &format!("background = {{ type = \"gradient\", bottom = [0, 0, 0], top = [1, 2, 3] }}\n{}", CAMERA),
+            Path::new(""),
+        ).unwrap();
+        assert_eq!(scene.environment.color(up), Color::new(1.0, 2.0, 3.0));
+
+        // A map, read relative to the scene's directory
+        let directory = std::env::temp_dir();
+        let image = Image::from_pixels(2, 1, vec![Color::new(1.0, 1.0, 1.0), Color::new(4.0, 0.0, 0.0)]);
+        let mut file = fs::File::create(directory.join("river_scene_environment.hdr")).unwrap();
+        write_hdr(&image, &mut file).unwrap();
+        let scene = parse_scene(
+            &format!(
+                "background = {{ type = \"map\", path = \"river_scene_environment.hdr\", intensity = 0.5 }}\n{}",
+                CAMERA,
+            ),
+            &directory,
+        ).unwrap();
+        assert!(matches!(scene.environment, Environment::Map(_)));
+        let behind = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!((scene.environment.color(behind) - Color::new(0.5, 0.5, 0.5)).length() < 0.01);
+        assert!(scene.environment.random(&mut IndependentSampler::new(0)).is_some());
+
+        assert_eq!(error_key(&format!("background = {{ type = \"map\", path = \"missing.hdr\" }}\n{}", CAMERA)),
+            "background.path");
+        assert_eq!(error_key(&format!("background = {{ type = \"cube\" }}\n{}", CAMERA)), "background.type");
+
+        let scene = parse_scene(
+            &format!("background = {{ type = \"daylight\", elevation = 20, azimuth = 90, turbidity = 4 }}\n{}", CAMERA),
+            Path::new(""),
+        ).unwrap();
+        let expected = DaylightSettings { elevation: 20.0, azimuth: 90.0, turbidity: 4.0, ..DaylightSettings::default() };
+        assert_eq!(scene.environment, Environment::Daylight(expected.build()));
+        assert_eq!(error_key(&format!("background = {{ type = \"daylight\", elevation = -5 }}\n{}", CAMERA)),
+            "background.elevation");
+        assert_eq!(error_key(&format!("background = {{ type = \"daylight\", turbidity = 1 }}\n{}", CAMERA)),
+            "background.turbidity");
+    }
+
+    #[test]
+    fn colored_glass() {
+        let scene = parse_scene(r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "dielectric", index_of_refraction = 1.5, color = [0.5, 1, 1], absorption_distance = 2 }
+        "#, Path::new("")).unwrap();
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        match hit.material {
+            Material::Dielectric { absorption, .. } => {
+                assert!((absorption.r() - 0.5f32.ln() / -2.0).abs() < 1e-6);
+                assert_eq!(absorption.g(), 0.0);
+            }
+            _ => panic!("expected a dielectric"),
+        }
+
+        let glass = "[materials.m]\ntype = \"dielectric\"\nindex_of_refraction = 1.5\n";
+        assert_eq!(error_key(&format!("{}{}color = [1.5, 1, 1]\n", CAMERA, glass)), "materials.m.color");
+        assert_eq!(
+            error_key(&format!("{}{}color = [0.5, 1, 1]\nabsorption_distance = 0\n", CAMERA, glass)),
+            "materials.m.absorption_distance"
+        );
+        assert_eq!(
+            error_key(&format!("{}{}absorption_distance = 2\n", CAMERA, glass)),
+            "materials.m.absorption_distance"
+        );
+    }
+
+    #[test]
+    fn dispersive_glass() {
+        let scene = parse_scene(r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [materials.prism]
+            type = "dielectric"
+            index_of_refraction = "bk7"
+
+            [materials.cauchy]
+            type = "dielectric"
+            index_of_refraction = { type = "cauchy", a = 1.5, b = 0.004 }
+
+            [materials.sellmeier]
+            type = "dielectric"
+            index_of_refraction = { type = "sellmeier", b = [0.3306, 4.3356, 0], c = [0.030625, 0.011236, 0] }
+
+            [[objects]]
+            type = "sphere"
+            center = [-3, 0, 0]
+            radius = 1
+            material = "prism"
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "cauchy"
+
+            [[objects]]
+            type = "sphere"
+            center = [3, 0, 0]
+            radius = 1
+            material = "sellmeier"
+        "#, Path::new("")).unwrap();
+
+        let ior_at = |x: f32| {
+            let ray = Ray::new(Point3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            match scene.world.hit(ray, 0.001, INFINITY).unwrap().material {
+                Material::Dielectric { index_of_refraction, .. } => *index_of_refraction,
+                _ => panic!("expected a dielectric"),
+            }
+        };
+        assert_eq!(ior_at(-3.0), Ior::BK7);
+        assert_eq!(ior_at(0.0), Ior::Cauchy { a: 1.5, b: 0.004 });
+        assert_eq!(ior_at(3.0), Ior::DIAMOND);
+
+        assert_eq!(
+            error_key(&format!("{}[materials.m]\ntype = \"dielectric\"\nindex_of_refraction = \"flint\"\n", CAMERA)),
+            "materials.m.index_of_refraction"
+        );
+        assert_eq!(
+            error_key(&format!(
+                "{}[materials.m]\ntype = \"dielectric\"\nindex_of_refraction = {{ type = \"abbe\", a = 1 }}\n", CAMERA
+            )),
+            "materials.m.index_of_refraction.type"
+        );
+        assert_eq!(error_key("[render]\nspectral = 1\n"), "render.spectral");
+    }
+
+    #[test]
+    fn principled_sliders_take_numbers_or_textures() {
+        let scene = parse_scene(r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "principled", base_color = [0.8, 0.1, 0.1], metallic = 1, clearcoat = 0.5, roughness = { type = "noise", scale = 4 } }
+        "#, Path::new("")).unwrap();
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        match hit.material {
+            Material::Principled(principled) => {
+                assert_eq!(principled.base_color.value(0.0, 0.0, hit.p), Color::new(0.8, 0.1, 0.1));
+                assert_eq!(principled.metallic.value(0.0, 0.0, hit.p), Color::new(1.0, 1.0, 1.0));
+                assert_eq!(principled.transmission.value(0.0, 0.0, hit.p), Color::new(0.0, 0.0, 0.0));
+                assert_eq!(principled.index_of_refraction, 1.5);
+            }
+            _ => panic!("expected a principled material"),
+        }
+
+        assert_eq!(
+            error_key(&format!("{}[materials.m]\ntype = \"principled\"\nbase_color = [1, 1, 1]\nsheen = 3\n", CAMERA)),
+            "materials.m.sheen"
+        );
+    }
+
+    #[test]
+    fn colors_can_be_textures() {
+        let scene = parse_scene(
+            &format!(
+                "{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = {{ type = \"diffuse_light\", emit = {{ type = \"checker\", scale = 10, even = [1, 0, 0], odd = {{ type = \"noise\", style = \"marble\" }} }} }}\n",
+                CAMERA
+            ),
+            Path::new(""),
+        ).unwrap();
+
+        let ray = Ray::new(Point3::new(0.1, 0.1, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.material.emitted(hit.u, hit.v, hit.p), Color::new(1.0, 0.0, 0.0));
+
+        let object = |material: &str| {
+            format!("{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = {}\n", CAMERA, material)
+        };
+        assert_eq!(
+            error_key(&object("{ type = \"lambertian\", albedo = { type = \"wood\" } }")),
+            "objects[0].material.albedo.type"
+        );
+        assert_eq!(
+            error_key(&object("{ type = \"metal\", albedo = { type = \"checker\", even = [1, 1, 1], odd = 2 } }")),
+            "objects[0].material.albedo.odd"
+        );
+        assert_eq!(
+            error_key(&object("{ type = \"lambertian\", albedo = { type = \"image\", path = \"missing.png\" } }")),
+            "objects[0].material.albedo.path"
+        );
+        assert_eq!(
+            error_key(&object("{ type = \"lambertian\", albedo = { type = \"image\", path = \"a.png\", wrap = \"tile\" } }")),
+            "objects[0].material.albedo.wrap"
+        );
+    }
+
+    #[test]
+    fn rects_quads_and_boxes() {
+        let scene = parse_scene(
+            r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [[objects]]
+            type = "xz_rect"
+            x0 = -1
+            x1 = 1
+            z0 = -1
+            z1 = 1
+            k = -2
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+
+            [[objects]]
+            type = "quad"
+            corner = [-1, -1, -3]
+            u = [2, 0, 0]
+            v = [0, 2, 0]
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+
+            [[objects]]
+            type = "box"
+            min = [2, 2, 2]
+            max = [3, 3, 3]
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+            "#,
+            Path::new(""),
+        ).unwrap();
+
+        let down = Ray::new(Point3::new(0.5, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert_eq!(scene.world.hit(down, 0.001, INFINITY).unwrap().t, 2.0);
+        let forward = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert_eq!(scene.world.hit(forward, 0.001, INFINITY).unwrap().t, 3.0);
+        let up = Ray::new(Point3::new(2.5, 0.0, 2.5), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert_eq!(scene.world.hit(up, 0.001, INFINITY).unwrap().t, 2.0);
+
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"yz_rect\"\ny0 = 0\ny1 = 1\nz0 = 0\nz1 = 1\nk = 0\nx0 = 0\n", CAMERA)),
+            "objects[0].x0"
+        );
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"xy_rect\"\nx0 = 1\nx1 = 0\ny0 = 0\ny1 = 1\nk = 0\nmaterial = \"a\"\n", CAMERA)),
+            "objects[0].x1"
+        );
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [2, 0, 0]\nmaterial = \"a\"\n", CAMERA)),
+            "objects[0].v"
+        );
+    }
+
+    #[test]
+    fn objects_can_be_transformed() {
+        let scene = parse_scene(
+            r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [[objects]]
+            type = "box"
+            min = [0, 0, 0]
+            max = [1, 1, 1]
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+            transform = [{ scale = 2 }, { rotate_y = 90 }, { translate = [10, 0, 0] }]
+            "#,
+            Path::new(""),
+        ).unwrap();
+
+        let bbox = scene.world.bounding_box(0.0, 1.0).unwrap();
+        assert!((bbox.min() - Point3::new(10.0, 0.0, -2.0)).length() < 1e-3);
+        assert!((bbox.max() - Point3::new(12.0, 2.0, 0.0)).length() < 1e-3);
+
+        let object = |transform: &str| {
+            format!(
+                "{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"a\"\ntransform = {}\n",
+                CAMERA, transform
+            )
+        };
+        assert_eq!(error_key(&object("[{ shear = 1 }]")), "objects[0].transform[0].shear");
+        assert_eq!(error_key(&object("[{ scale = [1, 0, 1] }]")), "objects[0].transform[0].scale");
+        assert_eq!(error_key(&object("[{ rotate_x = 1 }, { rotate_y = 1, rotate_z = 2 }]")), "objects[0].transform[1]");
+        assert_eq!(error_key(&object("[{ translate = [0, 0, 0] }]")), "objects[0].material");
+    }
+
+    #[test]
+    fn constant_media() {
+        let scene = parse_scene(
+            r#"
+            [camera]
+            look_from = [0, 0, 5]
+            look_at = [0, 0, 0]
+            vfov = 40
+
+            [[objects]]
+            type = "constant_medium"
+            density = 1e6
+            albedo = [0.5, 0.5, 0.5]
+            boundary = { type = "sphere", center = [0, 0, 0], radius = 1, material = { type = "dielectric", index_of_refraction = 1.5 } }
+            "#,
+            Path::new(""),
+        ).unwrap();
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.sample_hit(ray, 0.001, INFINITY, &mut IndependentSampler::new(0)).unwrap();
+        assert!(matches!(hit.material, Material::Isotropic { .. }));
+
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"constant_medium\"\ndensity = 1\nalbedo = [1, 1, 1]\nboundary = {{ type = \"sphere\", center = [0, 0, 0], material = \"a\" }}\n", CAMERA)),
+            "objects[0].boundary.radius"
+        );
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"constant_medium\"\ndensity = 0\nalbedo = [1, 1, 1]\nboundary = {{ type = \"box\", min = [0, 0, 0], max = [1, 1, 1], material = {{ type = \"isotropic\", albedo = [1, 1, 1] }} }}\n", CAMERA)),
+            "objects[0].density"
+        );
+    }
+
+    #[test]
+    fn render_section_is_optional() {
+        let scene = parse_scene(CAMERA, Path::new("")).unwrap();
+
+        assert_eq!(scene.settings, RenderSettings::default());
+        assert_eq!(scene.environment, Environment::SKY);
+    }
+
+    #[test]
+    fn render_settings_are_validated() {
+        assert_eq!(error_key("[render]\nseed = -1\n"), "render.seed");
+        assert_eq!(error_key("[render]\nsampler = \"random\"\n"), "render.sampler");
+        assert_eq!(error_key("[render]\nadaptive_threshold = 0\n"), "render.adaptive_threshold");
+        assert_eq!(error_key("[render]\nadaptive_threshold = nan\n"), "render.adaptive_threshold");
+        assert_eq!(error_key("[render]\nmin_samples_per_pixel = 0\n"), "render.min_samples_per_pixel");
+        assert_eq!(error_key("[render]\ntime_limit = -5\n"), "render.time_limit");
+        assert_eq!(error_key("[render]\ntime_limit = 1e30\n"), "render.time_limit");
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_key() {
+        assert_eq!(error_key("[render]\nwidth = 10\n"), "camera");
+        assert_eq!(error_key("[camera]\nlook_from = [0, 0]\n"), "camera.look_from");
+        assert_eq!(error_key(&format!("{}fov = 3\n", CAMERA)), "camera.fov");
+        assert_eq!(error_key(&format!("[render]\nwidth = -1\n{}", CAMERA)), "render.width");
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"nope\"\n", CAMERA)),
+            "objects[0].material"
+        );
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"cube\"\n", CAMERA)),
+            "objects[0].type"
+        );
+        assert_eq!(
+            error_key(&format!("{}[materials.red]\ntype = \"lambertian\"\nalbedo = \"red\"\n", CAMERA)),
+            "materials.red.albedo"
+        );
+        assert_eq!(
+            error_key(&format!(
+                "{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"a\"\n\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nmaterial = \"a\"\n\n[materials.a]\ntype = \"metal\"\nalbedo = [1, 1, 1]\n",
+                CAMERA
+            )),
+            "objects[1].radius"
+        );
+        assert_eq!(
+            error_key(&format!("{}[[objects]]\ntype = \"mesh\"\npositions = [[0, 0, 0]]\nindices = [[0, 0, 4]]\nmaterial = {{ type = \"lambertian\", albedo = [1, 1, 1] }}\n", CAMERA)),
+            "objects[0].indices[0]"
+        );
+    }
+
+    #[test]
+    fn example_scene_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/three_spheres.toml");
+        let scene = load_scene(&path).unwrap();
+
+        assert_eq!(scene.settings.image_width, 400);
+        assert!(scene.world.bounding_box(0.0, 1.0).is_some());
+
+        let scene = load_scene(&path.with_file_name("triangles.toml")).unwrap();
+        assert!(scene.world.bounding_box(0.0, 0.0).is_some());
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert!(matches!(parse_scene("[camera", Path::new("")), Err(SceneError::Syntax(_))));
+    }
+}
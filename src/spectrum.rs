@@ -0,0 +1,256 @@
+use std::sync::OnceLock;
+
+use crate::sampler::Sampler;
+use crate::vec3::{Color, Vec3};
+
+/// Shortest wavelength, in nanometres, that spectral rendering traces.
+pub const WAVELENGTH_MIN: f32 = 380.0;
+/// Longest wavelength, in nanometres, that spectral rendering traces.
+pub const WAVELENGTH_MAX: f32 = 780.0;
+/// The sodium d-line, where catalogue indices of refraction are quoted and
+/// where the dispersive models are evaluated in RGB mode.
+pub const REFERENCE_WAVELENGTH: f32 = 587.6;
+
+/// A wavelength drawn uniformly from the visible range.
+pub fn sample_wavelength(sampler: &mut dyn Sampler) -> f32 {
+    sampler.range(WAVELENGTH_MIN, WAVELENGTH_MAX)
+}
+
+/// The CIE 1931 color matching functions, using the multi-lobe Gaussian fit
+/// of Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(wavelength: f32) -> Vec3 {
+    let lobe = |mean: f32, below: f32, above: f32| {
+        let sigma = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+/// Converts CIE XYZ to linear sRGB (D65 white).
+pub fn xyz_to_srgb(xyz: Vec3) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color::new(
+        3.240_454 * x - 1.537_139 * y - 0.498_531 * z,
+        -0.969_266 * x + 1.876_011 * y + 0.041_556 * z,
+        0.055_643 * x - 0.204_026 * y + 1.057_225 * z,
+    )
+}
+
+/// What a single sample at `wavelength`, drawn by `sample_wavelength`,
+/// contributes to a pixel's linear sRGB color per unit of radiance. Scaled
+/// so that an equal-energy spectrum averages to white; some channels go
+/// negative outside the sRGB gamut.
+pub fn wavelength_to_rgb(wavelength: f32) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let steps = (WAVELENGTH_MAX - WAVELENGTH_MIN) as usize;
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for step in 0..steps {
+            total += xyz_to_srgb(cie_xyz(WAVELENGTH_MIN + step as f32 + 0.5));
+        }
+        total / steps as f32
+    });
+
+    let rgb = xyz_to_srgb(cie_xyz(wavelength));
+    Color::new(rgb.r() / white.r(), rgb.g() / white.g(), rgb.b() / white.b())
+}
+
+// Smits' basis spectra, in ten equal bins from 380 to 720 nm. Longer
+// wavelengths stay in the last bin.
+const SMITS_MAX: f32 = 720.0;
+const SMITS_WHITE: [f32; 10] = [1.0, 1.0, 0.9999, 0.9993, 0.9992, 0.9998, 1.0, 1.0, 1.0, 1.0];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0];
+const SMITS_MAGENTA: [f32; 10] = [1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// The value at `wavelength` of a smooth spectrum with the given linear
+/// color, built the way Smits (1999) does: white for what all three channels
+/// share, then a secondary and a primary for the rest. It scales with the
+/// color, so it serves for emission as well as for reflectance.
+pub fn rgb_to_spectrum(color: Color, wavelength: f32) -> f32 {
+    let bin = (((wavelength - WAVELENGTH_MIN) / (SMITS_MAX - WAVELENGTH_MIN) * 10.0) as usize).min(9);
+    let (r, g, b) = (color.r().max(0.0), color.g().max(0.0), color.b().max(0.0));
+
+    // The smallest channel is white; the others add the secondary that
+    // covers both of them and the primary that covers the larger one.
+    if r <= g && r <= b {
+        let rest = if g <= b {
+            (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        };
+        r * SMITS_WHITE[bin] + rest
+    } else if g <= r && g <= b {
+        let rest = if r <= b {
+            (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        };
+        g * SMITS_WHITE[bin] + rest
+    } else {
+        let rest = if r <= g {
+            (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        };
+        b * SMITS_WHITE[bin] + rest
+    }
+}
+
+/// `color` as a ray of the given wavelength sees it: its spectrum's value
+/// there, in every channel. Rays without a wavelength see it unchanged.
+pub fn at_wavelength(color: Color, wavelength: Option<f32>) -> Color {
+    match wavelength {
+        Some(wavelength) => {
+            let value = rgb_to_spectrum(color, wavelength);
+            Color::new(value, value, value)
+        }
+        None => color,
+    }
+}
+
+/// How a dielectric's index of refraction varies with wavelength.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Ior {
+    /// The same index everywhere; no dispersion
+    Constant(f32),
+    /// `a + b / λ²`, with λ in micrometres
+    Cauchy { a: f32, b: f32 },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with λ in micrometres and `c` in
+    /// square micrometres, as glass catalogues list them
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612, 0.231_792, 1.010_469],
+        c: [0.006_000_7, 0.020_017_9, 103.560_65],
+    };
+    pub const FUSED_SILICA: Ior = Ior::Sellmeier {
+        b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+        c: [0.004_679_15, 0.013_512_06, 97.934],
+    };
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.330_6, 4.335_6, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    /// Looks up one of the presets: "bk7", "fused_silica" or "diamond".
+    pub fn by_name(name: &str) -> Option<Ior> {
+        match name {
+            "bk7" => Some(Ior::BK7),
+            "fused_silica" => Some(Ior::FUSED_SILICA),
+            "diamond" => Some(Ior::DIAMOND),
+            _ => None,
+        }
+    }
+
+    /// The index at `wavelength` in nanometres, or at the d-line when the
+    /// ray carries no wavelength.
+    pub fn at(&self, wavelength: Option<f32>) -> f32 {
+        let micrometres = wavelength.unwrap_or(REFERENCE_WAVELENGTH) / 1000.0;
+        let l2 = micrometres * micrometres;
+        match self {
+            Ior::Constant(index) => *index,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f32 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+impl From<f32> for Ior {
+    fn from(index: f32) -> Ior {
+        Ior::Constant(index)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn matching_functions_peak_where_expected() {
+        assert!((cie_xyz(555.0).y() - 1.0).abs() < 0.01);
+        assert!(cie_xyz(440.0).z() > cie_xyz(440.0).y());
+        assert!(cie_xyz(600.0).x() > cie_xyz(600.0).z());
+        assert!(cie_xyz(WAVELENGTH_MAX).length() < 1e-3);
+    }
+
+    #[test]
+    fn equal_energy_averages_to_white() {
+        let steps = 4000;
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for step in 0..steps {
+            let wavelength = WAVELENGTH_MIN + (step as f32 + 0.5) / steps as f32 * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+            total += wavelength_to_rgb(wavelength);
+        }
+        let average = total / steps as f32;
+        assert!((average - Color::new(1.0, 1.0, 1.0)).length() < 1e-3, "{:?}", average);
+
+        // Spectral colors land where they should
+        let red = wavelength_to_rgb(650.0);
+        assert!(red.r() > red.g() && red.r() > red.b());
+        let blue = wavelength_to_rgb(450.0);
+        assert!(blue.b() > blue.r() && blue.b() > blue.g());
+    }
+
+    #[test]
+    fn upsampled_colors_come_back_from_the_film() {
+        let steps = 4000;
+        for color in [Color::new(0.2, 0.5, 0.8), Color::new(0.7, 0.7, 0.7), Color::new(0.8, 0.6, 0.2), Color::new(0.65, 0.05, 0.05)] {
+            let mut total = Color::new(0.0, 0.0, 0.0);
+            for step in 0..steps {
+                let wavelength = WAVELENGTH_MIN + (step as f32 + 0.5) / steps as f32 * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+                total += rgb_to_spectrum(color, wavelength) * wavelength_to_rgb(wavelength);
+            }
+            let average = total / steps as f32;
+            assert!((average - color).length() < 0.02, "{:?} came back as {:?}", color, average);
+        }
+
+        // A red surface reflects little blue light, however bright it is
+        let red = Color::new(0.9, 0.1, 0.1);
+        assert!(rgb_to_spectrum(red, 450.0) < 0.2 && rgb_to_spectrum(red, 650.0) > 0.8);
+        assert!((rgb_to_spectrum(5.0 * red, 650.0) - 5.0 * rgb_to_spectrum(red, 650.0)).abs() < 1e-5);
+        assert_eq!(at_wavelength(red, None), red);
+        assert_eq!(at_wavelength(red, Some(650.0)).r(), at_wavelength(red, Some(650.0)).b());
+    }
+
+    #[test]
+    fn sampled_wavelengths_are_visible() {
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..1000 {
+            let wavelength = sample_wavelength(&mut sampler);
+            assert!((WAVELENGTH_MIN..WAVELENGTH_MAX).contains(&wavelength));
+        }
+    }
+
+    #[test]
+    fn dispersion_models() {
+        // Catalogue values at the d-line
+        assert!((Ior::BK7.at(None) - 1.5168).abs() < 1e-3);
+        assert!((Ior::FUSED_SILICA.at(None) - 1.4585).abs() < 1e-3);
+        assert!((Ior::DIAMOND.at(None) - 2.417).abs() < 5e-3);
+
+        // Blue bends more than red
+        for ior in [Ior::BK7, Ior::DIAMOND, Ior::Cauchy { a: 1.5, b: 0.004 }] {
+            assert!(ior.at(Some(450.0)) > ior.at(Some(650.0)));
+        }
+        assert_eq!(Ior::from(1.5).at(Some(450.0)), 1.5);
+        assert_eq!(Ior::by_name("diamond"), Some(Ior::DIAMOND));
+        assert_eq!(Ior::by_name("glass"), None);
+    }
+}
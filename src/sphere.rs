@@ -1,8 +1,19 @@
+use crate::aabb::{Aabb, surrounding_box};
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::utility::{dot};
-use crate::vec3::{Point3, Vec3};
+use crate::sampler::Sampler;
+use crate::utility::{INFINITY, PI, dot};
+use crate::vec3::{Point3, Vec3, random_to_sphere, random_unit_vector};
+
+/// Surface coordinates of a point on the unit sphere: `u` runs around the
+/// y axis starting from -x, `v` runs from the south pole to the north.
+pub fn sphere_uv(p: Point3) -> (f32, f32) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
 
 pub struct Sphere {
     center: Point3,
@@ -13,15 +24,15 @@ pub struct Sphere {
 impl Sphere {
     pub fn new(center: Point3, radius: f32, material: Material) -> Self {
         Sphere {
-            center: center,
-            radius: radius,
-            material: material
+            center,
+            radius,
+            material
         }
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let oc = ray.origin() - self.center;
         let a = ray.direction().length_squared();
         let half_b = dot(oc, ray.direction());
@@ -48,17 +59,53 @@ impl Hittable for Sphere {
         let t = root;
         let p = ray.at(t);
         let outward_normal: Vec3 =  (p - self.center) / self.radius;
+        let (u, v) = sphere_uv(outward_normal);
         let mut record = HitRecord{
-            p: p,
+            p,
             normal: outward_normal,
-            t: t,
+            t,
+            u,
+            v,
             front_face: false,
             material: &self.material
         };
 
         record.set_face_normal(ray, outward_normal);
 
-        return Some(record)
+        Some(record)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    /// Seen from outside, directions are sampled uniformly over the cone the
+    /// sphere subtends; from inside, over the whole sphere of directions.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f32 {
+        if self.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY).is_none() {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return random_unit_vector(sampler);
+        }
+        Onb::from_w(direction).local(random_to_sphere(sampler, self.radius, distance_squared))
     }
 }
 
@@ -88,7 +135,7 @@ impl MovingSphere {
 }
 
 impl Hittable for MovingSphere {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let oc = ray.origin() - self.center(ray.time());
         let a = ray.direction().length_squared();
         let half_b = dot(oc, ray.direction());
@@ -114,16 +161,78 @@ impl Hittable for MovingSphere {
         let t = root;
         let p = ray.at(t);
         let outward_normal: Vec3 =  (p - self.center(ray.time())) / self.radius;
+        let (u, v) = sphere_uv(outward_normal);
         let mut record = HitRecord{
-            p: p,
+            p,
             normal: outward_normal,
-            t: t,
+            t,
+            u,
+            v,
             front_face: false,
             material: &self.material
         };
 
         record.set_face_normal(ray, outward_normal);
 
-        return Some(record)
+        Some(record)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        let box0 = Aabb::new(self.center(time0) - radius, self.center(time0) + radius);
+        let box1 = Aabb::new(self.center(time1) - radius, self.center(time1) + radius);
+        Some(surrounding_box(box0, box1))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::utility::INFINITY;
+    use crate::vec3::Color;
+
+    #[test]
+    fn sphere_uv_landmarks() {
+        let close = |(u, v): (f32, f32), expected: (f32, f32)| {
+            (u - expected.0).abs() < 1e-6 && (v - expected.1).abs() < 1e-6
+        };
+        assert!(close(sphere_uv(Point3::new(1.0, 0.0, 0.0)), (0.5, 0.5)));
+        assert!(close(sphere_uv(Point3::new(0.0, 0.0, 1.0)), (0.25, 0.5)));
+        assert!(close(sphere_uv(Point3::new(0.0, 0.0, -1.0)), (0.75, 0.5)));
+        assert_eq!(sphere_uv(Point3::new(0.0, 1.0, 0.0)).1, 1.0);
+        assert_eq!(sphere_uv(Point3::new(0.0, -1.0, 0.0)).1, 0.0);
+    }
+
+    #[test]
+    fn sampled_directions_hit_the_sphere() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, Material::lambertian(Color::new(1.0, 1.0, 1.0)));
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        let mut sampler = IndependentSampler::new(0);
+        for _ in 0..100 {
+            let direction = sphere.random(origin, &mut sampler);
+            assert!(sphere.hit(Ray::new(origin, direction, 0.0), 0.001, INFINITY).is_some());
+        }
+        // One over the solid angle of a cone with sin(theta_max) = 1/3
+        let cos_theta_max = (8.0f32 / 9.0).sqrt();
+        let expected = 1.0 / (2.0 * PI * (1.0 - cos_theta_max));
+        assert!((sphere.pdf_value(origin, Vec3::new(0.0, 0.0, -1.0)) - expected).abs() < 1e-3 * expected);
+        assert_eq!(sphere.pdf_value(origin, Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn hits_report_sphere_uv() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 2.0, Material::lambertian(Color::new(1.0, 1.0, 1.0)));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let hit = sphere.hit(ray, 0.001, INFINITY).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-6);
+        assert!((hit.v - 0.5).abs() < 1e-6);
     }
 }
@@ -0,0 +1,337 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::image::Image;
+use crate::input;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::utility::dot;
+use crate::vec3::{Color, Point3, Vec3};
+
+/// A color that varies over a surface, looked up from the hit's surface
+/// coordinates (`u`, `v`) or its position in space.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        self.color
+    }
+}
+
+/// Alternates between two textures in cubes of side `scale` through space,
+/// so it doesn't depend on the surface parameterization.
+pub struct CheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    scale: f32,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f32) -> Self {
+        CheckerTexture { even, odd, scale }
+    }
+
+    pub fn from_colors(even: Color, odd: Color, scale: f32) -> Self {
+        CheckerTexture::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), scale)
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f32, v: f32, p: Point3) -> Color {
+        let cell = |value: f32| (value / self.scale).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// What an image texture does with coordinates outside [0, 1].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    fn apply(&self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+        let index = match self {
+            WrapMode::Repeat => index.rem_euclid(size),
+            WrapMode::Clamp => index.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let index = index.rem_euclid(2 * size);
+                if index < size { index } else { 2 * size - 1 - index }
+            }
+        };
+        index as usize
+    }
+}
+
+/// An image mapped over (`u`, `v`), with `v` = 0 at the bottom row.
+/// Lookups blend the four nearest texels.
+pub struct ImageTexture {
+    image: Image,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image, wrap: WrapMode) -> Self {
+        ImageTexture { image, wrap }
+    }
+
+    /// Reads a PNG, PPM or HDR file.
+    pub fn load(path: &Path, wrap: WrapMode) -> io::Result<Self> {
+        Ok(ImageTexture::new(input::load(path)?, wrap))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        self.image.pixel(
+            self.wrap.apply(x, self.image.width()),
+            self.wrap.apply(y, self.image.height()),
+        )
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: Point3) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        if width == 0 || height == 0 {
+            // Stands out as an obvious mistake in the render
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        // Texel centers sit at half-integer coordinates
+        let x = u * width as f32 - 0.5;
+        let y = (1.0 - v) * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise in [-1, 1] (Perlin's improved version with random unit
+/// gradients and Hermite smoothing). The tables come from a fixed seed, so
+/// a noise texture looks the same on every run.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        let mut sampler = IndependentSampler::new(0);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::random_range(&mut sampler, -1.0, 1.0);
+                if v.near_zero() { Vec3::new(1.0, 0.0, 0.0) } else { v / v.length() }
+            })
+            .collect();
+
+        Perlin {
+            gradients,
+            perm_x: Perlin::permutation(&mut sampler),
+            perm_y: Perlin::permutation(&mut sampler),
+            perm_z: Perlin::permutation(&mut sampler),
+        }
+    }
+
+    fn permutation(sampler: &mut dyn Sampler) -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let target = ((sampler.next_1d() * (i + 1) as f32) as usize).min(i);
+            p.swap(i, target);
+        }
+        p
+    }
+
+    pub fn noise(&self, p: Point3) -> f32 {
+        let (x, y, z) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - x, p.y() - y, p.z() - z);
+        let (i, j, k) = (x as i64, y as i64, z as i64);
+
+        let wrap = |value: i64| (value & (POINT_COUNT as i64 - 1)) as usize;
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut accumulated = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)];
+                    let (fi, fj, fk) = (di as f32, dj as f32, dk as f32);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accumulated += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * dot(self.gradients[index], weight);
+                }
+            }
+        }
+        accumulated
+    }
+
+    /// Sum of `depth` octaves of noise, each at double the frequency and half
+    /// the weight of the last.
+    pub fn turbulence(&self, p: Point3, depth: usize) -> f32 {
+        let mut accumulated = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accumulated += weight * self.noise(p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+        accumulated.abs()
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum NoiseStyle {
+    /// Plain noise remapped to [0, 1]
+    Smooth,
+    /// Multi-octave turbulence
+    Turbulence,
+    /// Sine stripes along z, perturbed by turbulence at the base frequency
+    Marble,
+}
+
+const TURBULENCE_DEPTH: usize = 7;
+
+/// Grey-scale Perlin noise multiplying `color`. `scale` sets the frequency.
+pub struct NoiseTexture {
+    noise: Perlin,
+    color: Color,
+    scale: f32,
+    style: NoiseStyle,
+}
+
+impl NoiseTexture {
+    pub fn new(color: Color, scale: f32, style: NoiseStyle) -> Self {
+        NoiseTexture { noise: Perlin::new(), color, scale, style }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
+        let scaled = self.scale * p;
+        let intensity = match self.style {
+            NoiseStyle::Smooth => 0.5 * (1.0 + self.noise.noise(scaled)),
+            NoiseStyle::Turbulence => self.noise.turbulence(scaled, TURBULENCE_DEPTH),
+            // Only the stripe frequency follows the scale; the veins keep their size.
+            NoiseStyle::Marble => {
+                0.5 * (1.0 + (scaled.z() + 10.0 * self.noise.turbulence(p, TURBULENCE_DEPTH)).sin())
+            }
+        };
+        intensity * self.color
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin() -> Point3 {
+        Point3::new(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn checker_alternates_in_space() {
+        let checker = CheckerTexture::from_colors(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 0.5);
+
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(0.1, 0.1, 0.1)), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(0.6, 0.1, 0.1)), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(-0.1, 0.1, 0.1)), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(0.6, 0.6, 0.1)), Color::new(1.0, 1.0, 1.0));
+    }
+
+    fn two_by_one() -> Image {
+        Image::from_pixels(2, 1, vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0)])
+    }
+
+    #[test]
+    fn image_lookups_are_bilinear() {
+        let texture = ImageTexture::new(two_by_one(), WrapMode::Clamp);
+
+        // Texel centers return the texel itself, points between them blend.
+        assert_eq!(texture.value(0.25, 0.5, origin()), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(texture.value(0.75, 0.5, origin()), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(texture.value(0.5, 0.5, origin()), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let repeat = ImageTexture::new(two_by_one(), WrapMode::Repeat);
+        let clamp = ImageTexture::new(two_by_one(), WrapMode::Clamp);
+        let mirror = ImageTexture::new(two_by_one(), WrapMode::Mirror);
+
+        assert_eq!(repeat.value(1.25, 0.5, origin()), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(clamp.value(1.25, 0.5, origin()), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(mirror.value(1.25, 0.5, origin()), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(mirror.value(-0.25, 0.5, origin()), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(repeat.value(0.0, 0.5, origin()), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(clamp.value(0.0, 0.5, origin()), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn image_rows_run_top_down() {
+        let image = Image::from_pixels(1, 2, vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0)]);
+        let texture = ImageTexture::new(image, WrapMode::Clamp);
+
+        assert_eq!(texture.value(0.5, 1.0, origin()), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(texture.value(0.5, 0.0, origin()), Color::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn noise_is_bounded_and_continuous() {
+        let perlin = Perlin::new();
+        let point = |i: usize| Point3::new(i as f32 * 0.01, i as f32 * 0.007, 1.3);
+        let mut previous = perlin.noise(point(0));
+        for i in 1..1000 {
+            let value = perlin.noise(point(i));
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - previous).abs() < 0.1);
+            previous = value;
+        }
+        // Noise vanishes on the integer lattice
+        assert!(perlin.noise(Point3::new(3.0, -2.0, 7.0)).abs() < 1e-6);
+        // and is the same every time
+        assert_eq!(Perlin::new().noise(point(42)), perlin.noise(point(42)));
+
+        let texture = NoiseTexture::new(Color::new(1.0, 0.5, 0.0), 4.0, NoiseStyle::Marble);
+        let value = texture.value(0.0, 0.0, Point3::new(0.3, 0.2, 0.1));
+        assert!((0.0..=1.0).contains(&value.x()));
+        assert_eq!(value.y(), 0.5 * value.x());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::aabb::Aabb;
+use crate::utility::degrees_to_radians;
+use crate::vec3::{Point3, Vec3};
+
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 4]; 4];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+/// An affine 4x4 transform kept together with its inverse. Matrices are
+/// row-major and act on column vectors, so the translation is the last column.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Transform {
+    /// Builds a transform from an affine matrix. The bottom row is taken to
+    /// be (0, 0, 0, 1). Returns `None` if the matrix can't be inverted.
+    pub fn new(matrix: Matrix) -> Option<Transform> {
+        let m = |i: usize, j: usize| matrix[i][j];
+        // Cofactors of the upper-left 3x3 block
+        let c00 = m(1, 1) * m(2, 2) - m(1, 2) * m(2, 1);
+        let c01 = m(1, 2) * m(2, 0) - m(1, 0) * m(2, 2);
+        let c02 = m(1, 0) * m(2, 1) - m(1, 1) * m(2, 0);
+        let determinant = m(0, 0) * c00 + m(0, 1) * c01 + m(0, 2) * c02;
+        if determinant.abs() < 1e-12 || !determinant.is_finite() {
+            return None;
+        }
+
+        let inv_det = 1.0 / determinant;
+        let linear = [
+            [c00, m(0, 2) * m(2, 1) - m(0, 1) * m(2, 2), m(0, 1) * m(1, 2) - m(0, 2) * m(1, 1)],
+            [c01, m(0, 0) * m(2, 2) - m(0, 2) * m(2, 0), m(0, 2) * m(1, 0) - m(0, 0) * m(1, 2)],
+            [c02, m(0, 1) * m(2, 0) - m(0, 0) * m(2, 1), m(0, 0) * m(1, 1) - m(0, 1) * m(1, 0)],
+        ];
+
+        let mut inverse = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                inverse[i][j] = linear[i][j] * inv_det;
+            }
+            // The inverse translation undoes the original one
+            inverse[i][3] = -(0..3).map(|j| inverse[i][j] * m(j, 3)).sum::<f32>();
+        }
+
+        let mut matrix = matrix;
+        matrix[3] = IDENTITY[3];
+        Some(Transform { matrix, inverse })
+    }
+
+    pub fn identity() -> Transform {
+        Transform { matrix: IDENTITY, inverse: IDENTITY }
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for axis in 0..3 {
+            matrix[axis][3] = offset.elements[axis];
+            inverse[axis][3] = -offset.elements[axis];
+        }
+        Transform { matrix, inverse }
+    }
+
+    /// Scales each axis by the matching component of `factors`, none of
+    /// which may be zero.
+    pub fn scale(factors: Vec3) -> Transform {
+        assert!(
+            factors.elements.iter().all(|&factor| factor != 0.0),
+            "scale factors must be non-zero"
+        );
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for axis in 0..3 {
+            matrix[axis][axis] = factors.elements[axis];
+            inverse[axis][axis] = 1.0 / factors.elements[axis];
+        }
+        Transform { matrix, inverse }
+    }
+
+    /// Rotation about one coordinate axis (0 = x, 1 = y, 2 = z),
+    /// counter-clockwise when looking down the axis toward the origin.
+    fn rotate_axis(axis: usize, degrees: f32) -> Transform {
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        let mut matrix = IDENTITY;
+        matrix[a][a] = cos;
+        matrix[a][b] = -sin;
+        matrix[b][a] = sin;
+        matrix[b][b] = cos;
+
+        // Rotations are orthonormal, so the inverse is the transpose
+        let mut inverse = matrix;
+        inverse[a][b] = sin;
+        inverse[b][a] = -sin;
+        Transform { matrix, inverse }
+    }
+
+    pub fn rotate_x(degrees: f32) -> Transform {
+        Transform::rotate_axis(0, degrees)
+    }
+
+    pub fn rotate_y(degrees: f32) -> Transform {
+        Transform::rotate_axis(1, degrees)
+    }
+
+    pub fn rotate_z(degrees: f32) -> Transform {
+        Transform::rotate_axis(2, degrees)
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: multiply(&next.matrix, &self.matrix),
+            inverse: multiply(&self.inverse, &next.inverse),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        let m = &self.matrix;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    /// Transforms a direction, ignoring the translation.
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.matrix;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    /// Transforms a surface normal by the inverse transpose, which keeps it
+    /// perpendicular to the transformed surface. The result isn't normalized.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse;
+        Vec3::new(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z(),
+        )
+    }
+
+    /// The smallest axis-aligned box holding the transformed corners of `aabb`.
+    pub fn bounding_box(&self, aabb: Aabb) -> Aabb {
+        let mut small = Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut big = Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for corner in 0..8 {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 { aabb.min().elements[axis] } else { aabb.max().elements[axis] }
+            };
+            let p = self.point(Point3::new(pick(0), pick(1), pick(2)));
+            for axis in 0..3 {
+                small.elements[axis] = small.elements[axis].min(p.elements[axis]);
+                big.elements[axis] = big.elements[axis].max(p.elements[axis]);
+            }
+        }
+        Aabb::new(small, big)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::dot;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn rotations_follow_the_right_hand_rule() {
+        assert_close(Transform::rotate_z(90.0).point(Point3::new(1.0, 0.0, 0.0)), Point3::new(0.0, 1.0, 0.0));
+        assert_close(Transform::rotate_x(90.0).point(Point3::new(0.0, 1.0, 0.0)), Point3::new(0.0, 0.0, 1.0));
+        assert_close(Transform::rotate_y(90.0).point(Point3::new(0.0, 0.0, 1.0)), Point3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn composition_applies_in_order() {
+        let transform = Transform::scale(Vec3::new(2.0, 2.0, 2.0))
+            .then(&Transform::rotate_z(90.0))
+            .then(&Transform::translate(Vec3::new(0.0, 0.0, 5.0)));
+        let p = Point3::new(1.0, 0.0, 0.0);
+
+        assert_close(transform.point(p), Point3::new(0.0, 2.0, 5.0));
+        assert_close(transform.inverse().point(transform.point(p)), p);
+        // Directions ignore the translation
+        assert_close(transform.vector(p), Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn general_matrices_are_inverted() {
+        let transform = Transform::new([
+            [1.0, 2.0, 0.0, 3.0],
+            [0.0, 1.0, 4.0, -1.0],
+            [5.0, 0.0, 1.0, 2.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]).unwrap();
+        let p = Point3::new(0.3, -1.2, 7.0);
+
+        assert_close(transform.inverse().point(transform.point(p)), p);
+        assert!(Transform::new([[1.0, 2.0, 3.0, 0.0], [2.0, 4.0, 6.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0; 4]]).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::scale(Vec3::new(4.0, 1.0, 1.0)).then(&Transform::rotate_y(30.0));
+        // A surface through the origin spanned by these two tangents
+        let (tangent, bitangent) = (Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let normal = Vec3::new(1.0, -1.0, 0.0);
+
+        let n = transform.normal(normal);
+        assert!(dot(n, transform.vector(tangent)).abs() < 1e-4);
+        assert!(dot(n, transform.vector(bitangent)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn boxes_grow_to_fit_rotated_corners() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let rotated = Transform::rotate_y(45.0).bounding_box(aabb);
+
+        let half = 2f32.sqrt();
+        assert_close(rotated.max(), Point3::new(half, 1.0, half));
+        assert_close(rotated.min(), Point3::new(-half, -1.0, -half));
+    }
+}
@@ -0,0 +1,389 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utility::{cross, dot, unit_vector};
+use crate::vec3::{Point3, Vec3};
+
+/// Möller–Trumbore ray/triangle intersection. Returns the ray parameter and
+/// the barycentric weights of `p1` and `p2`.
+fn intersect(ray: Ray, p0: Point3, p1: Point3, p2: Point3, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = cross(ray.direction(), edge2);
+    let det = dot(edge1, pvec);
+
+    if det.abs() < 1e-8 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin() - p0;
+    let b1 = dot(tvec, pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = cross(tvec, edge1);
+    let b2 = dot(ray.direction(), qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = dot(edge2, qvec) * inv_det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+fn triangle_box(p0: Point3, p1: Point3, p2: Point3) -> Aabb {
+    let small = Point3::new(
+        p0.x().min(p1.x()).min(p2.x()),
+        p0.y().min(p1.y()).min(p2.y()),
+        p0.z().min(p1.z()).min(p2.z()),
+    );
+    let big = Point3::new(
+        p0.x().max(p1.x()).max(p2.x()),
+        p0.y().max(p1.y()).max(p2.y()),
+        p0.z().max(p1.z()).max(p2.z()),
+    );
+    Aabb::new(small, big).padded()
+}
+
+pub struct Triangle {
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    material: Material,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, material: Material) -> Self {
+        Triangle { p0, p1, p2, material }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, b1, b2) = intersect(ray, self.p0, self.p1, self.p2, t_min, t_max)?;
+        let outward_normal = unit_vector(cross(self.p1 - self.p0, self.p2 - self.p0));
+
+        let mut record = HitRecord {
+            p: ray.at(t),
+            normal: outward_normal,
+            t,
+            u: b1,
+            v: b2,
+            front_face: false,
+            material: &self.material,
+        };
+
+        record.set_face_normal(ray, outward_normal);
+
+        Some(record)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(triangle_box(self.p0, self.p1, self.p2))
+    }
+}
+
+
+/// Vertex and index buffers shared by every triangle of a mesh. Normals and
+/// UVs, when present, are indexed by the same indices as the positions.
+struct MeshData {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f32, f32)>>,
+    indices: Vec<[usize; 3]>,
+    material: Material,
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> (Point3, Point3, Point3) {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        (self.mesh.positions[i0], self.mesh.positions[i1], self.mesh.positions[i2])
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (p0, p1, p2) = self.vertices();
+        let (t, b1, b2) = intersect(ray, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+
+        let geometric_normal = unit_vector(cross(p1 - p0, p2 - p0));
+
+        let (u, v) = match &self.mesh.uvs {
+            Some(uvs) => (
+                b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+                b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1,
+            ),
+            None => (b1, b2),
+        };
+
+        let mut record = HitRecord {
+            p: ray.at(t),
+            normal: geometric_normal,
+            t,
+            u,
+            v,
+            front_face: false,
+            material: &self.mesh.material,
+        };
+
+        record.set_face_normal(ray, geometric_normal);
+
+        if let Some(normals) = &self.mesh.normals {
+            let mut shading_normal = unit_vector(b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]);
+
+            // Keep the shading normal on the geometric side even when the
+            // vertex normals disagree with the winding order.
+            if dot(shading_normal, geometric_normal) < 0.0 {
+                shading_normal = -shading_normal;
+            }
+            record.normal = if record.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+
+        Some(record)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        let (p0, p1, p2) = self.vertices();
+        Some(triangle_box(p0, p1, p2))
+    }
+}
+
+/// Why a set of vertex arrays doesn't describe a valid `TriangleMesh`.
+#[derive(Debug, PartialEq)]
+pub enum MeshError {
+    NoTriangles,
+    IndexOutOfRange { index: usize, vertex_count: usize },
+    NormalCount { expected: usize, found: usize },
+    UvCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::NoTriangles => write!(f, "a mesh needs at least one triangle"),
+            MeshError::IndexOutOfRange { index, vertex_count } => {
+                write!(f, "index {} out of range for {} vertices", index, vertex_count)
+            }
+            MeshError::NormalCount { expected, found } => {
+                write!(f, "expected one normal per vertex ({}), found {}", expected, found)
+            }
+            MeshError::UvCount { expected, found } => {
+                write!(f, "expected one uv per vertex ({}), found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+pub struct TriangleMesh {
+    bvh: BvhNode,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>, indices: Vec<[usize; 3]>,
+        normals: Option<Vec<Vec3>>, uvs: Option<Vec<(f32, f32)>>,
+        material: Material,
+    ) -> Result<Self, MeshError> {
+        if indices.is_empty() {
+            return Err(MeshError::NoTriangles);
+        }
+        if let Some(&index) = indices.iter().flatten().find(|&&index| index >= positions.len()) {
+            return Err(MeshError::IndexOutOfRange { index, vertex_count: positions.len() });
+        }
+        if let Some(normals) = &normals {
+            if normals.len() != positions.len() {
+                return Err(MeshError::NormalCount { expected: positions.len(), found: normals.len() });
+            }
+        }
+        if let Some(uvs) = &uvs {
+            if uvs.len() != positions.len() {
+                return Err(MeshError::UvCount { expected: positions.len(), found: uvs.len() });
+            }
+        }
+
+        let face_count = indices.len();
+        let mesh = Arc::new(MeshData { positions, normals, uvs, indices, material });
+
+        let triangles: Vec<Box<dyn Hittable>> = (0..face_count)
+            .map(|face| Box::new(MeshTriangle { mesh: Arc::clone(&mesh), face }) as Box<dyn Hittable>)
+            .collect();
+
+        Ok(TriangleMesh { bvh: BvhNode::new(triangles, 0.0, 0.0) })
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn sample_hit(&self, ray: Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        self.bvh.bounding_box(time0, time1)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::INFINITY;
+    use crate::vec3::Color;
+
+    fn material() -> Material {
+        Material::lambertian(Color::new(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Point3::new(1.0, 0.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+            material(),
+        );
+        let ray = Ray::new(Point3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let hit = triangle.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.p, Point3::new(0.25, 0.25, -2.0));
+        assert_eq!((hit.u, hit.v), (0.25, 0.25));
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn triangle_miss() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Point3::new(1.0, 0.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+            material(),
+        );
+        let outside = Ray::new(Point3::new(0.75, 0.75, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let parallel = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+        assert!(triangle.hit(outside, 0.001, INFINITY).is_none());
+        assert!(triangle.hit(parallel, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn triangle_back_face() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+            Point3::new(1.0, 0.0, -2.0),
+            material(),
+        );
+        let ray = Ray::new(Point3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let hit = triangle.hit(ray, 0.001, INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn mesh_interpolates_normals_and_uvs() {
+        let positions = vec![
+            Point3::new(-1.0, -1.0, 0.0),
+            Point3::new(1.0, -1.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(-1.0, 1.0, 0.0),
+        ];
+        let normals = vec![
+            unit_vector(Vec3::new(-1.0, 0.0, 1.0)),
+            unit_vector(Vec3::new(1.0, 0.0, 1.0)),
+            unit_vector(Vec3::new(1.0, 0.0, 1.0)),
+            unit_vector(Vec3::new(-1.0, 0.0, 1.0)),
+        ];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mesh = TriangleMesh::new(
+            positions, vec![[0, 1, 2], [0, 2, 3]],
+            Some(normals), Some(uvs), material(),
+        )
+        .unwrap();
+
+        let ray = Ray::new(Point3::new(0.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(ray, 0.001, INFINITY).unwrap();
+
+        assert_eq!(hit.t, 5.0);
+        assert!((hit.u - 0.5).abs() < 1e-6);
+        assert!((hit.v - 0.75).abs() < 1e-6);
+        assert!(hit.normal.x().abs() < 1e-6);
+        assert!((hit.normal.z() - 1.0).abs() < 1e-6);
+
+        let ray = Ray::new(Point3::new(0.5, -0.5, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(ray, 0.001, INFINITY).unwrap();
+        assert!(hit.normal.x() > 0.0);
+    }
+
+    #[test]
+    fn flat_mesh_has_hittable_bounds() {
+        let positions = vec![
+            Point3::new(-1.0, 0.0, -1.0),
+            Point3::new(1.0, 0.0, -1.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2]], None, None, material()).unwrap();
+        let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+
+        let hit = mesh.hit(ray, 0.001, INFINITY).unwrap();
+        assert_eq!(hit.t, 1.0);
+    }
+
+    #[test]
+    fn invalid_meshes_are_rejected() {
+        let positions = || vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+
+        let error = TriangleMesh::new(positions(), vec![], None, None, material()).err();
+        assert_eq!(error, Some(MeshError::NoTriangles));
+
+        let error = TriangleMesh::new(positions(), vec![[0, 1, 3]], None, None, material()).err();
+        assert_eq!(error, Some(MeshError::IndexOutOfRange { index: 3, vertex_count: 3 }));
+
+        let normals = vec![Vec3::new(0.0, 0.0, 1.0)];
+        let error = TriangleMesh::new(positions(), vec![[0, 1, 2]], Some(normals), None, material()).err();
+        assert_eq!(error, Some(MeshError::NormalCount { expected: 3, found: 1 }));
+
+        let uvs = vec![(0.0, 0.0); 4];
+        let error = TriangleMesh::new(positions(), vec![[0, 1, 2]], None, Some(uvs), material()).err();
+        assert_eq!(error, Some(MeshError::UvCount { expected: 3, found: 4 }));
+    }
+}
@@ -1,7 +1,6 @@
-use rand::Rng;
 use crate::vec3::Vec3;
 
-pub const PI: f32 = 3.1415926535897932385;
+pub const PI: f32 = std::f32::consts::PI;
 pub const INFINITY: f32 = f32::INFINITY;
 
 pub fn dot(v1: Vec3, v2: Vec3) -> f32 {
@@ -19,23 +18,16 @@ pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
 }
 
 pub fn unit_vector(v: Vec3) -> Vec3 {
-    v / v.length()
+    // Normalize in double precision, so each component is correctly rounded
+    let (x, y, z) = (v.x() as f64, v.y() as f64, v.z() as f64);
+    let length = (x * x + y * y + z * z).sqrt();
+    Vec3::new((x / length) as f32, (y / length) as f32, (z / length) as f32)
 }
 
 pub fn degrees_to_radians(degrees: f32) -> f32 {
     degrees * PI / 180.0
 }
 
-pub fn random_double() -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f32>()
-}
-
-pub fn random_double_range(min: f32, max:f32) -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
-}
-
 pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
     if x < min {
         return min;
@@ -50,6 +42,7 @@ pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
 
 
 #[cfg(test)]
+#[allow(clippy::excessive_precision)]
 mod tests {
     use super::*;
 
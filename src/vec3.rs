@@ -4,7 +4,8 @@ use std::ops::{
     Neg
 };
 
-use crate::utility::{random_double, random_double_range};
+use crate::sampler::Sampler;
+use crate::utility::PI;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Vec3 {
@@ -60,15 +61,15 @@ impl Vec3 {
         );
     }
 
-    pub fn random() -> Vec3 {
+    pub fn random(sampler: &mut dyn Sampler) -> Vec3 {
         Vec3 {
-            elements: [random_double(), random_double(), random_double()]
+            elements: [sampler.next_1d(), sampler.next_1d(), sampler.next_1d()]
         }
     }
 
-    pub fn random_range(min: f32, max: f32) -> Vec3 {
+    pub fn random_range(sampler: &mut dyn Sampler, min: f32, max: f32) -> Vec3 {
         Vec3 {
-            elements: [random_double_range(min, max), random_double_range(min, max), random_double_range(min, max)]
+            elements: [sampler.range(min, max), sampler.range(min, max), sampler.range(min, max)]
         }
     }
 
@@ -90,18 +91,18 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
     v / v.length()
 }
 
-pub fn random_unit_in_sphere() -> Vec3 {
-    loop {
-        let p = Vec3::random_range(-1.0, 1.0);
-        if p.length_squared() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
+/// A point uniformly distributed inside the unit sphere.
+pub fn random_unit_in_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    random_unit_vector(sampler) * sampler.next_1d().cbrt()
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_unit_in_sphere())
+/// A point uniformly distributed on the unit sphere.
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.next_2d();
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
@@ -120,14 +121,45 @@ pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f32) -> Vec3 {
     r_out_perp + r_out_parallel
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(random_double_range(-1.0, 1.0), random_double_range(-1.0, 1.0), 0.0);
-        if p.length_squared() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
+/// The Rec. 709 luminance of a linear color.
+pub fn luminance(color: Color) -> f32 {
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+/// A point uniformly distributed inside the unit disk in the xy plane,
+/// using Shirley and Chiu's concentric mapping so that well-spread samples
+/// stay well spread.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.next_2d();
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// A unit vector around +z with density cos(theta) / pi.
+pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.next_2d();
+    let phi = 2.0 * PI * r1;
+    let sqrt_r2 = r2.sqrt();
+    Vec3::new(phi.cos() * sqrt_r2, phi.sin() * sqrt_r2, (1.0 - r2).sqrt())
+}
+
+/// A unit vector around +z, uniform over the cone that a sphere of `radius`
+/// at `distance_squared` from the origin subtends.
+pub fn random_to_sphere(sampler: &mut dyn Sampler, radius: f32, distance_squared: f32) -> Vec3 {
+    let (r1, r2) = sampler.next_2d();
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
 }
 
 impl Add<Vec3> for Vec3 {
@@ -288,6 +320,7 @@ impl DivAssign<Vec3> for Vec3 {
 
 
 #[cfg(test)]
+#[allow(unused_mut)]
 mod tests {
     use super::*;
 
@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use std::sync::Arc;
+
+use river::{
+    BvhNode, CameraSettings, Color, ConstantMedium, Hittable, HittableList, ImageFormat, Material,
+    Point3, Ray, RenderSettings, Renderer, Sphere, Triangle, Vec3, output,
+};
+use river::utility::INFINITY;
+
+fn camera_settings() -> CameraSettings {
+    CameraSettings {
+        look_from: Point3::new(0.0, 0.0, 3.0),
+        look_at: Point3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 40.0,
+        aperture: 0.0,
+        focus_dist: 3.0,
+        time0: 0.0,
+        time1: 1.0,
+    }
+}
+
+fn small_settings() -> RenderSettings {
+    RenderSettings {
+        image_width: 32,
+        aspect_ratio: 2.0,
+        samples_per_pixel: 4,
+        max_depth: 5,
+        ..RenderSettings::default()
+    }
+}
+
+#[test]
+fn renders_image_of_requested_size() {
+    let settings = small_settings();
+    let world = HittableList::new(vec![]);
+    let camera = camera_settings().build(settings.aspect_ratio);
+
+    let image = Renderer::new(settings).render(&world, &camera);
+
+    assert_eq!(image.width(), 32);
+    assert_eq!(image.height(), 16);
+    assert_eq!(image.pixels().len(), 32 * 16);
+}
+
+#[test]
+fn empty_world_shows_sky_gradient() {
+    let settings = small_settings();
+    let world = HittableList::new(vec![]);
+    let camera = camera_settings().build(settings.aspect_ratio);
+
+    let image = Renderer::new(settings).render(&world, &camera);
+    let top = image.pixel(16, 0);
+    let bottom = image.pixel(16, 15);
+
+    // The sky fades from white at the bottom to blue at the top.
+    assert!(top.r() < bottom.r());
+    assert!((top.b() - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn black_sphere_blocks_the_sky() {
+    let settings = small_settings();
+    let black = Material::lambertian(Color::new(0.0, 0.0, 0.0));
+    let objects: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 0.5, black)),
+    ];
+    let world = BvhNode::new(objects, 0.0, 1.0);
+    let camera = camera_settings().build(settings.aspect_ratio);
+
+    let image = Renderer::new(settings).render(&world, &camera);
+
+    assert_eq!(image.pixel(16, 8), Color::new(0.0, 0.0, 0.0));
+    assert_ne!(image.pixel(0, 0), Color::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn renders_repeat_exactly_whatever_the_thread_count() {
+    let settings = RenderSettings { samples_per_pixel: 8, max_depth: 10, ..small_settings() };
+    let objects: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, -100.5, 0.0), 100.0, Material::lambertian(Color::new(0.5, 0.5, 0.5)))),
+        Box::new(Sphere::new(Point3::new(-0.6, 0.0, 0.0), 0.5, Material::dielectric(1.5))),
+        Box::new(Sphere::new(Point3::new(0.6, 0.0, 0.0), 0.5, Material::metal(Color::new(0.8, 0.6, 0.2), 0.3))),
+        Box::new(ConstantMedium::from_color(
+            Arc::new(Sphere::new(Point3::new(0.0, 0.3, -1.0), 0.6, Material::lambertian(Color::new(1.0, 1.0, 1.0)))),
+            2.0,
+            Color::new(0.9, 0.9, 0.9),
+        )),
+    ];
+    let world = BvhNode::new(objects, 0.0, 1.0);
+    let camera = camera_settings().build(settings.aspect_ratio);
+
+    let render_with = |threads: usize, seed: u64| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| Renderer::new(RenderSettings { seed, ..settings }).render(&world, &camera))
+    };
+
+    let one = render_with(1, 3);
+    assert_eq!(one.pixels(), render_with(4, 3).pixels());
+    assert_eq!(one.pixels(), render_with(7, 3).pixels());
+    assert_ne!(one.pixels(), render_with(4, 4).pixels());
+}
+
+#[test]
+fn rendered_images_encode_in_every_format() {
+    let settings = small_settings();
+    let world = HittableList::new(vec![]);
+    let camera = camera_settings().build(settings.aspect_ratio);
+    let image = Renderer::new(settings).render(&world, &camera);
+
+    let mut ppm = vec![];
+    output::write(&image, ImageFormat::Ppm, &mut ppm).unwrap();
+    assert!(ppm.starts_with(b"P6\n32 16\n255\n"));
+    assert_eq!(ppm.len(), 13 + 32 * 16 * 3);
+
+    let mut ascii = vec![];
+    output::write(&image, ImageFormat::PpmAscii, &mut ascii).unwrap();
+    let ascii = String::from_utf8(ascii).unwrap();
+    assert!(ascii.starts_with("P3\n32 16\n255\n"));
+    assert_eq!(ascii.lines().count(), 3 + 32 * 16);
+
+    let mut png = vec![];
+    output::write(&image, ImageFormat::Png, &mut png).unwrap();
+    assert!(png.starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn geometry_is_usable_from_outside_the_crate() {
+    let grey = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+    let objects: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 1.0, grey.clone())),
+        Box::new(Triangle::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Point3::new(1.0, -1.0, -2.0),
+            Point3::new(0.0, 1.0, -2.0),
+            grey,
+        )),
+    ];
+    let world = HittableList::new(objects);
+    let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+    let hit = world.hit(ray, 0.001, INFINITY).unwrap();
+    assert_eq!(hit.t, 2.0);
+}
+
+#[test]
+fn scene_files_load_through_the_library() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/three_spheres.toml");
+    let scene = river::load_scene(&path).unwrap();
+
+    assert!(!scene.world.is_empty());
+    assert_eq!(scene.settings, RenderSettings::default());
+}
+
+#[test]
+fn presets_are_listed_by_name() {
+    for name in river::presets::NAMES {
+        assert!(river::presets::by_name(name).is_some());
+    }
+    assert!(river::presets::by_name("missing").is_none());
+}